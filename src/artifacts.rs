@@ -0,0 +1,143 @@
+extern crate humantime;
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use unit::UnitName;
+use unitbroadcaster::{LogEntry, LogSource};
+
+use self::humantime::{format_rfc3339_nanos, parse_rfc3339};
+
+/// A directory holding everything captured during one scenario run: the
+/// output of each test and support command, every log entry for the run,
+/// and the results.  Tests find it in EXCLAVE_ARTIFACT_DIR, so that they
+/// can save files of their own there too.
+pub struct ArtifactDir {
+    path: PathBuf,
+    run: u64,
+}
+
+impl ArtifactDir {
+    /// Create a directory for a run under `root`.
+    pub fn create(
+        root: &Path,
+        scenario: &UnitName,
+        slot: Option<u32>,
+        run: u64,
+    ) -> io::Result<ArtifactDir> {
+        // Starting with the time keeps names unique across restarts, and
+        // means they sort oldest first.
+        let time = format!("{}", format_rfc3339_nanos(SystemTime::now())).replace(':', "");
+        // Temporary scenarios have a "/" in their names, which can't go in a directory name.
+        let scenario_id = scenario.id().replace('/', "-");
+        let name = match slot {
+            Some(s) => format!("{}-run{}-{}-slot{}", time, run, scenario_id, s),
+            None => format!("{}-run{}-{}", time, run, scenario_id),
+        };
+        let path = root.join(name);
+        fs::create_dir_all(&path)?;
+        Ok(ArtifactDir {
+            path: path,
+            run: run,
+        })
+    }
+
+    /// Remove the oldest run directories under `root` so that at most `retain`
+    /// are left.  A `retain` of 0 keeps them all.  Directories in `in_progress`
+    /// belong to runs that haven't finished, possibly on other slots, so they
+    /// are left alone and removed by a later prune once they're old enough.
+    pub fn prune(root: &Path, retain: usize, in_progress: &[&Path]) -> io::Result<()> {
+        if retain == 0 {
+            return Ok(());
+        }
+        let runs = Self::list(root)?;
+        let excess = runs.len().saturating_sub(retain);
+        for path in runs[..excess].iter().filter(|x| !in_progress.contains(&x.as_path())) {
+            fs::remove_dir_all(path)?;
+        }
+        Ok(())
+    }
+
+    /// Every run directory under `root`, oldest first.
+    pub fn list(root: &Path) -> io::Result<Vec<PathBuf>> {
+        let mut runs = vec![];
+        for entry in fs::read_dir(root)? {
+            // Entries that can't be read are left out, rather than failing the whole list.
+            let entry = match entry {
+                Ok(e) => e,
+                Err(_) => continue,
+            };
+            let name = entry.file_name().to_string_lossy().into_owned();
+            // Leave alone anything that isn't one of ours.
+            if entry.file_type().map(|t| t.is_dir()).unwrap_or(false)
+                && name.starts_with(|c: char| c.is_ascii_digit())
+                && name.contains("-run")
+            {
+                runs.push(entry.path());
+            }
+        }
+        runs.sort();
+        Ok(runs)
+    }
+
+    /// When the run in a directory made by `create` started, taken from its name.
+    pub fn start_time(path: &Path) -> Option<SystemTime> {
+        let name = path.file_name()?.to_string_lossy().into_owned();
+        let time = &name[..name.find("Z-run")? + 1];
+        if !time.is_ascii() {
+            return None;
+        }
+
+        // Put back the colons that were taken out of the time of day.
+        let (date, clock) = time.split_at(time.find('T')? + 1);
+        if clock.len() < 6 {
+            return None;
+        }
+        parse_rfc3339(&format!("{}{}:{}:{}", date, &clock[0..2], &clock[2..4], &clock[4..])).ok()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn run(&self) -> u64 {
+        self.run
+    }
+
+    fn append(&self, file_name: &str, line: &str) -> io::Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.path.join(file_name))?;
+        writeln!(file, "{}", line)
+    }
+
+    /// Save a line of a unit's output to a file named after the unit and
+    /// stream, e.g. "flash.test.stdout".
+    pub fn record_output(&self, unit: &UnitName, source: &LogSource, line: &str) -> io::Result<()> {
+        self.append(&format!("{}.{}", unit, source.as_str()), line)
+    }
+
+    /// Add a log entry to "exclave.log".
+    pub fn record_log(&self, entry: &LogEntry) -> io::Result<()> {
+        self.append("exclave.log", &format!("{}", entry))
+    }
+
+    /// Add a line to "results.tsv" giving the unit, its result, and the message,
+    /// followed by how many seconds it took if it ran.
+    pub fn record_result(
+        &self,
+        unit: &UnitName,
+        result: &str,
+        message: &str,
+        duration: Option<Duration>,
+    ) -> io::Result<()> {
+        let mut line = format!("{}\t{}\t{}", unit, result, message.replace('\t', " ").replace('\n', " "));
+        if let Some(d) = duration {
+            line.push_str(&format!("\t{}.{:03}", d.as_secs(), d.subsec_millis()));
+        }
+        self.append("results.tsv", &line)
+    }
+}
@@ -1,4 +1,5 @@
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::time::Duration;
@@ -11,6 +12,8 @@ pub struct Config {
     scenario_working_directory: Rc<RefCell<Option<PathBuf>>>,
     paths: Vec<PathBuf>,
     terminate_timeout: Duration,
+    artifact_root: Option<PathBuf>,
+    artifact_retain: usize,
 }
 
 impl Config {
@@ -27,6 +30,8 @@ impl Config {
                 Path::new("/usr/bin").to_owned(),
                 Path::new("/sbin:/bin").to_owned(),
             ],
+            artifact_root: None,
+            artifact_retain: 0,
         }
     }
 
@@ -67,6 +72,21 @@ impl Config {
         &self.paths
     }
 
+    /// The directory to create a directory for each scenario run under, if any.
+    pub fn artifact_root(&self) -> &Option<PathBuf> {
+        &self.artifact_root
+    }
+
+    /// How many run directories to keep under the artifact root, or 0 to keep them all.
+    pub fn artifact_retain(&self) -> usize {
+        self.artifact_retain
+    }
+
+    pub fn set_artifact_root(&mut self, root: &Path, retain: usize) {
+        self.artifact_root = Some(root.to_owned());
+        self.artifact_retain = retain;
+    }
+
     pub fn set_jig_working_directory(&self, new_path: &Path) {
         *self.jig_working_directory.borrow_mut() = Some(new_path.to_owned());
     }
@@ -83,3 +103,30 @@ impl Config {
         *self.scenario_working_directory.borrow_mut() = None;
     }
 }
+
+/// Build a command line that runs `cmd` with extra variables added to its
+/// environment.  Runny has no notion of a per-command environment, so the
+/// command is run through env(1), which leaves exclave's own environment alone.
+pub fn command_with_environment(cmd: &str, environment: &HashMap<String, String>) -> String {
+    if environment.is_empty() {
+        return cmd.to_owned();
+    }
+    let mut vars: Vec<(&String, &String)> = environment.iter().collect();
+    vars.sort();
+
+    let mut line = "env".to_owned();
+    for (key, value) in vars {
+        line.push(' ');
+        line.push_str(&quote_word(&format!("{}={}", key, value)));
+    }
+    line.push(' ');
+    line.push_str(cmd);
+    line
+}
+
+/// Quote a word so that Runny passes it through unchanged.  Runny doubles
+/// every backslash before splitting the line, so single quotes can't be
+/// escaped inside a quoted word; end the quotes and double-quote them instead.
+fn quote_word(word: &str) -> String {
+    format!("'{}'", word.replace('\'', "'\"'\"'"))
+}
@@ -0,0 +1,314 @@
+extern crate serde_json;
+
+use std::fs;
+use std::io::{self, BufRead, BufReader, ErrorKind, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{sync_channel, Sender, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use stationstate::StationState;
+use unit::UnitName;
+use unitmanager::{ManagerControlMessage, ManagerControlMessageContents, ManagerStatusMessage};
+use units::interface::{Interface, InterfaceServer};
+
+use self::serde_json::Value;
+
+/// How many writes may wait to be sent to a client before it is dropped for
+/// not keeping up.
+const CLIENT_QUEUE_SIZE: usize = 1000;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Protocol {
+    Text,
+    JSON,
+}
+
+/// A connected client.  Everything written to it, whether a status message
+/// or a reply to one of its own commands, goes through its writer thread, so
+/// that a slow reader can't hold up the manager or the other clients.
+struct Client {
+    /// Identifies the connection, so it can be removed when it closes.
+    number: u64,
+    stream: UnixStream,
+    protocol: Protocol,
+    lines: SyncSender<Vec<u8>>,
+}
+
+struct State {
+    station: StationState,
+    clients: Vec<Client>,
+    connections: u64,
+}
+
+/// A Unix domain socket that any number of programs can connect to, letting
+/// tools such as "exclave ctl" talk to a running instance.  Each connection
+/// speaks the JSON protocol if its first line is a JSON object, and the text
+/// protocol otherwise.
+pub struct ControlSocket {
+    path: PathBuf,
+    state: Arc<Mutex<State>>,
+    running: Arc<AtomicBool>,
+}
+
+impl ControlSocket {
+    /// Listen on the given path.  Commands are sent to the manager as coming
+    /// from the interface `id`.
+    pub fn start(path: &Path, id: &UnitName, control: Sender<ManagerControlMessage>) -> io::Result<ControlSocket> {
+        // A socket left behind by an instance that has exited can be replaced,
+        // but not one that something is still listening on.
+        if let Ok(metadata) = fs::symlink_metadata(path) {
+            if !metadata.file_type().is_socket() {
+                return Err(io::Error::new(
+                    ErrorKind::AlreadyExists,
+                    format!("{} exists and is not a socket", path.display()),
+                ));
+            }
+            if UnixStream::connect(path).is_ok() {
+                return Err(io::Error::new(
+                    ErrorKind::AddrInUse,
+                    format!("{} is already in use", path.display()),
+                ));
+            }
+            fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+
+        let state = Arc::new(Mutex::new(State {
+            station: StationState::new(),
+            clients: vec![],
+            connections: 0,
+        }));
+        let running = Arc::new(AtomicBool::new(true));
+
+        let thr_state = state.clone();
+        let thr_running = running.clone();
+        let thr_id = id.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if !thr_running.load(Ordering::SeqCst) {
+                    break;
+                }
+                let stream = match stream {
+                    Ok(s) => s,
+                    Err(_) => continue,
+                };
+                let state = thr_state.clone();
+                let id = thr_id.clone();
+                let control = control.clone();
+                thread::spawn(move || Self::handle_connection(stream, &state, &id, &control).ok());
+            }
+        });
+
+        Ok(ControlSocket {
+            path: path.to_owned(),
+            state: state,
+            running: running,
+        })
+    }
+
+    fn handle_connection(
+        stream: UnixStream,
+        state: &Mutex<State>,
+        id: &UnitName,
+        control: &Sender<ManagerControlMessage>,
+    ) -> io::Result<()> {
+        stream.set_write_timeout(Some(Duration::from_secs(5)))?;
+        let reader = BufReader::new(stream.try_clone()?);
+
+        let (lines, line_receiver) = sync_channel::<Vec<u8>>(CLIENT_QUEUE_SIZE);
+        let mut thr_stream = stream.try_clone()?;
+        let write_thread = thread::spawn(move || {
+            for buffer in line_receiver.iter() {
+                if thr_stream.write_all(&buffer).is_err() {
+                    // Wake up the reading side, so the connection is closed.
+                    thr_stream.shutdown(::std::net::Shutdown::Both).ok();
+                    break;
+                }
+            }
+        });
+
+        let mut number = None;
+        let result = Self::read_lines(reader, &stream, &lines, &mut number, state, id, control);
+
+        // Let the writer thread finish sending what's queued.
+        if let Some(number) = number {
+            state.lock().unwrap().clients.retain(|x| x.number != number);
+        }
+        drop(lines);
+        write_thread.join().ok();
+        stream.shutdown(::std::net::Shutdown::Both).ok();
+        result
+    }
+
+    /// Read commands from a client until it disconnects, queueing any replies
+    /// for its writer thread.
+    fn read_lines(
+        reader: BufReader<UnixStream>,
+        stream: &UnixStream,
+        lines: &SyncSender<Vec<u8>>,
+        number: &mut Option<u64>,
+        state: &Mutex<State>,
+        id: &UnitName,
+        control: &Sender<ManagerControlMessage>,
+    ) -> io::Result<()> {
+        for line in reader.lines() {
+            let line = line?;
+            let line = line.trim();
+
+            // Don't crash if we get a blank line.
+            if line.is_empty() {
+                continue;
+            }
+
+            // Replies are queued while holding the lock, so that they stay in
+            // order with status messages.
+            let mut state = state.lock().unwrap();
+            let protocol = match *number {
+                Some(n) => state.clients.iter().find(|x| x.number == n).map(|x| x.protocol).unwrap_or(Protocol::Text),
+                None => {
+                    // The first line decides which protocol the connection speaks.
+                    let protocol = if line.starts_with('{') { Protocol::JSON } else { Protocol::Text };
+                    state.connections += 1;
+                    let client = Client {
+                        number: state.connections,
+                        stream: stream.try_clone()?,
+                        protocol: protocol,
+                        lines: lines.clone(),
+                    };
+                    *number = Some(client.number);
+                    state.clients.push(client);
+                    protocol
+                }
+            };
+
+            let mut reply = vec![];
+            match protocol {
+                Protocol::JSON => Self::json_line(line, &mut reply, &state.station, id, control)?,
+                Protocol::Text => Self::text_line(line, &mut reply, &state.station, id, control)?,
+            }
+            if !reply.is_empty() && lines.try_send(reply).is_err() {
+                return Err(io::Error::new(ErrorKind::WouldBlock, "client isn't reading its replies"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Handle a line of the JSON protocol.  Every command gets a reply, either
+    /// {"type":"accepted"} once it has been passed on, or {"type":"error"}.
+    fn json_line(
+        line: &str,
+        writer: &mut Vec<u8>,
+        station: &StationState,
+        id: &UnitName,
+        control: &Sender<ManagerControlMessage>,
+    ) -> io::Result<()> {
+        let object = match serde_json::from_str::<Value>(line) {
+            Ok(o) => o,
+            Err(e) => return writeln!(writer, "{}", json!({"type": "error", "error": format!("Invalid JSON: {}", e)})),
+        };
+        let verb = object["verb"].as_str().unwrap_or("").to_lowercase();
+        let reply = match verb.as_str() {
+            "status" => {
+                let mut reply = station.state_json();
+                reply["type"] = json!("state");
+                reply
+            }
+            "logs" => {
+                for entry in station.logs() {
+                    writeln!(writer, "{}", Interface::json_object(ManagerStatusMessage::Log(entry.clone())))?;
+                }
+                json!({"type": "accepted", "verb": verb})
+            }
+            _ => match Interface::json_command(&object) {
+                (ManagerControlMessageContents::Error(e), _) => json!({"type": "error", "error": e}),
+                (ManagerControlMessageContents::Unimplemented(verb, _), _) => {
+                    json!({"type": "error", "error": format!("unrecognized command: {}", verb)})
+                }
+                (contents, slot) => match control.send(ManagerControlMessage::new_with_slot(id, slot, contents)) {
+                    Ok(_) => json!({"type": "accepted", "verb": verb}),
+                    Err(_) => json!({"type": "error", "error": "exclave is shutting down"}),
+                },
+            },
+        };
+        writeln!(writer, "{}", reply)
+    }
+
+    /// Handle a line of the text protocol, in the same way as a program
+    /// interface.  STATUS is answered with the current state as JSON.
+    fn text_line(
+        line: &str,
+        writer: &mut Vec<u8>,
+        station: &StationState,
+        id: &UnitName,
+        control: &Sender<ManagerControlMessage>,
+    ) -> io::Result<()> {
+        let mut words: Vec<String> = line
+            .split_whitespace()
+            .map(|x| Interface::cfti_unescape(x.to_owned()))
+            .collect();
+        let verb = words.remove(0).to_lowercase();
+        match verb.as_str() {
+            "status" => writeln!(writer, "STATUS {}", station.state_json()),
+            "logs" => {
+                for entry in station.logs() {
+                    Interface::text_format(writer, ManagerStatusMessage::Log(entry.clone()))?;
+                }
+                Ok(())
+            }
+            _ => {
+                let (contents, slot) = Interface::parse_command(&verb, words);
+                control.send(ManagerControlMessage::new_with_slot(id, slot, contents)).ok();
+                Ok(())
+            }
+        }
+    }
+}
+
+impl InterfaceServer for ControlSocket {
+    /// Stop accepting connections, disconnect all clients, and remove the socket.
+    fn stop(&self) {
+        self.running.store(false, Ordering::SeqCst);
+
+        // Wake up the accept thread so that it notices.
+        UnixStream::connect(&self.path).ok();
+
+        for client in self.state.lock().unwrap().clients.drain(..) {
+            client.stream.shutdown(::std::net::Shutdown::Both).ok();
+        }
+        fs::remove_file(&self.path).ok();
+    }
+
+    /// Record a status message, and pass it on to every connected client.
+    fn output_message(&self, msg: ManagerStatusMessage) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.station.update(&msg);
+        if state.clients.is_empty() {
+            return Ok(());
+        }
+
+        let json_line = format!("{}\n", Interface::json_object(msg.clone()));
+        let mut text_line = vec![];
+        Interface::text_format(&mut text_line, msg)?;
+
+        // Clients that can't keep up are dropped, rather than holding up the manager.
+        state.clients.retain(|client| {
+            let line = match client.protocol {
+                Protocol::JSON => json_line.as_bytes().to_vec(),
+                Protocol::Text => text_line.clone(),
+            };
+            match client.lines.try_send(line) {
+                Ok(()) => true,
+                Err(_) => {
+                    client.stream.shutdown(::std::net::Shutdown::Both).ok();
+                    false
+                }
+            }
+        });
+        Ok(())
+    }
+}
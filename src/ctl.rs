@@ -0,0 +1,91 @@
+extern crate serde_json;
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+
+use self::serde_json::Value;
+
+/// Run an "exclave ctl" command against a running instance, using its
+/// socket interface.  Returns the exit code.
+pub fn run(socket: &Path, command: &str, args: &[&str], follow: bool) -> i32 {
+    match run_command(socket, command, args, follow) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("exclave ctl: {}: {}", socket.display(), e);
+            1
+        }
+    }
+}
+
+fn run_command(socket: &Path, command: &str, args: &[&str], follow: bool) -> io::Result<i32> {
+    let request = match command {
+        "status" | "units" | "reload" | "logs" => json!({"verb": command}),
+        "start" | "abort" => json!({"verb": command, "args": args}),
+        other => {
+            eprintln!("exclave ctl: unknown command \"{}\"", other);
+            return Ok(2);
+        }
+    };
+
+    let mut stream = UnixStream::connect(socket)?;
+    writeln!(stream, "{}", request)?;
+
+    // Other messages are sent to every client, so skip anything that isn't
+    // the answer to this command.
+    for line in BufReader::new(stream).lines() {
+        let mut object: Value = match serde_json::from_str(&line?) {
+            Ok(o) => o,
+            Err(_) => continue,
+        };
+        let kind = object["type"].as_str().unwrap_or("").to_owned();
+        match (command, kind.as_str()) {
+            (_, "error") => {
+                eprintln!("exclave ctl: {}", object["error"].as_str().unwrap_or("unknown error"));
+                return Ok(1);
+            }
+            ("status", "state") => {
+                if let Some(o) = object.as_object_mut() {
+                    o.remove("type");
+                }
+                println!("{}", serde_json::to_string_pretty(&object).unwrap_or_default());
+                return Ok(0);
+            }
+            ("units", "units") => {
+                for unit in object["units"].as_array().unwrap_or(&vec![]) {
+                    println!(
+                        "{}.{}\t{}\t{}",
+                        unit["unit"].as_str().unwrap_or(""),
+                        unit["kind"].as_str().unwrap_or(""),
+                        unit["status"].as_str().unwrap_or(""),
+                        unit["last_failure"].as_str().unwrap_or("")
+                    );
+                }
+                return Ok(0);
+            }
+            ("logs", "log") => print_log(&object["entry"]),
+            ("logs", "accepted") if !follow => return Ok(0),
+            ("start", "accepted") | ("abort", "accepted") | ("reload", "accepted") => return Ok(0),
+            _ => (),
+        }
+    }
+
+    // The connection closing is only expected when following logs.
+    if follow {
+        Ok(0)
+    } else {
+        eprintln!("exclave ctl: connection closed before a reply was received");
+        Ok(1)
+    }
+}
+
+/// Print a log entry in the same form as the console.
+fn print_log(entry: &Value) {
+    println!(
+        "{} {}.{}: {}",
+        entry["log_type"].as_str().unwrap_or("").to_uppercase(),
+        entry["unit"]["id"].as_str().unwrap_or(""),
+        entry["unit"]["kind"].as_str().unwrap_or("").to_lowercase(),
+        entry["log_message"].as_str().unwrap_or("")
+    );
+}
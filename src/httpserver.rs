@@ -0,0 +1,499 @@
+extern crate serde_json;
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{sync_channel, Sender, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use stationstate::StationState;
+use unit::UnitName;
+use unitmanager::{ManagerControlMessage, ManagerControlMessageContents, ManagerStatusMessage};
+use units::interface::{Interface, InterfaceServer};
+
+use self::serde_json::{Map, Value};
+
+/// The largest request body or WebSocket frame that will be accepted.
+const MAX_REQUEST_SIZE: u64 = 64 * 1024;
+
+/// Appended to a client's key to form the WebSocket accept key (RFC 6455).
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// How many frames may wait to be sent to a WebSocket client before it is
+/// dropped for not keeping up.
+const CLIENT_QUEUE_SIZE: usize = 1000;
+
+/// A connected WebSocket client.  Every frame for it, whether a status
+/// message or a reply to one of its own frames, goes through its writer
+/// thread, so frames never get mixed up and a slow client can't hold up
+/// the manager.
+struct Client {
+    number: usize,
+    stream: TcpStream,
+    frames: SyncSender<Vec<u8>>,
+}
+
+/// The server's view of the station, along with the WebSocket clients that
+/// get every status message.
+struct State {
+    station: StationState,
+    clients: Vec<Client>,
+
+    /// How many WebSocket clients have connected, used to number them
+    connections: usize,
+}
+
+/// A small HTTP server offering a JSON REST API for running scenarios, plus a
+/// WebSocket stream of every status message at /api/events.
+pub struct HttpServer {
+    id: UnitName,
+    address: SocketAddr,
+    state: Arc<Mutex<State>>,
+    running: Arc<AtomicBool>,
+    control: Sender<ManagerControlMessage>,
+}
+
+impl HttpServer {
+    /// Start listening on the given address, e.g. "127.0.0.1:8080".  Requests
+    /// are sent to the manager as coming from the interface `id`.
+    pub fn start(listen: &str, id: &UnitName, control: Sender<ManagerControlMessage>) -> io::Result<HttpServer> {
+        let listener = TcpListener::bind(listen)?;
+        let address = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(State {
+            station: StationState::new(),
+            clients: vec![],
+            connections: 0,
+        }));
+        let running = Arc::new(AtomicBool::new(true));
+
+        let thr_state = state.clone();
+        let thr_running = running.clone();
+        let thr_id = id.clone();
+        let thr_control = control.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if !thr_running.load(Ordering::SeqCst) {
+                    break;
+                }
+                let stream = match stream {
+                    Ok(s) => s,
+                    Err(_) => continue,
+                };
+                let state = thr_state.clone();
+                let id = thr_id.clone();
+                let control = thr_control.clone();
+                thread::spawn(move || Self::handle_connection(stream, &state, &id, &control).ok());
+            }
+        });
+
+        Ok(HttpServer {
+            id: id.clone(),
+            address: address,
+            state: state,
+            running: running,
+            control: control,
+        })
+    }
+
+    fn handle_connection(
+        stream: TcpStream,
+        state: &Mutex<State>,
+        id: &UnitName,
+        control: &Sender<ManagerControlMessage>,
+    ) -> io::Result<()> {
+        stream.set_read_timeout(Some(Duration::from_secs(30)))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = stream;
+
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        let words: Vec<&str> = request_line.split_whitespace().collect();
+        if words.len() < 2 {
+            return Self::respond(&mut writer, "400 Bad Request", &json!({"error": "malformed request"}));
+        }
+        let method = words[0].to_uppercase();
+        let path = words[1].split('?').next().unwrap_or("").trim_end_matches('/').to_owned();
+
+        let mut headers = HashMap::new();
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 {
+                break;
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some(idx) = line.find(':') {
+                headers.insert(line[..idx].trim().to_lowercase(), line[idx + 1..].trim().to_owned());
+            }
+        }
+
+        let content_length = headers
+            .get("content-length")
+            .and_then(|x| x.parse::<u64>().ok())
+            .unwrap_or(0);
+        if content_length > MAX_REQUEST_SIZE {
+            return Self::respond(&mut writer, "413 Payload Too Large", &json!({"error": "request too large"}));
+        }
+        let mut body = String::new();
+        (&mut reader).take(content_length).read_to_string(&mut body)?;
+
+        let segments: Vec<&str> = path.split('/').skip(1).collect();
+        let (status, response) = match (method.as_str(), segments.as_slice()) {
+            ("GET", ["api", "events"]) => {
+                return Self::websocket(reader, writer, &headers, state, id, control);
+            }
+            ("GET", ["api", "scenarios"]) => ("200 OK", state.lock().unwrap().station.scenarios_json()),
+            ("GET", ["api", "scenarios", scenario, "tests"]) => match state.lock().unwrap().station.tests_json(scenario) {
+                Some(tests) => ("200 OK", tests),
+                None => ("404 Not Found", json!({"error": format!("unknown scenario {}", scenario)})),
+            },
+            ("GET", ["api", "state"]) => ("200 OK", state.lock().unwrap().station.state_json()),
+            ("GET", ["api", "history"]) => ("200 OK", state.lock().unwrap().station.history_json()),
+            ("POST", ["api", verb]) if *verb == "start" || *verb == "abort" || *verb == "command" => {
+                match Self::parse_body(&body) {
+                    Err(e) => ("400 Bad Request", json!({"error": e})),
+                    Ok(mut command) => {
+                        if *verb != "command" {
+                            // A "scenario" field is the first argument, as in "start scenario".
+                            if let Some(scenario) = command.remove("scenario") {
+                                command.insert("args".to_owned(), json!([scenario]));
+                            }
+                            command.insert("verb".to_owned(), json!(verb));
+                        }
+                        Self::send_command(&Value::Object(command), id, control)
+                    }
+                }
+            }
+            _ => ("404 Not Found", json!({"error": format!("no such endpoint: {} {}", method, path)})),
+        };
+        Self::respond(&mut writer, status, &response)
+    }
+
+    fn parse_body(body: &str) -> Result<Map<String, Value>, String> {
+        if body.trim().is_empty() {
+            return Ok(Map::new());
+        }
+        match serde_json::from_str::<Value>(body) {
+            Ok(Value::Object(object)) => Ok(object),
+            Ok(_) => Err("request body must be a JSON object".to_owned()),
+            Err(e) => Err(format!("invalid JSON: {}", e)),
+        }
+    }
+
+    /// Pass a JSON command (in the same form JSON interfaces use) to the manager.
+    fn send_command(
+        command: &Value,
+        id: &UnitName,
+        control: &Sender<ManagerControlMessage>,
+    ) -> (&'static str, Value) {
+        match Interface::json_command(command) {
+            (ManagerControlMessageContents::Error(e), _) => ("400 Bad Request", json!({"error": e})),
+            (ManagerControlMessageContents::Unimplemented(verb, _), _) => {
+                ("400 Bad Request", json!({"error": format!("unrecognized command: {}", verb)}))
+            }
+            (contents, slot) => match control.send(ManagerControlMessage::new_with_slot(id, slot, contents)) {
+                Ok(_) => ("202 Accepted", json!({"status": "accepted"})),
+                Err(_) => ("503 Service Unavailable", json!({"error": "exclave is shutting down"})),
+            },
+        }
+    }
+
+    fn respond(stream: &mut TcpStream, status: &str, body: &Value) -> io::Result<()> {
+        let body = body.to_string();
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        )
+    }
+
+    /// Upgrade the connection to a WebSocket.  The client first gets the
+    /// current state as a "state" message, then every status message as it
+    /// happens.  Text frames from the client are treated as JSON commands.
+    fn websocket(
+        mut reader: BufReader<TcpStream>,
+        mut writer: TcpStream,
+        headers: &HashMap<String, String>,
+        state: &Mutex<State>,
+        id: &UnitName,
+        control: &Sender<ManagerControlMessage>,
+    ) -> io::Result<()> {
+        let key = match headers.get("sec-websocket-key") {
+            Some(k) if headers.get("upgrade").map(|x| x.to_lowercase()) == Some("websocket".to_owned()) => k,
+            _ => {
+                return Self::respond(&mut writer, "400 Bad Request", &json!({"error": "expected a WebSocket upgrade"}))
+            }
+        };
+        write!(
+            writer,
+            "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+            websocket_accept_key(key)
+        )?;
+        writer.set_write_timeout(Some(Duration::from_secs(5)))?;
+        reader.get_ref().set_read_timeout(None)?;
+
+        let (frames, frame_receiver) = sync_channel::<Vec<u8>>(CLIENT_QUEUE_SIZE);
+        let mut thr_stream = writer.try_clone()?;
+        let write_thread = thread::spawn(move || {
+            for frame in frame_receiver.iter() {
+                if thr_stream.write_all(&frame).is_err() {
+                    // Wake up the reading side, so the connection is closed.
+                    thr_stream.shutdown(::std::net::Shutdown::Both).ok();
+                    break;
+                }
+            }
+        });
+
+        // The snapshot is queued while holding the lock, so it comes before any
+        // status message that isn't already part of it.
+        let number = {
+            let mut state = state.lock().unwrap();
+            let mut snapshot = state.station.state_json();
+            snapshot["type"] = json!("state");
+            frames.send(websocket_frame(0x1, snapshot.to_string().as_bytes())).ok();
+            state.connections += 1;
+            let client = Client {
+                number: state.connections,
+                stream: writer.try_clone()?,
+                frames: frames.clone(),
+            };
+            state.clients.push(client);
+            state.connections
+        };
+
+        let result = Self::websocket_read(&mut reader, &frames, id, control);
+
+        // Let the writer thread finish sending what's queued, such as a close frame.
+        state.lock().unwrap().clients.retain(|x| x.number != number);
+        drop(frames);
+        write_thread.join().ok();
+        writer.shutdown(::std::net::Shutdown::Both).ok();
+        result
+    }
+
+    /// Read frames from a WebSocket client until it closes the connection,
+    /// queueing any replies for its writer thread.
+    fn websocket_read(
+        reader: &mut BufReader<TcpStream>,
+        frames: &SyncSender<Vec<u8>>,
+        id: &UnitName,
+        control: &Sender<ManagerControlMessage>,
+    ) -> io::Result<()> {
+        let send = |frame: Vec<u8>| {
+            frames
+                .send(frame)
+                .map_err(|_| io::Error::new(ErrorKind::BrokenPipe, "WebSocket client has gone away"))
+        };
+        loop {
+            let (opcode, payload) = read_websocket_frame(reader)?;
+            match opcode {
+                // Text
+                0x1 => {
+                    let (status, response) = match serde_json::from_slice::<Value>(&payload) {
+                        Ok(command) => Self::send_command(&command, id, control),
+                        Err(e) => ("400 Bad Request", json!({"error": format!("invalid JSON: {}", e)})),
+                    };
+                    if status.starts_with('4') {
+                        let mut error = response;
+                        error["type"] = json!("error");
+                        send(websocket_frame(0x1, error.to_string().as_bytes()))?;
+                    }
+                }
+                // Close
+                0x8 => return send(websocket_frame(0x8, &payload)),
+                // Ping
+                0x9 => send(websocket_frame(0xa, &payload))?,
+                _ => (),
+            }
+        }
+    }
+}
+
+impl InterfaceServer for HttpServer {
+    /// Stop accepting connections, and disconnect any WebSocket clients.
+    fn stop(&self) {
+        self.running.store(false, Ordering::SeqCst);
+
+        // Wake up the accept thread so that it notices.
+        TcpStream::connect(self.address).ok();
+
+        for client in self.state.lock().unwrap().clients.drain(..) {
+            client.stream.shutdown(::std::net::Shutdown::Both).ok();
+        }
+    }
+
+    /// Record a status message, and pass it on to every WebSocket client.
+    fn output_message(&self, msg: ManagerStatusMessage) -> io::Result<()> {
+        // Keep the test lists up to date, so they can be served without waiting.
+        if let ManagerStatusMessage::Scenarios(ref list) = msg {
+            for scenario in list {
+                self.control
+                    .send(ManagerControlMessage::new(
+                        &self.id,
+                        ManagerControlMessageContents::Tests(Some(scenario.clone())),
+                    )).ok();
+            }
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.station.update(&msg);
+        if state.clients.is_empty() {
+            return Ok(());
+        }
+
+        let frame = websocket_frame(0x1, Interface::json_object(msg).to_string().as_bytes());
+        // Clients that can't keep up are dropped, rather than holding up the manager.
+        state.clients.retain(|client| match client.frames.try_send(frame.clone()) {
+            Ok(()) => true,
+            Err(_) => {
+                client.stream.shutdown(::std::net::Shutdown::Both).ok();
+                false
+            }
+        });
+        Ok(())
+    }
+}
+
+/// Build an unmasked, unfragmented WebSocket frame.
+fn websocket_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x80 | opcode];
+    let len = payload.len();
+    if len < 126 {
+        frame.push(len as u8);
+    } else if len < 65536 {
+        frame.push(126);
+        frame.push((len >> 8) as u8);
+        frame.push(len as u8);
+    } else {
+        frame.push(127);
+        for shift in (0..8).rev() {
+            frame.push(((len as u64) >> (shift * 8)) as u8);
+        }
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Read one WebSocket frame, returning its opcode and unmasked payload.
+fn read_websocket_frame<R: Read>(reader: &mut R) -> io::Result<(u8, Vec<u8>)> {
+    let mut header = [0u8; 2];
+    reader.read_exact(&mut header)?;
+    let opcode = header[0] & 0x0f;
+    let masked = header[1] & 0x80 != 0;
+    let len = match header[1] & 0x7f {
+        126 => {
+            let mut ext = [0u8; 2];
+            reader.read_exact(&mut ext)?;
+            ext.iter().fold(0, |acc, b| acc << 8 | u64::from(*b))
+        }
+        127 => {
+            let mut ext = [0u8; 8];
+            reader.read_exact(&mut ext)?;
+            ext.iter().fold(0, |acc, b| acc << 8 | u64::from(*b))
+        }
+        l => u64::from(l),
+    };
+    if len > MAX_REQUEST_SIZE {
+        return Err(io::Error::new(ErrorKind::InvalidData, "WebSocket frame too large"));
+    }
+    let mut mask = [0u8; 4];
+    if masked {
+        reader.read_exact(&mut mask)?;
+    }
+    let mut payload = vec![0u8; len as usize];
+    reader.read_exact(&mut payload)?;
+    if masked {
+        for (i, b) in payload.iter_mut().enumerate() {
+            *b ^= mask[i % 4];
+        }
+    }
+    Ok((opcode, payload))
+}
+
+/// Compute the Sec-WebSocket-Accept value for a client's Sec-WebSocket-Key.
+pub fn websocket_accept_key(key: &str) -> String {
+    base64(&sha1(format!("{}{}", key.trim(), WEBSOCKET_GUID).as_bytes()))
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x6745_2301, 0xEFCD_AB89, 0x98BA_DCFE, 0x1032_5476, 0xC3D2_E1F0];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    let bit_len = (data.len() as u64).wrapping_mul(8);
+    for shift in (0..8).rev() {
+        message.push((bit_len >> (shift * 8)) as u8);
+    }
+
+    for chunk in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in chunk.chunks(4).enumerate() {
+            w[i] = word.iter().fold(0, |acc, b| acc << 8 | u32::from(*b));
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let (mut a, mut b, mut c, mut d, mut e) = (h[0], h[1], h[2], h[3], h[4]);
+        for (i, word) in w.iter().enumerate() {
+            let (f, k) = if i < 20 {
+                ((b & c) | (!b & d), 0x5A82_7999)
+            } else if i < 40 {
+                (b ^ c ^ d, 0x6ED9_EBA1)
+            } else if i < 60 {
+                ((b & c) | (b & d) | (c & d), 0x8F1B_BCDC)
+            } else {
+                (b ^ c ^ d, 0xCA62_C1D6)
+            };
+            let temp = a.rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        h[0] = h[0].wrapping_add(a);
+        h[1] = h[1].wrapping_add(b);
+        h[2] = h[2].wrapping_add(c);
+        h[3] = h[3].wrapping_add(d);
+        h[4] = h[4].wrapping_add(e);
+    }
+
+    let mut digest = [0u8; 20];
+    for (i, value) in h.iter().enumerate() {
+        for j in 0..4 {
+            digest[i * 4 + j] = (value >> (24 - j * 8)) as u8;
+        }
+    }
+    digest
+}
+
+fn base64(data: &[u8]) -> String {
+    const CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in data.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |acc, (i, b)| acc | u32::from(*b) << (16 - i * 8));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(CHARS[(n >> (18 - i * 6)) as usize & 63] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
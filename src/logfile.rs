@@ -0,0 +1,216 @@
+extern crate humantime;
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use unit::UnitDescriptionError;
+
+use self::humantime::{parse_duration, DurationError};
+
+/// Somewhere a logger's writer thread can send formatted lines.
+pub trait LogOutput: Write + Send {
+    /// Make sure everything written so far has been safely stored.
+    fn sync(&mut self) -> io::Result<()> {
+        self.flush()
+    }
+}
+
+/// When a log file gets rotated, and how many old files are kept.
+#[derive(Clone, Debug)]
+pub struct RotationPolicy {
+    /// Rotate before the file grows past this many bytes.
+    pub max_size: Option<u64>,
+
+    /// Rotate once the file has been written to for this long.
+    pub max_age: Option<Duration>,
+
+    /// How many rotated files to keep, or 0 to keep them all.
+    pub retain: u32,
+}
+
+impl RotationPolicy {
+    pub fn new() -> RotationPolicy {
+        RotationPolicy {
+            max_size: None,
+            max_age: None,
+            retain: 0,
+        }
+    }
+
+    /// Parse one of the rotation directives from the named section.
+    /// Returns Ok(false) if the key is not a rotation directive.
+    pub fn parse_directive(
+        &mut self,
+        section: &str,
+        key: &str,
+        value: Option<&str>,
+    ) -> Result<bool, UnitDescriptionError> {
+        match key {
+            "RotateSize" | "RotateInterval" | "Retain" => (),
+            _ => return Ok(false),
+        }
+        let value = match value {
+            Some(v) => v,
+            None => {
+                return Err(UnitDescriptionError::MissingValue(
+                    section.to_owned(),
+                    key.to_owned(),
+                ))
+            }
+        };
+        match key {
+            "RotateSize" => self.max_size = Some(Self::parse_size(value)?),
+            "RotateInterval" => self.max_age = Some(Self::parse_time(value)?),
+            _ => self.retain = value.parse()?,
+        }
+        Ok(true)
+    }
+
+    /// Parse a size in bytes, optionally followed by K, M, or G.
+    fn parse_size(size_str: &str) -> Result<u64, UnitDescriptionError> {
+        let size_str = size_str.trim();
+        let (number, multiplier) = match size_str.chars().last() {
+            Some('k') | Some('K') => (&size_str[..size_str.len() - 1], 1024),
+            Some('m') | Some('M') => (&size_str[..size_str.len() - 1], 1024 * 1024),
+            Some('g') | Some('G') => (&size_str[..size_str.len() - 1], 1024 * 1024 * 1024),
+            _ => (size_str, 1),
+        };
+        Ok(number.trim().parse::<u64>()? * multiplier)
+    }
+
+    fn parse_time(time_str: &str) -> Result<Duration, DurationError> {
+        if let Ok(val) = time_str.parse::<u64>() {
+            Ok(Duration::from_secs(val))
+        } else {
+            parse_duration(time_str)
+        }
+    }
+}
+
+/// A log file that is rotated according to a RotationPolicy.  Rotated files
+/// get a numeric suffix, with ".1" being the most recent.
+pub struct LogFile {
+    path: PathBuf,
+    policy: RotationPolicy,
+
+    /// A line to start each new file with, such as a CSV header
+    header: Option<String>,
+
+    file: File,
+    size: u64,
+    opened: Instant,
+
+    /// Files are only rotated between lines, never in the middle of one.
+    at_line_start: bool,
+}
+
+impl LogFile {
+    pub fn open(path: &Path, policy: &RotationPolicy, header: Option<String>) -> io::Result<LogFile> {
+        let (file, size) = Self::open_file(path, &header)?;
+        Ok(LogFile {
+            path: path.to_owned(),
+            policy: policy.clone(),
+            header: header,
+            file: file,
+            size: size,
+            opened: Instant::now(),
+            at_line_start: true,
+        })
+    }
+
+    /// Open a file for appending, starting it with the header if it's empty.
+    fn open_file(path: &Path, header: &Option<String>) -> io::Result<(File, u64)> {
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        let mut size = file.metadata()?.len();
+        if size == 0 {
+            if let Some(ref header) = *header {
+                writeln!(file, "{}", header)?;
+                size = header.len() as u64 + 1;
+            }
+        }
+        Ok((file, size))
+    }
+
+    fn rotated_path(&self, number: u32) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", number));
+        PathBuf::from(name)
+    }
+
+    fn header_size(&self) -> u64 {
+        self.header.as_ref().map(|h| h.len() as u64 + 1).unwrap_or(0)
+    }
+
+    fn needs_rotation(&self, incoming: u64) -> bool {
+        if !self.at_line_start || self.size <= self.header_size() {
+            return false;
+        }
+        if let Some(max_size) = self.policy.max_size {
+            if self.size + incoming > max_size {
+                return true;
+            }
+        }
+        if let Some(max_age) = self.policy.max_age {
+            if self.opened.elapsed() >= max_age {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Move the current file to ".1", shifting older files up by one and
+    /// removing any beyond the retention limit, then start a new file.
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.sync_all()?;
+
+        let mut oldest = 0;
+        while self.rotated_path(oldest + 1).exists() {
+            oldest += 1;
+        }
+        for number in (1..oldest + 1).rev() {
+            if self.policy.retain > 0 && number >= self.policy.retain {
+                fs::remove_file(self.rotated_path(number))?;
+            } else {
+                fs::rename(self.rotated_path(number), self.rotated_path(number + 1))?;
+            }
+        }
+        fs::rename(&self.path, self.rotated_path(1))?;
+
+        let (file, size) = Self::open_file(&self.path, &self.header)?;
+        self.file = file;
+        self.size = size;
+        self.opened = Instant::now();
+        Ok(())
+    }
+}
+
+impl Write for LogFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.needs_rotation(buf.len() as u64) {
+            self.rotate()?;
+        }
+        let count = self.file.write(buf)?;
+        self.size += count as u64;
+        if count > 0 {
+            self.at_line_start = buf[count - 1] == b'\n';
+        }
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl LogOutput for LogFile {
+    fn sync(&mut self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+}
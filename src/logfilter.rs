@@ -0,0 +1,119 @@
+extern crate regex;
+
+use unit::UnitDescriptionError;
+use unitbroadcaster::LogEntry;
+
+use self::regex::Regex;
+
+/// One `field:value,value` term of a filter.  Each value is a regex,
+/// which for unit names is built from a glob.
+#[derive(Clone, Debug)]
+struct LogFilterTerm {
+    field: LogFilterField,
+    values: Vec<Regex>,
+    negated: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum LogFilterField {
+    Kind,
+    Unit,
+    Type,
+    Source,
+    Message,
+}
+
+/// Decides which log entries get sent to a logger or interface.
+///
+/// A filter is a whitespace-separated list of `field:value` terms, where
+/// `field` is one of `kind`, `unit`, `type`, `source`, or `message`.  A
+/// term may list several comma-separated values, and matches if any of
+/// them do.  An entry passes if it matches every term.  Prefixing a term
+/// with `!` inverts it, so that matching entries are rejected instead.
+#[derive(Clone, Debug, Default)]
+pub struct LogFilter {
+    terms: Vec<LogFilterTerm>,
+}
+
+impl LogFilter {
+    pub fn from_str(section: &str, key: &str, filter: &str) -> Result<LogFilter, UnitDescriptionError> {
+        let mut terms = vec![];
+        for word in filter.split_whitespace() {
+            let (negated, word) = if word.starts_with('!') {
+                (true, &word[1..])
+            } else {
+                (false, word)
+            };
+            let mut parts = word.splitn(2, ':');
+            let field = match parts.next().unwrap_or("") {
+                "kind" => LogFilterField::Kind,
+                "unit" => LogFilterField::Unit,
+                "type" => LogFilterField::Type,
+                "source" => LogFilterField::Source,
+                "message" => LogFilterField::Message,
+                _ => {
+                    return Err(UnitDescriptionError::InvalidValue(
+                        section.to_owned(),
+                        key.to_owned(),
+                        word.to_owned(),
+                        vec![
+                            "kind:".to_owned(),
+                            "unit:".to_owned(),
+                            "type:".to_owned(),
+                            "source:".to_owned(),
+                            "message:".to_owned(),
+                        ],
+                    ))
+                }
+            };
+            let value = match parts.next() {
+                Some(v) => v,
+                None => return Err(UnitDescriptionError::MissingValue(section.to_owned(), key.to_owned())),
+            };
+
+            // Message regexes may contain commas themselves, so only split other fields.
+            let values = match field {
+                LogFilterField::Message => vec![Regex::new(value)?],
+                LogFilterField::Unit => value
+                    .split(',')
+                    .map(|glob| Regex::new(&Self::glob_to_regex(glob)))
+                    .collect::<Result<Vec<Regex>, regex::Error>>()?,
+                _ => value
+                    .split(',')
+                    .map(|exact| Regex::new(&format!("^{}$", regex::escape(&exact.to_lowercase()))))
+                    .collect::<Result<Vec<Regex>, regex::Error>>()?,
+            };
+            terms.push(LogFilterTerm {
+                field: field,
+                values: values,
+                negated: negated,
+            });
+        }
+        Ok(LogFilter { terms: terms })
+    }
+
+    /// Determine whether a log entry should be passed on.
+    pub fn matches(&self, entry: &LogEntry) -> bool {
+        self.terms.iter().all(|term| {
+            let kind;
+            let subject = match term.field {
+                LogFilterField::Kind => {
+                    kind = format!("{}", entry.id().kind());
+                    &kind
+                }
+                LogFilterField::Unit => entry.id().id(),
+                LogFilterField::Type => entry.kind().as_str(),
+                LogFilterField::Source => entry.source().as_str(),
+                LogFilterField::Message => entry.message().as_str(),
+            };
+            term.values.iter().any(|re| re.is_match(subject)) != term.negated
+        })
+    }
+
+    fn glob_to_regex(glob: &str) -> String {
+        format!(
+            "^{}$",
+            regex::escape(glob).replace("\\*", ".*").replace("\\?", ".")
+        )
+    }
+}
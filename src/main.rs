@@ -4,8 +4,11 @@ extern crate ctrlc;
 #[macro_use]
 extern crate serde_derive;
 extern crate serde;
+#[macro_use]
 extern crate serde_json;
 
+use std::path::Path;
+use std::sync::mpsc::RecvTimeoutError;
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
@@ -18,36 +21,39 @@ mod units;
 mod unitwatcher;
 mod terminal;
 mod config;
+mod artifacts;
+mod httpserver;
+#[cfg(unix)]
+mod controlsocket;
+mod stationstate;
+#[cfg(unix)]
+mod ctl;
+mod logfile;
+mod logfilter;
+mod metrics;
 mod quiesce;
+mod sdnotify;
+#[cfg(unix)]
+mod sighup;
+mod yieldstats;
 
-use unitbroadcaster::{UnitBroadcaster, UnitEvent};
+use unit::UnitName;
+use unitbroadcaster::{LogType, UnitBroadcaster, UnitEvent};
+use unitmanager::{ManagerControlMessage, ManagerControlMessageContents};
+use units::scenario::RunOptions;
 use unitwatcher::UnitWatcher;
 use unitloader::UnitLoader;
 use unitlibrary::UnitLibrary;
 
-use clap::{App, Arg};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 
 fn main() {
-    let config = Arc::new(Mutex::new(config::Config::new()));
-
-    let unit_broadcaster = UnitBroadcaster::new();
-    let message_receiver = unit_broadcaster.subscribe();
-    let unit_library = UnitLibrary::new(&unit_broadcaster, &config);
-    let unit_loader = UnitLoader::new(&unit_broadcaster);
-    let mut unit_watcher = UnitWatcher::new(&unit_broadcaster);
-
-    // The signal handler must come first, so that the same mask gets
-    // applied to all threads.
-    let ctrl_c_broadcaster = unit_broadcaster.clone();
-    ctrlc::set_handler(move || {
-        ctrl_c_broadcaster.broadcast(&UnitEvent::Shutdown);
-    }).expect("Error setting Ctrl-C handler");
-
     let matches = App::new("Exclave Testing System")
         .version(env!("CARGO_PKG_VERSION"))
         .long_version(env!("GIT_VERSION"))
         .author("Sean Cross <sean@xobs.io>")
         .about("Orchestrates the Common Factory Test Interface server")
+        .setting(AppSettings::SubcommandsNegateReqs)
         .arg(
             Arg::with_name("CONFIG_DIR")
                 .short("c")
@@ -71,6 +77,15 @@ fn main() {
                 .long("no-output")
                 .help("Prevent console output entirely"),
         )
+        .arg(
+            Arg::with_name("LOG_LEVEL")
+                .long("log-level")
+                .value_name("LEVEL")
+                .help("Only show log entries at least this severe on the console")
+                .possible_values(&["debug", "info", "warn", "error"])
+                .default_value("info")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("DEBUG_LOGFILE")
                 .short("9")
@@ -79,14 +94,152 @@ fn main() {
                 .required(false)
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("ARTIFACT_DIR")
+                .long("artifact-dir")
+                .value_name("DIR")
+                .help("Save the output and results of each scenario run in its own directory under DIR")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("ARTIFACT_RETAIN")
+                .long("artifact-retain")
+                .value_name("COUNT")
+                .help("Only keep the most recent COUNT run directories under --artifact-dir")
+                .required(false)
+                .takes_value(true)
+                .validator(is_count),
+        )
+        .arg(
+            Arg::with_name("METRICS_LISTEN")
+                .long("metrics-listen")
+                .value_name("ADDRESS")
+                .help("Serve Prometheus metrics at /metrics on ADDRESS, e.g. 127.0.0.1:9100")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("METRICS_FILE")
+                .long("metrics-file")
+                .value_name("FILE")
+                .help("Write Prometheus metrics to FILE, for a textfile collector")
+                .required(false)
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("KEYBOARD_TRIGGER")
                 .short("k")
                 .long("keyboard-trigger")
                 .help("Run default scenario on enter key press"),
         )
+        .arg(
+            Arg::with_name("RUN_TEST")
+                .short("r")
+                .long("run")
+                .value_name("TEST")
+                .help("Run the specified test, along with its dependencies, once units have loaded")
+                .required(false)
+                .validator(is_test_name)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("ASSUME")
+                .long("assume")
+                .value_name("TEST")
+                .help("Assume the specified test passed when starting a scenario from the keyboard")
+                .required(false)
+                .validator(is_test_name)
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("SKIP")
+                .long("skip")
+                .value_name("TEST")
+                .help("Skip the specified test when starting a scenario from the keyboard")
+                .required(false)
+                .validator(is_test_name)
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true),
+        )
+        .subcommand(
+            SubCommand::with_name("ctl")
+                .about("Control a running instance through its socket interface")
+                .setting(AppSettings::TrailingVarArg)
+                .arg(
+                    Arg::with_name("SOCKET")
+                        .short("s")
+                        .long("socket")
+                        .value_name("PATH")
+                        .help("Path of the socket interface to connect to")
+                        .default_value("/run/exclave.sock")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("FOLLOW")
+                        .short("f")
+                        .long("follow")
+                        .help("With logs, keep printing new log entries as they arrive"),
+                )
+                .arg(
+                    Arg::with_name("COMMAND")
+                        .required(true)
+                        .possible_values(&["status", "start", "abort", "units", "logs", "reload"])
+                        .help("What to do"),
+                )
+                .arg(
+                    Arg::with_name("ARGS")
+                        .multiple(true)
+                        .help("Arguments for the command, e.g. a scenario and run options for start"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("stats")
+                .about("Print yield and failure statistics from saved scenario runs")
+                .arg(
+                    Arg::with_name("ARTIFACT_DIR")
+                        .short("d")
+                        .long("artifact-dir")
+                        .value_name("DIR")
+                        .help("The directory runs were saved under with --artifact-dir")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("WINDOW")
+                        .multiple(true)
+                        .help("Which runs to include, e.g. \"8h\" or \"last 50\" (default: all of them)"),
+                ),
+        )
         .get_matches();
 
+    if let Some(ctl_matches) = matches.subcommand_matches("ctl") {
+        std::process::exit(run_ctl(ctl_matches));
+    }
+    if let Some(stats_matches) = matches.subcommand_matches("stats") {
+        std::process::exit(run_stats(stats_matches));
+    }
+
+    let config = Arc::new(Mutex::new(config::Config::new()));
+
+    let unit_broadcaster = UnitBroadcaster::new();
+    let message_receiver = unit_broadcaster.subscribe();
+    let unit_library = UnitLibrary::new(&unit_broadcaster, &config);
+    let unit_loader = UnitLoader::new(&unit_broadcaster);
+    let mut unit_watcher = UnitWatcher::new(&unit_broadcaster);
+
+    // The signal handler must come first, so that the same mask gets
+    // applied to all threads.
+    let ctrl_c_broadcaster = unit_broadcaster.clone();
+    ctrlc::set_handler(move || {
+        ctrl_c_broadcaster.broadcast(&UnitEvent::Shutdown);
+    }).expect("Error setting Ctrl-C handler");
+    #[cfg(unix)]
+    sighup::reload_on_sighup(&unit_broadcaster).expect("Error setting SIGHUP handler");
+
     let config_dirs: Vec<_> = matches.values_of("CONFIG_DIR").unwrap().collect();
     let output_type = if matches.is_present("PLAIN") {
         Some(terminal::TerminalOutputType::Plain)
@@ -96,7 +249,38 @@ fn main() {
         None
     };
 
-    terminal::TerminalInterface::start(output_type, &unit_broadcaster, matches.is_present("KEYBOARD_TRIGGER"));
+    let mut run_options = RunOptions::default();
+    for name in matches.values_of("ASSUME").unwrap_or_default() {
+        run_options.assume.push(UnitName::from_str(name, "test").unwrap());
+    }
+    for name in matches.values_of("SKIP").unwrap_or_default() {
+        run_options.skip.push(UnitName::from_str(name, "test").unwrap());
+    }
+
+    let log_level = LogType::from_str(matches.value_of("LOG_LEVEL").unwrap()).unwrap();
+
+    if let Some(artifact_dir) = matches.value_of("ARTIFACT_DIR") {
+        let retain = matches
+            .value_of("ARTIFACT_RETAIN")
+            .map(|r| r.parse().unwrap())
+            .unwrap_or(0);
+        config.lock().unwrap().set_artifact_root(Path::new(artifact_dir), retain);
+        unit_library.get_manager().borrow().load_test_durations();
+    }
+
+    let mut metrics = metrics::Metrics::new();
+    if let Some(address) = matches.value_of("METRICS_LISTEN") {
+        metrics
+            .listen(address)
+            .expect(&format!("Unable to serve metrics on {}", address));
+    }
+    if let Some(path) = matches.value_of("METRICS_FILE") {
+        metrics
+            .write_to(Path::new(path))
+            .expect(&format!("Unable to write metrics to {}", path));
+    }
+
+    terminal::TerminalInterface::start(output_type, &unit_broadcaster, matches.is_present("KEYBOARD_TRIGGER"), run_options, log_level);
 
     for config_dir in config_dirs {
         unit_watcher
@@ -106,6 +290,10 @@ fn main() {
 
     let mut quiesce = quiesce::Quiesce::new(Duration::from_secs(1), &unit_broadcaster);
 
+    let mut run_test = matches
+        .value_of("RUN_TEST")
+        .map(|name| UnitName::from_str(name, "test").unwrap());
+
     unit_broadcaster.log("main", format!("Exclave initializing"));
 
     let mut debug_file = match matches.value_of("DEBUG_LOGFILE") {
@@ -117,9 +305,28 @@ fn main() {
             Some(File::create(&path).expect("Couldn't create logfile"))
         }
     };
+    let mut notifier = sdnotify::Notifier::from_env();
+
     // Main message loop.  Monitor messages and pass them to each component.
+    // Under a systemd watchdog, wake up in time to ping it even when idle.
     let mut loops = 1;
-    while let Ok(msg) = message_receiver.recv() {
+    loop {
+        let msg = match notifier.as_ref().and_then(|n| n.timeout()) {
+            Some(timeout) => match message_receiver.recv_timeout(timeout) {
+                Ok(msg) => msg,
+                Err(RecvTimeoutError::Timeout) => {
+                    if let Some(n) = notifier.as_mut() {
+                        n.keepalive();
+                    }
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            },
+            None => match message_receiver.recv() {
+                Ok(msg) => msg,
+                Err(_) => break,
+            },
+        };
         if let Some(file) = debug_file.as_mut() {
             use std::io::Write;
             use std::time;
@@ -145,6 +352,82 @@ fn main() {
         unit_loader.process_message(&msg);
         unit_library.process_message(&msg);
         quiesce.process_message(&msg);
+        metrics.process_message(&msg);
+        if let Some(notifier) = notifier.as_mut() {
+            notifier.process_message(&msg);
+            notifier.keepalive();
+        }
+
+        // The watcher knows which directories to read again.
+        if msg == UnitEvent::ReloadRequest {
+            unit_library.reload(unit_watcher.paths());
+        }
+
+        // Once the first scan has loaded all units, run any test requested on the command line.
+        if msg == UnitEvent::RescanFinish {
+            if let Some(test_name) = run_test.take() {
+                let id = UnitName::internal("main");
+                unit_broadcaster.broadcast(&UnitEvent::ManagerRequest(ManagerControlMessage::new(
+                    &id,
+                    ManagerControlMessageContents::RunTest(test_name),
+                )));
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn run_ctl(matches: &ArgMatches) -> i32 {
+    let args: Vec<&str> = matches.values_of("ARGS").unwrap_or_default().collect();
+    ctl::run(
+        Path::new(matches.value_of("SOCKET").unwrap()),
+        matches.value_of("COMMAND").unwrap(),
+        &args,
+        matches.is_present("FOLLOW"),
+    )
+}
+
+#[cfg(not(unix))]
+fn run_ctl(_: &ArgMatches) -> i32 {
+    eprintln!("exclave ctl is only supported on Unix");
+    1
+}
+
+/// Check a test name given on the command line, so that clap can report it.
+fn is_test_name(name: String) -> Result<(), String> {
+    UnitName::from_str(&name, "test")
+        .map(|_| ())
+        .map_err(|e| format!("invalid test name \"{}\": {}", name, e))
+}
+
+fn is_count(count: String) -> Result<(), String> {
+    count
+        .parse::<usize>()
+        .map(|_| ())
+        .map_err(|e| format!("invalid count \"{}\": {}", count, e))
+}
+
+fn run_stats(matches: &ArgMatches) -> i32 {
+    let window: Vec<&str> = matches.values_of("WINDOW").unwrap_or_default().collect();
+    let window = match yieldstats::StatsWindow::from_str(&window.join(" ")) {
+        Ok(w) => w,
+        Err(e) => {
+            eprintln!("exclave stats: {}", e);
+            return 2;
+        }
+    };
+    let root = Path::new(matches.value_of("ARTIFACT_DIR").unwrap());
+    match yieldstats::YieldStats::load(root, &window) {
+        Ok(stats) => {
+            for line in stats.report() {
+                println!("{}", line);
+            }
+            0
+        }
+        Err(e) => {
+            eprintln!("exclave stats: {}: {}", root.display(), e);
+            1
+        }
     }
 }
 
@@ -0,0 +1,261 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as FmtWrite;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use unit::{UnitKind, UnitName};
+use unitbroadcaster::{UnitEvent, UnitStatus, UnitStatusEvent};
+use unitmanager::{ManagerControlMessage, ManagerControlMessageContents};
+
+/// Upper bounds of the test duration histogram buckets, in seconds.
+const DURATION_BUCKETS: [f64; 10] = [0.1, 0.5, 1.0, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0, 600.0];
+
+#[derive(Default)]
+struct Histogram {
+    /// How many observations fell into each bucket (not cumulative).
+    buckets: [u64; 10],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        if let Some(idx) = DURATION_BUCKETS.iter().position(|x| value <= *x) {
+            self.buckets[idx] += 1;
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+#[derive(Default)]
+struct Counters {
+    scenarios_started: BTreeMap<String, u64>,
+    scenarios_passed: BTreeMap<String, u64>,
+    scenarios_failed: BTreeMap<String, u64>,
+    test_durations: BTreeMap<String, Histogram>,
+    test_failures: BTreeMap<String, u64>,
+    unit_restarts: BTreeMap<(String, String), u64>,
+    active_jig: Option<String>,
+}
+
+/// Counters and gauges describing what the station has been doing, fed from
+/// the broadcaster and served in the Prometheus text format.
+pub struct Metrics {
+    counters: Arc<Mutex<Counters>>,
+
+    /// When each running test started, keyed by test and slot.
+    test_start_times: HashMap<(UnitName, Option<u32>), Instant>,
+
+    /// A file for the node exporter's textfile collector, rewritten whenever
+    /// something changes.
+    textfile: Option<PathBuf>,
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Metrics {
+            counters: Arc::new(Mutex::new(Counters::default())),
+            test_start_times: HashMap::new(),
+            textfile: None,
+        }
+    }
+
+    /// Serve the metrics over HTTP on the given address, e.g. "127.0.0.1:9100".
+    pub fn listen(&self, address: &str) -> io::Result<()> {
+        let listener = TcpListener::bind(address)?;
+        let counters = self.counters.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if let Ok(stream) = stream {
+                    Self::handle_connection(stream, &counters).ok();
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Write the metrics to the given file whenever they change.
+    pub fn write_to(&mut self, path: &Path) -> io::Result<()> {
+        self.textfile = Some(path.to_owned());
+        self.write_textfile()
+    }
+
+    fn handle_connection(stream: TcpStream, counters: &Mutex<Counters>) -> io::Result<()> {
+        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = stream;
+
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 || line.trim_end().is_empty() {
+                break;
+            }
+        }
+
+        let words: Vec<&str> = request_line.split_whitespace().collect();
+        let (status, body) = match (words.get(0), words.get(1).map(|x| x.split('?').next().unwrap_or(""))) {
+            (Some(&"GET"), Some("/metrics")) => ("200 OK", Self::render_counters(&counters.lock().unwrap())),
+            _ => ("404 Not Found", "metrics are served at /metrics\n".to_owned()),
+        };
+        write!(
+            writer,
+            "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        )
+    }
+
+    /// Write the file through a temporary one, so the collector never reads half of it.
+    fn write_textfile(&self) -> io::Result<()> {
+        let path = match self.textfile {
+            Some(ref p) => p,
+            None => return Ok(()),
+        };
+        let mut temp_name = path.clone().into_os_string();
+        temp_name.push(".tmp");
+        fs::write(&temp_name, self.render())?;
+        fs::rename(&temp_name, path)
+    }
+
+    /// The metrics, in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        Self::render_counters(&self.counters.lock().unwrap())
+    }
+
+    fn render_counters(counters: &Counters) -> String {
+        let mut out = String::new();
+        Self::render_map(&mut out, "exclave_scenarios_started_total", "Scenario runs started.", "scenario", &counters.scenarios_started);
+        Self::render_map(&mut out, "exclave_scenarios_passed_total", "Scenario runs where every test passed.", "scenario", &counters.scenarios_passed);
+        Self::render_map(&mut out, "exclave_scenarios_failed_total", "Scenario runs that failed or were aborted.", "scenario", &counters.scenarios_failed);
+        Self::render_map(&mut out, "exclave_test_failures_total", "Test runs that failed.", "test", &counters.test_failures);
+
+        out.push_str("# HELP exclave_test_duration_seconds How long each test took to run.\n");
+        out.push_str("# TYPE exclave_test_duration_seconds histogram\n");
+        for (test, histogram) in &counters.test_durations {
+            let test = escape_label(test);
+            let mut cumulative = 0;
+            for (bound, count) in DURATION_BUCKETS.iter().zip(histogram.buckets.iter()) {
+                cumulative += count;
+                writeln!(out, "exclave_test_duration_seconds_bucket{{test=\"{}\",le=\"{}\"}} {}", test, bound, cumulative).ok();
+            }
+            writeln!(out, "exclave_test_duration_seconds_bucket{{test=\"{}\",le=\"+Inf\"}} {}", test, histogram.count).ok();
+            writeln!(out, "exclave_test_duration_seconds_sum{{test=\"{}\"}} {}", test, histogram.sum).ok();
+            writeln!(out, "exclave_test_duration_seconds_count{{test=\"{}\"}} {}", test, histogram.count).ok();
+        }
+
+        out.push_str("# HELP exclave_unit_restarts_total Times a unit was restarted after exiting.\n");
+        out.push_str("# TYPE exclave_unit_restarts_total counter\n");
+        for (&(ref unit, ref kind), count) in &counters.unit_restarts {
+            writeln!(out, "exclave_unit_restarts_total{{unit=\"{}\",kind=\"{}\"}} {}", escape_label(unit), kind, count).ok();
+        }
+
+        out.push_str("# HELP exclave_active_jig The jig that is currently active.\n");
+        out.push_str("# TYPE exclave_active_jig gauge\n");
+        if let Some(ref jig) = counters.active_jig {
+            writeln!(out, "exclave_active_jig{{jig=\"{}\"}} 1", escape_label(jig)).ok();
+        }
+        out
+    }
+
+    fn render_map(out: &mut String, name: &str, help: &str, label: &str, values: &BTreeMap<String, u64>) {
+        writeln!(out, "# HELP {} {}", name, help).ok();
+        writeln!(out, "# TYPE {} counter", name).ok();
+        for (key, value) in values {
+            writeln!(out, "{}{{{}=\"{}\"}} {}", name, label, escape_label(key), value).ok();
+        }
+    }
+
+    /// Update the metrics from an event on the broadcaster.
+    pub fn process_message(&mut self, msg: &UnitEvent) {
+        let changed = match msg {
+            &UnitEvent::ManagerRequest(ref req) => self.manager_request(req),
+            &UnitEvent::Status(ref status) => self.unit_status(status),
+            _ => false,
+        };
+        if changed {
+            self.write_textfile().ok();
+        }
+    }
+
+    fn manager_request(&mut self, req: &ManagerControlMessage) -> bool {
+        let &ManagerControlMessage {
+            ref sender,
+            ref contents,
+            slot,
+        } = req;
+        let mut counters = self.counters.lock().unwrap();
+        match contents {
+            &ManagerControlMessageContents::ScenarioStarted => {
+                *counters.scenarios_started.entry(sender.id().to_owned()).or_insert(0) += 1;
+            }
+            &ManagerControlMessageContents::ScenarioFinished(code, _) => {
+                let map = if code == 200 {
+                    &mut counters.scenarios_passed
+                } else {
+                    &mut counters.scenarios_failed
+                };
+                *map.entry(sender.id().to_owned()).or_insert(0) += 1;
+            }
+            &ManagerControlMessageContents::TestStarted => {
+                self.test_start_times.insert((sender.clone(), slot), Instant::now());
+                return false;
+            }
+            &ManagerControlMessageContents::TestFinished(result, _) => {
+                if let Some(start) = self.test_start_times.remove(&(sender.clone(), slot)) {
+                    let elapsed = start.elapsed();
+                    let seconds = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1_000_000_000.0;
+                    counters
+                        .test_durations
+                        .entry(sender.id().to_owned())
+                        .or_insert_with(Histogram::default)
+                        .observe(seconds);
+                }
+                if result != 0 {
+                    *counters.test_failures.entry(sender.id().to_owned()).or_insert(0) += 1;
+                }
+            }
+            &ManagerControlMessageContents::Restart => {
+                let key = (sender.id().to_owned(), format!("{}", sender.kind()));
+                *counters.unit_restarts.entry(key).or_insert(0) += 1;
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    fn unit_status(&mut self, event: &UnitStatusEvent) -> bool {
+        if *event.kind() != UnitKind::Jig {
+            return false;
+        }
+        let mut counters = self.counters.lock().unwrap();
+        let jig = event.name().id().to_owned();
+        match event.status() {
+            &UnitStatus::Active => counters.active_jig = Some(jig),
+            &UnitStatus::DeactivatedSuccessfully(_)
+            | &UnitStatus::DeactivatedUnsuccessfully(_)
+            | &UnitStatus::Deselected(_)
+            | &UnitStatus::Removed(_) => {
+                if counters.active_jig.as_ref() != Some(&jig) {
+                    return false;
+                }
+                counters.active_jig = None;
+            }
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// Escape a label value, as the text format requires.
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
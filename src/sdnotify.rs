@@ -0,0 +1,197 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::ffi::OsStr;
+use std::io;
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+use std::process;
+use std::time::{Duration, Instant};
+
+use unitbroadcaster::UnitEvent;
+use unitmanager::{ManagerControlMessage, ManagerControlMessageContents};
+
+/// Tells systemd how exclave is doing when it runs as a Type=notify
+/// service: READY=1 once the first scan of units has finished, STATUS=
+/// with what each slot is doing, and WATCHDOG=1 from the main loop so that
+/// systemd can restart exclave if the loop gets stuck.
+pub struct Notifier {
+    #[cfg(unix)]
+    socket: UnixDatagram,
+
+    /// How often systemd expects to hear from us, if the watchdog is enabled.
+    watchdog: Option<Duration>,
+    last_ping: Instant,
+
+    ready: bool,
+    last_status: String,
+
+    /// The scenario on each slot, and the test it is running.
+    runs: BTreeMap<Option<u32>, (String, Option<String>)>,
+}
+
+impl Notifier {
+    /// Connect to the socket in NOTIFY_SOCKET, if exclave was started by
+    /// systemd.  The variables are removed so that units don't inherit them.
+    pub fn from_env() -> Option<Notifier> {
+        let path = env::var_os("NOTIFY_SOCKET")?;
+        // The watchdog is meant for the process systemd started, and not for
+        // anything that happened to inherit the variables.
+        let for_us = env::var("WATCHDOG_PID")
+            .map(|pid| pid == process::id().to_string())
+            .unwrap_or(true);
+        let watchdog = match env::var("WATCHDOG_USEC") {
+            Ok(ref usec) if for_us => usec.parse().ok().map(Duration::from_micros),
+            _ => None,
+        };
+        env::remove_var("NOTIFY_SOCKET");
+        env::remove_var("WATCHDOG_USEC");
+        env::remove_var("WATCHDOG_PID");
+
+        match Self::connect(&path, watchdog) {
+            Ok(notifier) => Some(notifier),
+            Err(e) => {
+                eprintln!("Unable to connect to NOTIFY_SOCKET {}: {}", path.to_string_lossy(), e);
+                None
+            }
+        }
+    }
+
+    /// Connect to a notification socket.  A path starting with "@" is in the
+    /// abstract namespace.
+    #[cfg(unix)]
+    pub fn connect(path: &OsStr, watchdog: Option<Duration>) -> io::Result<Notifier> {
+        let socket = UnixDatagram::unbound()?;
+        match path.as_bytes().split_first() {
+            Some((&b'@', name)) => Self::connect_abstract(&socket, name)?,
+            _ => socket.connect(path)?,
+        }
+        // Never hold up the main loop waiting on systemd.
+        socket.set_nonblocking(true)?;
+
+        let notifier = Notifier {
+            socket: socket,
+            watchdog: watchdog,
+            last_ping: Instant::now(),
+            ready: false,
+            last_status: String::new(),
+            runs: BTreeMap::new(),
+        };
+        notifier.send("STATUS=Loading units")?;
+        Ok(notifier)
+    }
+
+    #[cfg(not(unix))]
+    pub fn connect(_: &OsStr, _: Option<Duration>) -> io::Result<Notifier> {
+        Err(io::Error::new(io::ErrorKind::Other, "NOTIFY_SOCKET is only supported on Unix"))
+    }
+
+    #[cfg(target_os = "linux")]
+    fn connect_abstract(socket: &UnixDatagram, name: &[u8]) -> io::Result<()> {
+        use std::os::linux::net::SocketAddrExt;
+        use std::os::unix::net::SocketAddr;
+
+        socket.connect_addr(&SocketAddr::from_abstract_name(name)?)
+    }
+
+    #[cfg(all(unix, not(target_os = "linux")))]
+    fn connect_abstract(_: &UnixDatagram, _: &[u8]) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Other, "abstract sockets are only supported on Linux"))
+    }
+
+    #[cfg(unix)]
+    fn send(&self, state: &str) -> io::Result<()> {
+        self.socket.send(state.as_bytes()).map(|_| ())
+    }
+
+    #[cfg(not(unix))]
+    fn send(&self, _: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// How long the main loop may wait for a message before it has to call
+    /// `keepalive`, or None if the watchdog isn't enabled.
+    pub fn timeout(&self) -> Option<Duration> {
+        // Ping at twice the rate systemd asks for, as it recommends.
+        self.watchdog
+            .map(|interval| (interval / 2).checked_sub(self.last_ping.elapsed()).unwrap_or_default())
+    }
+
+    /// Ping the watchdog, if it is due.
+    pub fn keepalive(&mut self) {
+        if let Some(interval) = self.watchdog {
+            if self.last_ping.elapsed() >= interval / 2 {
+                self.send("WATCHDOG=1").ok();
+                self.last_ping = Instant::now();
+            }
+        }
+    }
+
+    /// Describe what each slot is doing, for STATUS=.
+    fn status(&self) -> String {
+        if self.runs.is_empty() {
+            return "Waiting for a scenario to start".to_owned();
+        }
+        let runs: Vec<String> = self.runs
+            .iter()
+            .map(|(slot, &(ref scenario, ref test))| {
+                let state = match *test {
+                    Some(ref test) => format!("running {}: {}", scenario, test),
+                    None => format!("running {}", scenario),
+                };
+                match *slot {
+                    Some(s) => format!("slot {} {}", s, state),
+                    None => state,
+                }
+            })
+            .collect();
+        runs.join("; ")
+    }
+
+    /// Update systemd from an event on the broadcaster.
+    pub fn process_message(&mut self, msg: &UnitEvent) {
+        let mut finished = None;
+        match msg {
+            &UnitEvent::RescanFinish if !self.ready => {
+                self.ready = true;
+                self.last_status = self.status();
+                self.send(&format!("READY=1\nSTATUS={}", self.last_status)).ok();
+                return;
+            }
+            &UnitEvent::Shutdown => {
+                self.send("STOPPING=1").ok();
+                return;
+            }
+            &UnitEvent::ManagerRequest(ManagerControlMessage {
+                ref sender,
+                ref contents,
+                slot,
+            }) => match contents {
+                &ManagerControlMessageContents::ScenarioStarted => {
+                    self.runs.insert(slot, (sender.id().to_owned(), None));
+                }
+                &ManagerControlMessageContents::TestStarted => match self.runs.get_mut(&slot) {
+                    Some(run) => run.1 = Some(sender.id().to_owned()),
+                    None => return,
+                },
+                &ManagerControlMessageContents::ScenarioFinished(code, ref message) => {
+                    self.runs.remove(&slot);
+                    finished = Some(format!("{} finished: {} ({})", sender.id(), message, code));
+                }
+                _ => return,
+            },
+            _ => return,
+        }
+
+        // Until something else starts, say how the last run went.
+        let status = match finished {
+            Some(ref finished) if self.runs.is_empty() => finished.clone(),
+            _ => self.status(),
+        };
+        if status != self.last_status {
+            self.send(&format!("STATUS={}", status)).ok();
+            self.last_status = status;
+        }
+    }
+}
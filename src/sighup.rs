@@ -0,0 +1,58 @@
+extern crate libc;
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::mem;
+use std::os::unix::io::FromRawFd;
+use std::ptr;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::thread;
+
+use unitbroadcaster::{UnitBroadcaster, UnitEvent};
+
+/// The end of the pipe that the signal handler writes to.
+static PIPE_WRITE: AtomicI32 = AtomicI32::new(-1);
+
+extern "C" fn handle_sighup(_: libc::c_int) {
+    // Only async-signal-safe calls are allowed here, so just wake up the thread.
+    let fd = PIPE_WRITE.load(Ordering::SeqCst);
+    unsafe {
+        libc::write(fd, b"h".as_ptr() as *const libc::c_void, 1);
+    }
+}
+
+/// Read the configuration directories again whenever exclave gets SIGHUP.
+pub fn reload_on_sighup(broadcaster: &UnitBroadcaster) -> io::Result<()> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // Keep the pipe out of the programs that units run.
+    for fd in &fds {
+        unsafe {
+            libc::fcntl(*fd, libc::F_SETFD, libc::FD_CLOEXEC);
+        }
+    }
+    PIPE_WRITE.store(fds[1], Ordering::SeqCst);
+
+    let mut action: libc::sigaction = unsafe { mem::zeroed() };
+    action.sa_sigaction = handle_sighup as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    action.sa_flags = libc::SA_RESTART;
+    unsafe {
+        libc::sigemptyset(&mut action.sa_mask);
+    }
+    if unsafe { libc::sigaction(libc::SIGHUP, &action, ptr::null_mut()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut pipe = unsafe { File::from_raw_fd(fds[0]) };
+    let broadcaster = broadcaster.clone();
+    thread::spawn(move || {
+        let mut buf = [0; 1];
+        while let Ok(1) = pipe.read(&mut buf) {
+            broadcaster.log("main", "received SIGHUP, reloading units".to_owned());
+            broadcaster.broadcast(&UnitEvent::ReloadRequest);
+        }
+    });
+    Ok(())
+}
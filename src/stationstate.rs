@@ -0,0 +1,208 @@
+extern crate serde_json;
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use unit::UnitName;
+use unitbroadcaster::LogEntry;
+use unitmanager::ManagerStatusMessage;
+
+use self::serde_json::{Map, Value};
+
+/// How many finished runs are kept in the history.
+const HISTORY_LENGTH: usize = 100;
+
+/// How many recent log entries are kept.
+const LOG_LENGTH: usize = 100;
+
+/// Everything a built-in interface has learned from status messages, so
+/// that it can answer requests without having to go through the manager.
+pub struct StationState {
+    jig: Option<String>,
+    slots: u32,
+    scenarios: Vec<String>,
+    selected: Option<String>,
+
+    /// The test sequence of each scenario.
+    tests: BTreeMap<String, Vec<String>>,
+
+    /// Names and descriptions, keyed by full unit name (e.g. "flash.test").
+    descriptions: HashMap<String, Map<String, Value>>,
+
+    /// The most recent run on each slot.
+    runs: BTreeMap<Option<u32>, Value>,
+
+    /// Finished runs, oldest first.
+    history: VecDeque<Value>,
+
+    /// The most recent log entries, oldest first.
+    logs: VecDeque<LogEntry>,
+}
+
+impl StationState {
+    pub fn new() -> StationState {
+        StationState {
+            jig: None,
+            slots: 1,
+            scenarios: vec![],
+            selected: None,
+            tests: BTreeMap::new(),
+            descriptions: HashMap::new(),
+            runs: BTreeMap::new(),
+            history: VecDeque::new(),
+            logs: VecDeque::new(),
+        }
+    }
+
+    fn describe(&self, name: &str, kind: &str) -> Value {
+        let mut object = self.descriptions
+            .get(&format!("{}.{}", name, kind))
+            .cloned()
+            .unwrap_or_default();
+        object.insert("id".to_owned(), json!(name));
+        Value::Object(object)
+    }
+
+    /// Each scenario's id, name, and description.
+    pub fn scenarios_json(&self) -> Value {
+        Value::Array(self.scenarios.iter().map(|x| self.describe(x, "scenario")).collect())
+    }
+
+    /// The tests a scenario runs, in order, or None if the scenario isn't known.
+    pub fn tests_json(&self, scenario: &str) -> Option<Value> {
+        self.tests
+            .get(scenario)
+            .map(|tests| Value::Array(tests.iter().map(|x| self.describe(x, "test")).collect()))
+    }
+
+    /// The jig, the selected scenario, and the current run on each slot.
+    pub fn state_json(&self) -> Value {
+        json!({
+            "jig": self.jig,
+            "slots": self.slots,
+            "scenario": self.selected,
+            "runs": self.runs.values().collect::<Vec<&Value>>(),
+        })
+    }
+
+    pub fn history_json(&self) -> Value {
+        Value::Array(self.history.iter().cloned().collect())
+    }
+
+    pub fn logs(&self) -> &VecDeque<LogEntry> {
+        &self.logs
+    }
+
+    /// Get the current run on a slot, starting a new record if there isn't one.
+    fn run(&mut self, scenario: &UnitName, slot: Option<u32>) -> &mut Value {
+        self.runs.entry(slot).or_insert_with(|| {
+            json!({
+                "scenario": scenario.id(),
+                "slot": slot,
+                "state": "running",
+                "test": null,
+                "tests": {},
+                "started": unix_time(),
+            })
+        })
+    }
+
+    fn set_test_state(&mut self, test: &UnitName, slot: Option<u32>, state: &str) {
+        if let Some(run) = self.runs.get_mut(&slot) {
+            run["tests"][test.id()] = json!(state);
+            run["prompt"] = Value::Null;
+            if state == "running" {
+                run["test"] = json!(test.id());
+            }
+        }
+    }
+
+    /// Update the cached state from a status message.
+    pub fn update(&mut self, msg: &ManagerStatusMessage) {
+        match *msg {
+            ManagerStatusMessage::Jig(ref jig) => self.jig = jig.as_ref().map(|x| x.id().to_owned()),
+            ManagerStatusMessage::Slots(count) => self.slots = count,
+            ManagerStatusMessage::Scenarios(ref list) => {
+                self.scenarios = list.iter().map(|x| x.id().to_owned()).collect();
+                self.scenarios.sort();
+            }
+            ManagerStatusMessage::Scenario(ref name) => {
+                self.selected = name.as_ref().map(|x| x.id().to_owned())
+            }
+            ManagerStatusMessage::Tests(ref scenario, ref tests) => {
+                self.tests.insert(
+                    scenario.id().to_owned(),
+                    tests.iter().map(|x| x.id().to_owned()).collect(),
+                );
+            }
+            ManagerStatusMessage::Describe(ref id, ref field, ref value) => {
+                self.descriptions
+                    .entry(format!("{}", id))
+                    .or_insert_with(Map::new)
+                    .insert(format!("{}", field), json!(value));
+            }
+            ManagerStatusMessage::Start(ref scenario, slot) => {
+                self.runs.remove(&slot);
+                self.run(scenario, slot);
+            }
+            ManagerStatusMessage::Running(ref test, slot) => self.set_test_state(test, slot, "running"),
+            ManagerStatusMessage::Pass(ref test, _, slot) => self.set_test_state(test, slot, "pass"),
+            ManagerStatusMessage::Fail(ref test, _, _, slot) => self.set_test_state(test, slot, "fail"),
+            ManagerStatusMessage::Skipped(ref test, _, slot) => self.set_test_state(test, slot, "skip"),
+            ManagerStatusMessage::Paused(ref scenario, slot) => {
+                self.run(scenario, slot)["state"] = json!("paused")
+            }
+            ManagerStatusMessage::Resumed(ref scenario, slot) => {
+                self.run(scenario, slot)["state"] = json!("running")
+            }
+            ManagerStatusMessage::Eta(ref scenario, seconds, slot) => {
+                self.run(scenario, slot)["eta"] = json!(seconds)
+            }
+            ManagerStatusMessage::Values(ref scenario, ref values, slot) => {
+                self.run(scenario, slot)["values"] = json!(values)
+            }
+            ManagerStatusMessage::Progress(ref test, percent, ref status, slot) => {
+                if let Some(run) = self.runs.get_mut(&slot) {
+                    run["progress"] = json!({"test": test.id(), "percent": percent, "status": status});
+                }
+            }
+            ManagerStatusMessage::Prompt(ref test, ref prompt, ref responses, slot) => {
+                if let Some(run) = self.runs.get_mut(&slot) {
+                    run["prompt"] = json!({"test": test.id(), "prompt": prompt, "responses": responses});
+                }
+            }
+            ManagerStatusMessage::Finished(ref scenario, result, ref reason, slot) => {
+                let record = {
+                    let run = self.run(scenario, slot);
+                    run["state"] = json!("finished");
+                    run["test"] = Value::Null;
+                    run["prompt"] = Value::Null;
+                    run["result"] = json!(result);
+                    run["reason"] = json!(reason);
+                    run["finished"] = json!(unix_time());
+                    run.clone()
+                };
+                self.history.push_back(record);
+                while self.history.len() > HISTORY_LENGTH {
+                    self.history.pop_front();
+                }
+            }
+            ManagerStatusMessage::Log(ref entry) => {
+                self.logs.push_back(entry.clone());
+                while self.logs.len() > LOG_LENGTH {
+                    self.logs.pop_front();
+                }
+            }
+            ManagerStatusMessage::Hello(_)
+            | ManagerStatusMessage::Units(_)
+            | ManagerStatusMessage::Stats(_) => (),
+        }
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs())
+        .unwrap_or(0)
+}
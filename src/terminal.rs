@@ -2,12 +2,14 @@ extern crate console;
 
 use self::console::Term;
 use unit::{UnitKind, UnitName};
-use unitbroadcaster::{LogEntry, UnitCategoryStatus, UnitEvent, UnitStatus};
+use unitbroadcaster::{LogEntry, LogType, UnitCategoryStatus, UnitEvent, UnitStatus};
 use std::collections::{BTreeMap, HashMap};
 use std::sync::mpsc::Receiver;
 use unitbroadcaster::{UnitBroadcaster};
 use std::thread;
 use unitmanager::{ManagerControlMessage, ManagerControlMessageContents};
+use units::scenario::RunOptions;
+use yieldstats::StatsWindow;
 
 #[derive(PartialEq)]
 pub enum TerminalOutputType {
@@ -42,10 +44,13 @@ pub struct TerminalInterface {
 
     /// How many lines of history to keep.
     log_history: usize,
+
+    /// Log entries less severe than this aren't shown.
+    log_level: LogType,
 }
 
 impl TerminalInterface {
-    pub fn start(output_type: Option<TerminalOutputType>, broadcaster: &UnitBroadcaster, monitor_keypress: bool) {
+    pub fn start(output_type: Option<TerminalOutputType>, broadcaster: &UnitBroadcaster, monitor_keypress: bool, run_options: RunOptions, log_level: LogType) {
         let stdout = Term::stdout();
         let output_type = match output_type {
             Some(s) => s,
@@ -63,6 +68,7 @@ impl TerminalInterface {
                 last_line_count: 0,
                 logs: vec![],
                 log_history: MAX_LOG_HISTORY,
+                log_level: log_level,
             };
 
             while let Ok(event) = receiver.recv() {
@@ -75,20 +81,51 @@ impl TerminalInterface {
             let id = UnitName::internal("terminal");
             let thread_broadcaster = broadcaster.clone();
 
-            // Broadcast a start scenario message if an enter key is pressed in the terminal where exclave is running
-            // Could possibly be extended to do things like, run test #1 when the '1' key is entered or print stats when
-            // the '?' key is entered
+            // Broadcast a start scenario message if an enter key is pressed in the terminal where exclave is running.
+            // The scenario is started with any assume/skip options given on the command line.
+            // "p", "r", and "s" followed by enter will pause, resume, and single-step the running scenario.
+            // "a [response]" answers the prompt of a manual test.
+            // "?" prints yield statistics, and "stats [window]" limits them to e.g. the last 8h or the last 50 runs.
+            // Anything else is logged as an error rather than starting a scenario.
+            // Could possibly be extended to do things like, run test #1 when the '1' key is entered
             thread::spawn(move || {
                 loop {
                     let mut line = String::new();
                     std::io::stdin().read_line(&mut line).expect("Failed to read line");
-                    thread_broadcaster.broadcast(&UnitEvent::ManagerRequest(ManagerControlMessage::new(&id, ManagerControlMessageContents::StartScenario(None))));
+                    let line = line.trim();
+
+                    // Only the command word is case-insensitive, so answers are passed on as typed.
+                    let (command, rest) = match line.find(char::is_whitespace) {
+                        Some(idx) => (&line[..idx], line[idx..].trim_start()),
+                        None => (line, ""),
+                    };
+                    let msg = match command.to_lowercase().as_str() {
+                        "p" | "pause" => ManagerControlMessageContents::Pause,
+                        "r" | "resume" => ManagerControlMessageContents::Resume,
+                        "s" | "step" => ManagerControlMessageContents::Step,
+                        "?" => ManagerControlMessageContents::Stats(StatsWindow::default()),
+                        "stats" => match StatsWindow::from_str(rest) {
+                            Ok(window) => ManagerControlMessageContents::Stats(window),
+                            Err(e) => ManagerControlMessageContents::Error(e),
+                        },
+                        "a" | "answer" if !rest.is_empty() => ManagerControlMessageContents::Answer(None, rest.to_owned()),
+                        "a" | "answer" => ManagerControlMessageContents::Error("No answer given".to_owned()),
+                        "" => ManagerControlMessageContents::StartScenario(None, run_options.clone()),
+                        _ => ManagerControlMessageContents::Error(format!("unrecognized command \"{}\" (press enter to start a scenario)", line)),
+                    };
+                    thread_broadcaster.broadcast(&UnitEvent::ManagerRequest(ManagerControlMessage::new(&id, msg)));
                 }
             });
         }
     }
 
     fn update_unit(&mut self, event: UnitEvent) {
+        if let UnitEvent::Log(ref log) = event {
+            if *log.kind() < self.log_level {
+                return;
+            }
+        }
+
         // Insert the new event into the relevent data structures
         match event {
             UnitEvent::Category(ref cat) => {
@@ -121,6 +158,7 @@ impl TerminalInterface {
             UnitEvent::RescanStart => (),
             UnitEvent::RescanFinish => (),
             UnitEvent::RescanRequest => (),
+            UnitEvent::ReloadRequest => (),
             UnitEvent::Shutdown => (),
             UnitEvent::ManagerRequest(_) => (),
         }
@@ -137,6 +175,7 @@ impl TerminalInterface {
             UnitEvent::Status(stat) => println!("    {} -> {}", stat.name(), stat.status()),
             UnitEvent::Category(stat) => println!("{}: {}", stat.kind(), stat.status()),
             UnitEvent::RescanRequest => println!("Unit rescan requested"),
+            UnitEvent::ReloadRequest => println!("Unit reload requested"),
             UnitEvent::RescanStart => println!("Started unit recsan..."),
             UnitEvent::RescanFinish => println!("Finished rescanning units"),
             UnitEvent::Shutdown => println!("Shutting down"),
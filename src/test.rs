@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc::{Receiver, RecvError, Sender};
 use std::sync::{Arc, Mutex};
 use std::thread;
@@ -11,10 +11,10 @@ use unitbroadcaster::{UnitBroadcaster, UnitEvent, UnitStatus};
 use unitlibrary::UnitLibrary;
 use unitmanager::{ManagerControlMessage, ManagerControlMessageContents};
 
-use units::interface::InterfaceDescription;
+use units::interface::{Interface, InterfaceDescription};
 use units::jig::JigDescription;
 use units::logger::LoggerDescription;
-use units::scenario::ScenarioDescription;
+use units::scenario::{RunOptions, ScenarioDescription};
 use units::test::TestDescription;
 use units::trigger::TriggerDescription;
 
@@ -23,6 +23,7 @@ struct Exclave {
     receiver: Receiver<UnitEvent>,
     control: Sender<ManagerControlMessage>,
     library: UnitLibrary,
+    config: Arc<Mutex<Config>>,
 }
 
 const GENERIC_JIG: &str = r##"
@@ -121,9 +122,15 @@ impl Exclave {
             library: library,
             receiver: receiver,
             control: control,
+            config: config,
         }
     }
 
+    pub fn set_artifact_root(&self, root: &Path, retain: usize) {
+        self.config.lock().unwrap().set_artifact_root(root, retain);
+        self.library.get_manager().borrow().load_test_durations();
+    }
+
     pub fn add_unit(&self, name: &UnitName, unit_text: &str) {
         let name = name.clone();
         match *name.kind() {
@@ -155,6 +162,30 @@ impl Exclave {
                     .load_scenario(&desc)
                     .unwrap();
             }
+            UnitKind::Interface => {
+                let desc = InterfaceDescription::from_string(
+                    unit_text,
+                    name,
+                    &PathBuf::from("test/config"),
+                ).unwrap();
+                self.library
+                    .get_manager()
+                    .borrow()
+                    .load_interface(&desc)
+                    .unwrap();
+            }
+            UnitKind::Logger => {
+                let desc = LoggerDescription::from_string(
+                    unit_text,
+                    name,
+                    &PathBuf::from("test/config"),
+                ).unwrap();
+                self.library
+                    .get_manager()
+                    .borrow()
+                    .load_logger(&desc)
+                    .unwrap();
+            }
             _ => unimplemented!(),
         };
     }
@@ -163,22 +194,30 @@ impl Exclave {
         self.broadcaster.broadcast(&UnitEvent::RescanRequest);
     }
 
-    // pub fn activate(&self, name: &UnitName) {
-    //     self.manager.activate(name);
-    // }
+    pub fn activate(&self, name: &UnitName) {
+        self.library.get_manager().borrow().activate(name);
+    }
 
-    // pub fn deactivate(&self, name: &UnitName) {
-    //     self.manager
-    //         .deactivate(name, "test harness requested stop");
-    // }
+    pub fn deactivate(&self, name: &UnitName) {
+        self.library
+            .get_manager()
+            .borrow()
+            .deactivate(name, "test harness requested stop");
+    }
 
     pub fn start_scenario(&self, name: &UnitName) {
-        let mcmc = ManagerControlMessageContents::StartScenario(Some(name.clone()));
+        let mcmc = ManagerControlMessageContents::StartScenario(Some(name.clone()), RunOptions::default());
         self.control
             .send(ManagerControlMessage::new(name, mcmc))
             .expect("interface couldn't send exit message to controller");
     }
 
+    pub fn send_control(&self, name: &UnitName, contents: ManagerControlMessageContents) {
+        self.control
+            .send(ManagerControlMessage::new(name, contents))
+            .expect("couldn't send message to controller");
+    }
+
     pub fn run_once(&self) -> Result<UnitEvent, RecvError> {
         let msg = self.receiver.recv()?;
         self.library.process_message(&msg);
@@ -194,6 +233,7 @@ impl Exclave {
                     let ManagerControlMessage {
                         sender: ref sender_name,
                         contents: ref msg,
+                        ..
                     } = mrq;
                     match msg {
                         &ManagerControlMessageContents::ScenarioFinished(code, ref string) => {
@@ -284,9 +324,10 @@ ExecStop={}
                 let ManagerControlMessage {
                     sender: ref sender_name,
                     contents: ref msg,
+                    ..
                 } = mrq;
                 match msg {
-                    &ManagerControlMessageContents::Log(ref string) => {
+                    &ManagerControlMessageContents::Output(_, ref string) => {
                         if *sender_name == exec_stop && string == "cmd-ending" {
                             return;
                         }
@@ -354,9 +395,10 @@ ExecStopFailure={}
                 let ManagerControlMessage {
                     sender: ref sender_name,
                     contents: ref msg,
+                    ..
                 } = mrq;
                 match msg {
-                    &ManagerControlMessageContents::Log(ref string) => {
+                    &ManagerControlMessageContents::Output(_, ref string) => {
                         if *sender_name == exec_stop && string == "cmd-ending-success" {
                             return;
                         }
@@ -424,9 +466,10 @@ ExecStopFailure={}
                 let ManagerControlMessage {
                     sender: ref sender_name,
                     contents: ref msg,
+                    ..
                 } = mrq;
                 match msg {
-                    &ManagerControlMessageContents::Log(ref string) => {
+                    &ManagerControlMessageContents::Output(_, ref string) => {
                         if *sender_name == exec_stop && string == "cmd-ending-failure" {
                             return;
                         }
@@ -488,9 +531,10 @@ Tests=master
                 let ManagerControlMessage {
                     sender: ref sender_name,
                     contents: ref msg,
+                    ..
                 } = mrq;
                 match msg {
-                    &ManagerControlMessageContents::Log(ref string) => {
+                    &ManagerControlMessageContents::Output(_, ref string) => {
                         if *sender_name == dependent_name && string == "end-dependent" {
                             assert!(master_seen == false);
                             assert!(dependent_seen == false);
@@ -518,3 +562,1774 @@ Tests=master
         }
     }
 }
+
+#[cfg(unix)]
+#[test]
+fn multi_slot_scenario() {
+    let exclave = Exclave::new(Some(Duration::from_secs(30)));
+    let scenario_name = UnitName::from_str("slotted", "scenario").unwrap();
+    let test_name = UnitName::from_str("whichslot", "test").unwrap();
+
+    exclave.add_unit(
+        &UnitName::from_str("twoslot", "jig").unwrap(),
+        r##"[Jig]
+Name=Two-slot Jig
+Description=Tests two boards at once
+Slots=2
+"##,
+    );
+    exclave.add_unit(
+        &test_name,
+        r##"[Test]
+Name=Which slot
+Description=Print out the current slot
+ExecStart=/bin/sh -c "sleep 1; echo slot-$EXCLAVE_SLOT"
+"##,
+    );
+    exclave.add_unit(
+        &scenario_name,
+        r##"[Scenario]
+Name=Slotted Scenario
+Description=Run one test on each slot
+Tests=whichslot
+"##,
+    );
+    exclave.rescan();
+
+    // Start the scenario twice.  Each run should be placed on its own slot.
+    exclave.start_scenario(&scenario_name);
+    exclave.start_scenario(&scenario_name);
+
+    let mut slots_seen = vec![];
+    let mut slots_finished = vec![];
+    while slots_finished.len() < 2 {
+        let msg = exclave.run_once().unwrap();
+        println!("Message: {:?}", msg);
+        match msg {
+            UnitEvent::ManagerRequest(ref mrq) => {
+                let ManagerControlMessage {
+                    sender: ref sender_name,
+                    contents: ref msg,
+                    slot,
+                } = mrq;
+                match msg {
+                    &ManagerControlMessageContents::Output(_, ref string) => {
+                        if *sender_name == test_name && string.starts_with("slot-") {
+                            assert_eq!(*string, format!("slot-{}", slot.unwrap()));
+                            slots_seen.push(slot.unwrap());
+                        }
+                    }
+                    &ManagerControlMessageContents::ScenarioFinished(code, _) => {
+                        assert_eq!(code, 200);
+                        let slot = slot.expect("scenario finished without a slot");
+                        // A test's output is delivered before the test finishes.
+                        assert!(slots_seen.contains(&slot), "slot {} finished before its output arrived", slot);
+                        slots_finished.push(slot);
+                    }
+                    _ => (),
+                }
+            }
+            UnitEvent::Shutdown => panic!("timed out waiting for both slots to finish"),
+            _ => (),
+        }
+    }
+    slots_seen.sort();
+    slots_finished.sort();
+    assert_eq!(slots_seen, vec![1, 2]);
+    assert_eq!(slots_finished, vec![1, 2]);
+
+    // Unloading the scenario while it runs stops the copy on each slot.
+    exclave.start_scenario(&scenario_name);
+    exclave.start_scenario(&scenario_name);
+    let mut slots_started = 0;
+    while slots_started < 2 {
+        match exclave.run_once().unwrap() {
+            UnitEvent::ManagerRequest(ManagerControlMessage {
+                contents: ManagerControlMessageContents::TestStarted,
+                ..
+            }) => slots_started += 1,
+            UnitEvent::Shutdown => panic!("timed out waiting for both slots to start"),
+            _ => (),
+        }
+    }
+    exclave.library.get_manager().borrow().unload(&scenario_name);
+    let manager = exclave.library.get_manager();
+    assert!(manager.borrow().get_slot_scenario(1).is_none());
+    assert!(manager.borrow().get_slot_scenario(2).is_none());
+}
+
+#[test]
+fn scenario_pause_step_resume() {
+    let exclave = Exclave::new(Some(Duration::from_secs(30)));
+    let three_name = UnitName::from_str("three", "scenario").unwrap();
+
+    for n in 1..=3 {
+        exclave.add_unit(
+            &UnitName::from_str(&format!("test{}", n), "test").unwrap(),
+            &make_sleep_test(
+                &format!("test{}-start", n),
+                None,
+                &format!("test{}-stop", n),
+                None,
+            ),
+        );
+    }
+    exclave.add_unit(&three_name, THREE_TEST_SCENARIO);
+    exclave.rescan();
+
+    exclave.start_scenario(&three_name);
+
+    // Pause as soon as the first test starts, then step through the second
+    // test and resume for the third.
+    let mut tests_started = vec![];
+    loop {
+        let msg = exclave.run_once().unwrap();
+        println!("Message: {:?}", msg);
+        match msg {
+            UnitEvent::ManagerRequest(ref mrq) => {
+                let ManagerControlMessage {
+                    sender: ref sender_name,
+                    contents: ref msg,
+                    ..
+                } = mrq;
+                match msg {
+                    &ManagerControlMessageContents::TestStarted => {
+                        tests_started.push(sender_name.id().clone());
+                        if tests_started.len() == 1 {
+                            exclave.send_control(&three_name, ManagerControlMessageContents::Pause);
+                        }
+                    }
+                    // Each time the scenario holds a test, it reports that it has paused.
+                    &ManagerControlMessageContents::ScenarioPaused(_) => {
+                        if tests_started.len() == 1 {
+                            exclave.send_control(&three_name, ManagerControlMessageContents::Step);
+                        } else {
+                            assert_eq!(tests_started.len(), 2);
+                            exclave.send_control(&three_name, ManagerControlMessageContents::Resume);
+                        }
+                    }
+                    &ManagerControlMessageContents::ScenarioFinished(code, _) => {
+                        assert_eq!(code, 200);
+                        break;
+                    }
+                    _ => (),
+                }
+            }
+            UnitEvent::Shutdown => panic!("timed out waiting for scenario to finish"),
+            _ => (),
+        }
+    }
+    assert_eq!(tests_started, vec!["test1", "test2", "test3"]);
+}
+
+#[test]
+fn run_single_test() {
+    let exclave = Exclave::new(Some(Duration::from_secs(30)));
+    let dependent_name = UnitName::from_str("dependent", "test").unwrap();
+    let master_name = UnitName::from_str("master", "test").unwrap();
+    let unrelated_name = UnitName::from_str("unrelated", "test").unwrap();
+
+    exclave.add_unit(&dependent_name, &make_sleep_test("begin-dependent", None, "end-dependent", None));
+    exclave.add_unit(&unrelated_name, &make_sleep_test("begin-unrelated", None, "end-unrelated", None));
+    let mut master_test = make_sleep_test("begin-master", None, "end-master", None);
+    master_test.push_str("\nRequires=dependent");
+    exclave.add_unit(&master_name, &master_test);
+    exclave.rescan();
+
+    exclave.send_control(&master_name, ManagerControlMessageContents::RunTest(master_name.clone()));
+
+    let mut tests_started = vec![];
+    loop {
+        let msg = exclave.run_once().unwrap();
+        println!("Message: {:?}", msg);
+        match msg {
+            UnitEvent::ManagerRequest(ref mrq) => {
+                let ManagerControlMessage {
+                    sender: ref sender_name,
+                    contents: ref msg,
+                    ..
+                } = mrq;
+                match msg {
+                    &ManagerControlMessageContents::TestStarted => {
+                        tests_started.push(sender_name.id().clone());
+                    }
+                    &ManagerControlMessageContents::ScenarioFinished(code, _) => {
+                        assert_eq!(code, 200);
+                        // The temporary scenario can't be mistaken for a loaded one.
+                        assert_eq!(sender_name.id(), "run/master");
+                        break;
+                    }
+                    _ => (),
+                }
+            }
+            UnitEvent::Shutdown => panic!("timed out waiting for test run to finish"),
+            _ => (),
+        }
+    }
+    assert_eq!(tests_started, vec!["dependent", "master"]);
+}
+
+#[test]
+fn scenario_run_options() {
+    let exclave = Exclave::new(Some(Duration::from_secs(30)));
+    let three_name = UnitName::from_str("three", "scenario").unwrap();
+
+    for n in 1..=2 {
+        exclave.add_unit(
+            &UnitName::from_str(&format!("test{}", n), "test").unwrap(),
+            &make_sleep_test(&format!("test{}-start", n), None, &format!("test{}-stop", n), None),
+        );
+    }
+    let mut test3 = make_sleep_test("test3-start", None, "test3-stop", None);
+    test3.push_str("\nRequires=test1");
+    exclave.add_unit(&UnitName::from_str("test3", "test").unwrap(), &test3);
+    exclave.add_unit(&three_name, THREE_TEST_SCENARIO);
+    exclave.rescan();
+
+    // Assume the first test passed, and skip the second one.
+    let words = vec!["assume=test1".to_owned(), "skip=test2".to_owned()];
+    let options = RunOptions::from_words(&words).unwrap();
+    exclave.send_control(
+        &three_name,
+        ManagerControlMessageContents::StartScenario(Some(three_name.clone()), options),
+    );
+
+    let mut tests_started = vec![];
+    loop {
+        let msg = exclave.run_once().unwrap();
+        println!("Message: {:?}", msg);
+        match msg {
+            UnitEvent::ManagerRequest(ref mrq) => {
+                let ManagerControlMessage {
+                    sender: ref sender_name,
+                    contents: ref msg,
+                    ..
+                } = mrq;
+                match msg {
+                    &ManagerControlMessageContents::TestStarted => {
+                        tests_started.push(sender_name.id().clone());
+                    }
+                    &ManagerControlMessageContents::ScenarioFinished(_, _) => break,
+                    _ => (),
+                }
+            }
+            UnitEvent::Shutdown => panic!("timed out waiting for scenario to finish"),
+            _ => (),
+        }
+    }
+    assert_eq!(tests_started, vec!["test3"]);
+
+    // The options only applied to that run, and don't stay on the scenario.
+    let scenario = exclave.library.get_manager().borrow().get_scenario_named(&three_name).unwrap();
+    assert!(scenario.borrow().run_options().is_empty());
+    assert_eq!(scenario.borrow().test_sequence().len(), 3);
+}
+
+#[cfg(unix)]
+#[test]
+fn scenario_parameters() {
+    let exclave = Exclave::new(Some(Duration::from_secs(30)));
+    let scenario_name = UnitName::from_str("serialized", "scenario").unwrap();
+    let test_name = UnitName::from_str("printserial", "test").unwrap();
+
+    exclave.add_unit(
+        &test_name,
+        r##"[Test]
+Name=Print serial
+Description=Print out the serial number and board revision
+ExecStart=/bin/sh -c "echo param-$EXCLAVE_PARAM_SERIAL-$EXCLAVE_PARAM_REVISION"
+"##,
+    );
+    exclave.add_unit(
+        &scenario_name,
+        r##"[Scenario]
+Name=Serialized Scenario
+Description=Run a test that needs a serial number
+Tests=printserial
+Parameters=serial, revision=b
+"##,
+    );
+    exclave.rescan();
+
+    // The value is passed through a command line, so make sure quoting survives.
+    let options = RunOptions::from_words(&vec!["serial=12'34 x".to_owned()]).unwrap();
+    exclave.send_control(
+        &scenario_name,
+        ManagerControlMessageContents::StartScenario(Some(scenario_name.clone()), options),
+    );
+
+    let mut output = None;
+    let mut finished = false;
+    while !finished || output.is_none() {
+        let msg = exclave.run_once().unwrap();
+        println!("Message: {:?}", msg);
+        match msg {
+            UnitEvent::ManagerRequest(ref mrq) => {
+                let ManagerControlMessage {
+                    sender: ref sender_name,
+                    contents: ref msg,
+                    ..
+                } = mrq;
+                match msg {
+                    &ManagerControlMessageContents::Output(_, ref string) => {
+                        if *sender_name == test_name && string.starts_with("param-") {
+                            output = Some(string.clone());
+                        }
+                    }
+                    &ManagerControlMessageContents::ScenarioFinished(code, _) => {
+                        assert_eq!(code, 200);
+                        finished = true;
+                    }
+                    _ => (),
+                }
+            }
+            UnitEvent::Shutdown => panic!("timed out waiting for scenario to finish"),
+            _ => (),
+        }
+    }
+    assert_eq!(output, Some("param-12'34 x-b".to_owned()));
+}
+
+#[cfg(unix)]
+#[test]
+fn scenario_values() {
+    let exclave = Exclave::new(Some(Duration::from_secs(30)));
+    let scenario_name = UnitName::from_str("passalong", "scenario").unwrap();
+    let reader_name = UnitName::from_str("readmac", "test").unwrap();
+
+    exclave.add_unit(
+        &UnitName::from_str("setmac", "test").unwrap(),
+        r##"[Test]
+Name=Set MAC
+Description=Store a MAC address for later tests
+ExecStart=/bin/sh -c "echo 'EXCLAVE-SET mac 00:11:22:33:44:55'"
+"##,
+    );
+    exclave.add_unit(
+        &reader_name,
+        r##"[Test]
+Name=Read MAC
+Description=Print the MAC address stored by an earlier test
+ExecStart=/bin/sh -c "echo mac-$EXCLAVE_VALUE_MAC"
+"##,
+    );
+    exclave.add_unit(
+        &scenario_name,
+        r##"[Scenario]
+Name=Pass Along
+Description=Pass a value from one test to the next
+Tests=setmac, readmac
+"##,
+    );
+    exclave.rescan();
+
+    exclave.start_scenario(&scenario_name);
+
+    let mut output = None;
+    let mut values = None;
+    loop {
+        let msg = exclave.run_once().unwrap();
+        println!("Message: {:?}", msg);
+        match msg {
+            UnitEvent::ManagerRequest(ref mrq) => {
+                let ManagerControlMessage {
+                    sender: ref sender_name,
+                    contents: ref msg,
+                    ..
+                } = mrq;
+                match msg {
+                    &ManagerControlMessageContents::Output(_, ref string) => {
+                        if *sender_name == reader_name && string.starts_with("mac-") {
+                            output = Some(string.clone());
+                        }
+                    }
+                    &ManagerControlMessageContents::Values(ref v) => values = Some(v.clone()),
+                    &ManagerControlMessageContents::ScenarioFinished(code, _) => {
+                        assert_eq!(code, 200);
+                        break;
+                    }
+                    _ => (),
+                }
+            }
+            UnitEvent::Shutdown => panic!("timed out waiting for scenario to finish"),
+            _ => (),
+        }
+    }
+    assert_eq!(output, Some("mac-00:11:22:33:44:55".to_owned()));
+    let values = values.expect("scenario didn't report its values");
+    assert_eq!(values.get("mac"), Some(&"00:11:22:33:44:55".to_owned()));
+}
+
+#[test]
+fn manual_test() {
+    let exclave = Exclave::new(Some(Duration::from_secs(30)));
+    let scenario_name = UnitName::from_str("visual", "scenario").unwrap();
+    let led_name = UnitName::from_str("led", "test").unwrap();
+    let buzzer_name = UnitName::from_str("buzzer", "test").unwrap();
+
+    exclave.add_unit(
+        &led_name,
+        r##"[Test]
+Name=LED check
+Description=Ask the operator whether the LED is green
+Type=manual
+Prompt=Is the LED green?
+Responses=yes, no
+"##,
+    );
+    exclave.add_unit(
+        &buzzer_name,
+        r##"[Test]
+Name=Buzzer check
+Description=Nobody answers this one, so it times out
+Type=manual
+Prompt=Did the buzzer sound?
+Responses=yes, no
+Timeout=1
+"##,
+    );
+    exclave.add_unit(
+        &scenario_name,
+        r##"[Scenario]
+Name=Visual Checks
+Description=Checks that need an operator
+Tests=led, buzzer
+"##,
+    );
+    exclave.rescan();
+
+    exclave.start_scenario(&scenario_name);
+
+    let mut results = vec![];
+    loop {
+        let msg = exclave.run_once().unwrap();
+        println!("Message: {:?}", msg);
+        match msg {
+            UnitEvent::ManagerRequest(ref mrq) => {
+                let ManagerControlMessage {
+                    sender: ref sender_name,
+                    contents: ref msg,
+                    ..
+                } = mrq;
+                match msg {
+                    &ManagerControlMessageContents::Prompt(_, ref responses) => {
+                        assert_eq!(*responses, vec!["yes".to_owned(), "no".to_owned()]);
+                        if *sender_name == led_name {
+                            exclave.send_control(
+                                &scenario_name,
+                                ManagerControlMessageContents::Answer(None, "yes".to_owned()),
+                            );
+                        }
+                    }
+                    &ManagerControlMessageContents::TestFinished(result, _) => {
+                        results.push((sender_name.id().clone(), result));
+                    }
+                    &ManagerControlMessageContents::ScenarioFinished(code, _) => {
+                        assert_eq!(code, 501);
+                        break;
+                    }
+                    _ => (),
+                }
+            }
+            UnitEvent::Shutdown => panic!("timed out waiting for scenario to finish"),
+            _ => (),
+        }
+    }
+    assert_eq!(results, vec![("led".to_owned(), 0), ("buzzer".to_owned(), -1)]);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_prompt() {
+    let exclave = Exclave::new(Some(Duration::from_secs(30)));
+    let scenario_name = UnitName::from_str("insertcard", "scenario").unwrap();
+    let test_name = UnitName::from_str("sdcard", "test").unwrap();
+
+    exclave.add_unit(
+        &test_name,
+        r##"[Test]
+Name=SD card
+Description=Ask the operator to insert an SD card, then report their answer
+ExecStart=/bin/sh -c "echo 'EXCLAVE-PROMPT [ok,cancel] Insert an SD card'; read answer; echo got-$answer"
+Timeout=10
+"##,
+    );
+    exclave.add_unit(
+        &scenario_name,
+        r##"[Scenario]
+Name=Insert Card
+Description=Run a test that prompts the operator
+Tests=sdcard
+"##,
+    );
+    exclave.rescan();
+
+    exclave.start_scenario(&scenario_name);
+
+    let mut output = None;
+    let mut finished = false;
+    while !finished || output.is_none() {
+        let msg = exclave.run_once().unwrap();
+        println!("Message: {:?}", msg);
+        match msg {
+            UnitEvent::ManagerRequest(ref mrq) => {
+                let ManagerControlMessage {
+                    sender: ref sender_name,
+                    contents: ref msg,
+                    ..
+                } = mrq;
+                match msg {
+                    &ManagerControlMessageContents::Prompt(ref prompt, ref responses) => {
+                        assert_eq!(prompt, "Insert an SD card");
+                        assert_eq!(*responses, vec!["ok".to_owned(), "cancel".to_owned()]);
+                        exclave.send_control(
+                            &scenario_name,
+                            ManagerControlMessageContents::Answer(Some(test_name.clone()), "ok".to_owned()),
+                        );
+                    }
+                    &ManagerControlMessageContents::Output(_, ref string) => {
+                        if *sender_name == test_name && string.starts_with("got-") {
+                            output = Some(string.clone());
+                        }
+                    }
+                    &ManagerControlMessageContents::ScenarioFinished(code, _) => {
+                        assert_eq!(code, 200);
+                        finished = true;
+                    }
+                    _ => (),
+                }
+            }
+            UnitEvent::Shutdown => panic!("timed out waiting for scenario to finish"),
+            _ => (),
+        }
+    }
+    assert_eq!(output, Some("got-ok".to_owned()));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_progress() {
+    let exclave = Exclave::new(Some(Duration::from_secs(30)));
+    let scenario_name = UnitName::from_str("flash", "scenario").unwrap();
+    let test_name = UnitName::from_str("program", "test").unwrap();
+
+    exclave.add_unit(
+        &test_name,
+        r##"[Test]
+Name=Program flash
+Description=Pretend to program the flash, reporting progress along the way
+ExecStart=/bin/sh -c "echo 'EXCLAVE-PROGRESS 42 flashing bank 2'; echo 'EXCLAVE-PROGRESS 100% done'"
+"##,
+    );
+    exclave.add_unit(
+        &scenario_name,
+        r##"[Scenario]
+Name=Flash
+Description=Run a test that reports progress
+Tests=program
+"##,
+    );
+    exclave.rescan();
+
+    exclave.start_scenario(&scenario_name);
+
+    let mut progress = vec![];
+    let mut finished = false;
+    while !finished || progress.len() < 2 {
+        let msg = exclave.run_once().unwrap();
+        println!("Message: {:?}", msg);
+        match msg {
+            UnitEvent::ManagerRequest(ref mrq) => {
+                let ManagerControlMessage {
+                    contents: ref msg,
+                    ..
+                } = mrq;
+                match msg {
+                    &ManagerControlMessageContents::Progress(percent, ref status) => {
+                        progress.push((percent, status.clone()));
+                    }
+                    &ManagerControlMessageContents::ScenarioFinished(code, _) => {
+                        assert_eq!(code, 200);
+                        finished = true;
+                    }
+                    _ => (),
+                }
+            }
+            UnitEvent::Shutdown => panic!("timed out waiting for scenario to finish"),
+            _ => (),
+        }
+    }
+    assert_eq!(progress, vec![(42, "flashing bank 2".to_owned()), (100, "done".to_owned())]);
+}
+
+#[cfg(unix)]
+#[test]
+fn scenario_eta() {
+    use std::fs;
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixStream;
+    use std::process;
+    use std::sync::mpsc::channel;
+    use artifacts::ArtifactDir;
+
+    let exclave = Exclave::new(Some(Duration::from_secs(30)));
+    let three_name = UnitName::from_str("three", "scenario").unwrap();
+    let interface_name = UnitName::from_str("eta", "interface").unwrap();
+    let path = ::std::env::temp_dir().join(format!("exclave-eta-{}.sock", process::id()));
+    let artifact_root = ::std::env::temp_dir().join(format!("exclave-eta-{}", process::id()));
+    fs::remove_dir_all(&artifact_root).ok();
+
+    // Two earlier runs, of which only the most recent pass of each test counts.
+    let test_names: Vec<UnitName> = (1..=3)
+        .map(|n| UnitName::from_str(&format!("test{}", n), "test").unwrap())
+        .collect();
+    let dir = ArtifactDir::create(&artifact_root, &three_name, None, 1).unwrap();
+    dir.record_result(&test_names[0], "pass", "done", Some(Duration::from_secs(99))).unwrap();
+    dir.record_result(&three_name, "fail 500", "1 test failed", None).unwrap();
+    let dir = ArtifactDir::create(&artifact_root, &three_name, None, 2).unwrap();
+    for (n, test_name) in test_names.iter().enumerate() {
+        let seconds = 10 * (n as u64 + 1);
+        dir.record_result(test_name, "pass", "done", Some(Duration::from_secs(seconds))).unwrap();
+    }
+    dir.record_result(&three_name, "pass", "all tests passed", None).unwrap();
+
+    exclave.set_artifact_root(&artifact_root, 0);
+    loop {
+        match exclave.run_once().unwrap() {
+            UnitEvent::ManagerRequest(ManagerControlMessage {
+                contents: ManagerControlMessageContents::TestDurations(_),
+                ..
+            }) => break,
+            UnitEvent::Shutdown => panic!("timed out waiting for saved durations"),
+            _ => (),
+        }
+    }
+
+    for (n, test_name) in test_names.iter().enumerate() {
+        exclave.add_unit(
+            test_name,
+            &make_sleep_test(&format!("test{}-start", n + 1), None, &format!("test{}-end", n + 1), None),
+        );
+    }
+    exclave.add_unit(&three_name, THREE_TEST_SCENARIO);
+    exclave.add_unit(
+        &interface_name,
+        &format!("[Interface]\nName=ETA watcher\nType=socket\nListen={}\n", path.display()),
+    );
+    exclave.rescan();
+    exclave.activate(&interface_name);
+    loop {
+        match exclave.run_once().unwrap() {
+            UnitEvent::ManagerRequest(ManagerControlMessage {
+                contents: ManagerControlMessageContents::InitialGreeting,
+                ..
+            }) => break,
+            UnitEvent::Shutdown => panic!("timed out waiting for interface to start"),
+            _ => (),
+        }
+    }
+
+    // Once STATUS has been answered, the connection gets every status message.
+    let mut stream = UnixStream::connect(&path).unwrap();
+    writeln!(stream, "STATUS").unwrap();
+    let mut lines = BufReader::new(stream).lines();
+    assert!(lines.next().unwrap().unwrap().starts_with("STATUS "));
+    let (eta_sender, eta_receiver) = channel();
+    thread::spawn(move || {
+        for line in lines {
+            if let Some(seconds) = line.unwrap().trim().strip_prefix("ETA three ") {
+                eta_sender.send(seconds.parse::<u64>().unwrap()).ok();
+            }
+        }
+    });
+
+    exclave.start_scenario(&three_name);
+    loop {
+        match exclave.run_once().unwrap() {
+            UnitEvent::ManagerRequest(ref mrq) => {
+                if let ManagerControlMessageContents::ScenarioFinished(code, _) = mrq.contents {
+                    assert_eq!(code, 200);
+                    break;
+                }
+            }
+            UnitEvent::Shutdown => panic!("timed out waiting for scenario to finish"),
+            _ => (),
+        }
+    }
+
+    // One estimate when the scenario starts, and one as each test starts.  Each
+    // test finishes almost at once, which replaces its saved duration.
+    let estimates: Vec<u64> = (0..4)
+        .map(|_| eta_receiver.recv_timeout(Duration::from_secs(5)).unwrap())
+        .collect();
+    assert_eq!(estimates, vec![60, 60, 50, 30]);
+
+    exclave.deactivate(&interface_name);
+    fs::remove_dir_all(&artifact_root).ok();
+}
+
+#[cfg(unix)]
+#[test]
+fn interface_restart() {
+    let exclave = Exclave::new(Some(Duration::from_secs(30)));
+    let interface_name = UnitName::from_str("flaky", "interface").unwrap();
+
+    exclave.add_unit(
+        &interface_name,
+        r##"[Interface]
+Name=Flaky interface
+Description=An interface that crashes as soon as it starts
+ExecStart=/bin/sh -c "exit 1"
+Restart=on-failure
+RestartSec=10ms
+StartLimitBurst=2
+"##,
+    );
+    exclave.activate(&interface_name);
+
+    let mut restarts = 0;
+    loop {
+        let msg = exclave.run_once().unwrap();
+        println!("Message: {:?}", msg);
+        match msg {
+            UnitEvent::ManagerRequest(ManagerControlMessage {
+                contents: ManagerControlMessageContents::Restart,
+                ref sender,
+                ..
+            }) => {
+                assert_eq!(sender, &interface_name);
+                restarts += 1;
+            }
+            UnitEvent::Log(ref entry) => {
+                if entry.message().contains("not restarting again") {
+                    break;
+                }
+            }
+            UnitEvent::Shutdown => panic!("timed out waiting for interface to give up"),
+            _ => (),
+        }
+    }
+    assert_eq!(restarts, 2);
+}
+
+#[cfg(unix)]
+#[test]
+fn logger_spool() {
+    use std::fs;
+    use std::process;
+
+    let exclave = Exclave::new(Some(Duration::from_secs(30)));
+    let logger_name = UnitName::from_str("upload", "logger").unwrap();
+    let spool_path = ::std::env::temp_dir().join(format!("exclave-spool-{}", process::id()));
+    let output_path = ::std::env::temp_dir().join(format!("exclave-spool-out-{}", process::id()));
+    fs::remove_file(&spool_path).ok();
+    fs::remove_file(&output_path).ok();
+
+    exclave.add_unit(
+        &logger_name,
+        &format!(
+            r##"[Logger]
+Name=Upload logger
+Description=Save log entries, but only once it has been started
+ExecStart=/bin/sh -c "cat > {}"
+QueueSize=2
+SpoolFile={}
+"##,
+            output_path.display(),
+            spool_path.display()
+        ),
+    );
+
+    // Log some entries while the logger isn't running.
+    for n in 1..6 {
+        exclave.send_control(
+            &logger_name,
+            ManagerControlMessageContents::LogError(format!("spool entry {}", n)),
+        );
+    }
+    let mut logged = 0;
+    while logged < 5 {
+        match exclave.run_once().unwrap() {
+            UnitEvent::Log(ref entry) if entry.message().starts_with("spool entry") => logged += 1,
+            UnitEvent::Shutdown => panic!("timed out waiting for log entries"),
+            _ => (),
+        }
+    }
+    assert_eq!(fs::read_to_string(&spool_path).unwrap().lines().count(), 3);
+
+    // Once the logger starts, it should get everything, in order.
+    exclave.activate(&logger_name);
+    let mut received = vec![];
+    for _ in 0..100 {
+        received = fs::read_to_string(&output_path)
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|l| l.split('\t').nth(5))
+            .filter(|m| m.starts_with("spool entry"))
+            .map(|m| m.to_owned())
+            .collect();
+        if received.len() >= 5 {
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    exclave.deactivate(&logger_name);
+    fs::remove_file(&spool_path).ok();
+    fs::remove_file(&output_path).ok();
+
+    let expected: Vec<String> = (1..6).map(|n| format!("spool entry {}", n)).collect();
+    assert_eq!(received, expected);
+}
+
+#[cfg(unix)]
+#[test]
+fn logger_spool_compaction() {
+    use std::fs;
+    use std::process;
+
+    let exclave = Exclave::new(Some(Duration::from_secs(30)));
+    let logger_name = UnitName::from_str("stopped", "logger").unwrap();
+    let spool_path = ::std::env::temp_dir().join(format!("exclave-compact-{}", process::id()));
+    fs::remove_file(&spool_path).ok();
+
+    exclave.add_unit(
+        &logger_name,
+        &format!(
+            r##"[Logger]
+Name=Stopped logger
+Description=A logger that never gets started, so everything stays in the spool
+ExecStart=/bin/cat
+QueueSize=2
+SpoolFile={}
+SpoolSize=10
+"##,
+            spool_path.display()
+        ),
+    );
+
+    // Log far more than fits, so old entries keep getting dropped.
+    for n in 0..200 {
+        exclave.send_control(
+            &logger_name,
+            ManagerControlMessageContents::LogError(format!("compact entry {}", n)),
+        );
+    }
+    let mut logged = 0;
+    while logged < 200 {
+        match exclave.run_once().unwrap() {
+            UnitEvent::Log(ref entry) if entry.message().starts_with("compact entry") => logged += 1,
+            UnitEvent::Shutdown => panic!("timed out waiting for log entries"),
+            _ => (),
+        }
+    }
+    let spooled = fs::read_to_string(&spool_path).unwrap();
+    fs::remove_file(&spool_path).ok();
+
+    assert!(spooled.lines().count() <= 10);
+    assert!(spooled.lines().last().unwrap().contains("compact entry 199"));
+}
+
+#[test]
+fn logger_file_rotation() {
+    use std::fs;
+    use std::process;
+
+    let exclave = Exclave::new(Some(Duration::from_secs(30)));
+    let logger_name = UnitName::from_str("rotating", "logger").unwrap();
+    let log_dir = ::std::env::temp_dir().join(format!("exclave-logfile-{}", process::id()));
+    let log_path = log_dir.join("factory.csv");
+    fs::remove_dir_all(&log_dir).ok();
+
+    exclave.add_unit(
+        &logger_name,
+        &format!(
+            r##"[Logger]
+Name=Rotating logger
+Description=Write log entries straight to a file, keeping two old ones
+Type=file
+Path={}
+Format=csv
+Filter=message:^file\sentry
+RotateSize=200
+Retain=2
+"##,
+            log_path.display()
+        ),
+    );
+    exclave.activate(&logger_name);
+
+    for n in 1..7 {
+        exclave.send_control(
+            &logger_name,
+            ManagerControlMessageContents::Log(format!("file entry {}", n)),
+        );
+    }
+    let mut logged = 0;
+    while logged < 6 {
+        match exclave.run_once().unwrap() {
+            UnitEvent::Log(ref entry) if entry.message().starts_with("file entry") => logged += 1,
+            UnitEvent::Shutdown => panic!("timed out waiting for log entries"),
+            _ => (),
+        }
+    }
+    let mut found = false;
+    for _ in 0..100 {
+        if fs::read_to_string(&log_path).unwrap_or_default().contains("file entry 6") {
+            found = true;
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    exclave.deactivate(&logger_name);
+
+    let mut rotated = vec![];
+    for suffix in &["", ".1", ".2", ".3"] {
+        let mut path = log_path.clone().into_os_string();
+        path.push(suffix);
+        rotated.push(fs::read_to_string(&path).ok());
+    }
+    fs::remove_dir_all(&log_dir).ok();
+
+    assert!(found, "last entry was never written");
+    assert!(rotated[3].is_none(), "too many rotated files were kept");
+    for contents in &rotated[0..3] {
+        let contents = contents.as_ref().expect("rotated file is missing");
+        assert!(contents.starts_with("type,unit,unit_type,"));
+        assert!(contents.len() <= 200);
+    }
+    assert!(rotated[1].as_ref().unwrap().contains("file entry 5"));
+}
+
+#[cfg(unix)]
+#[test]
+fn logger_events() {
+    use std::fs;
+    use std::process;
+
+    let exclave = Exclave::new(Some(Duration::from_secs(30)));
+    let scenario_name = UnitName::from_str("judge", "scenario").unwrap();
+    let logger_name = UnitName::from_str("results", "logger").unwrap();
+    let output_path = ::std::env::temp_dir().join(format!("exclave-events-{}", process::id()));
+    fs::remove_file(&output_path).ok();
+
+    exclave.add_unit(
+        &logger_name,
+        &format!(
+            r##"[Logger]
+Name=Results logger
+Description=Save results and status changes, but no log entries
+ExecStart=/bin/sh -c "cat > {}"
+Events=results, status
+Filter=!kind:test,scenario,logger
+"##,
+            output_path.display()
+        ),
+    );
+    exclave.activate(&logger_name);
+
+    exclave.add_unit(
+        &UnitName::from_str("good", "test").unwrap(),
+        r##"[Test]
+Name=Good
+Description=Pass
+ExecStart=/bin/sh -c "echo fine"
+"##,
+    );
+    exclave.add_unit(
+        &UnitName::from_str("bad", "test").unwrap(),
+        r##"[Test]
+Name=Bad
+Description=Fail
+ExecStart=/bin/sh -c "echo broken; exit 3"
+"##,
+    );
+    exclave.add_unit(
+        &scenario_name,
+        r##"[Scenario]
+Name=Judge
+Description=One test passes, and one fails
+Tests=good, bad
+"##,
+    );
+    exclave.rescan();
+    exclave.start_scenario(&scenario_name);
+    loop {
+        match exclave.run_once().unwrap() {
+            UnitEvent::ManagerRequest(ManagerControlMessage {
+                contents: ManagerControlMessageContents::ScenarioFinished(_, _),
+                ..
+            }) => break,
+            UnitEvent::Shutdown => panic!("timed out waiting for scenario to finish"),
+            _ => (),
+        }
+    }
+
+    let mut text = String::new();
+    for _ in 0..100 {
+        text = fs::read_to_string(&output_path).unwrap_or_default();
+        if text.contains("finish\t") {
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    exclave.deactivate(&logger_name);
+    fs::remove_file(&output_path).ok();
+
+    let fields: Vec<Vec<&str>> = text.lines().map(|l| l.split('\t').collect()).collect();
+    let find = |event: &str, unit: &str| {
+        fields
+            .iter()
+            .find(|f| f[0] == event && f[1] == unit)
+            .expect(&format!("no {} event for {}", event, unit))
+            .clone()
+    };
+    assert_eq!(find("start", "judge")[6..], ["event", "judge", "", "1", ""]);
+    assert_eq!(find("running", "good")[2], "test");
+    assert_eq!(find("pass", "good")[5..], ["fine", "event", "judge", "", "1", "0"]);
+    assert_eq!(find("fail", "bad")[5..], ["broken", "event", "judge", "", "1", "3"]);
+    assert_eq!(find("finish", "judge")[7..], ["judge", "", "1", "501"]);
+    assert_eq!(find("status", "good")[5], "loaded");
+
+    // Only events were asked for, so no log entries should have been written.
+    assert!(fields.iter().all(|f| f[6] == "event"));
+}
+
+#[test]
+fn artifact_dir() {
+    use std::fs;
+    use std::process;
+
+    let exclave = Exclave::new(Some(Duration::from_secs(30)));
+    let scenario_name = UnitName::from_str("rma", "scenario").unwrap();
+    let artifact_root = ::std::env::temp_dir().join(format!("exclave-artifacts-{}", process::id()));
+    fs::remove_dir_all(&artifact_root).ok();
+    exclave.set_artifact_root(&artifact_root, 1);
+
+    exclave.add_unit(
+        &UnitName::from_str("capture", "test").unwrap(),
+        r##"[Test]
+Name=Capture
+Description=Print something, and save a file of its own
+ExecStart=/bin/sh -c "echo captured-output; echo dump > $EXCLAVE_ARTIFACT_DIR/dump.txt"
+"##,
+    );
+    exclave.add_unit(
+        &scenario_name,
+        r##"[Scenario]
+Name=RMA
+Description=Keep everything from each run
+Tests=capture
+"##,
+    );
+    exclave.rescan();
+
+    for _ in 0..2 {
+        exclave.start_scenario(&scenario_name);
+        loop {
+            match exclave.run_once().unwrap() {
+                UnitEvent::ManagerRequest(ManagerControlMessage {
+                    contents: ManagerControlMessageContents::ScenarioFinished(code, _),
+                    ..
+                }) => {
+                    assert_eq!(code, 200);
+                    break;
+                }
+                UnitEvent::Shutdown => panic!("timed out waiting for scenario to finish"),
+                _ => (),
+            }
+        }
+    }
+
+    // Only the most recent run should have been kept.
+    let runs: Vec<PathBuf> = fs::read_dir(&artifact_root)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .collect();
+    let read = |name: &str| fs::read_to_string(runs[0].join(name)).unwrap_or_default();
+    let stdout = read("capture.test.stdout");
+    let dump = read("dump.txt");
+    let results = read("results.tsv");
+    let log = read("exclave.log");
+    fs::remove_dir_all(&artifact_root).ok();
+
+    assert_eq!(runs.len(), 1);
+    assert!(runs[0].to_string_lossy().contains("-run2-rma"));
+    assert_eq!(stdout, "captured-output\n");
+    assert_eq!(dump, "dump\n");
+    assert!(results.contains("capture.test\tpass\t"));
+    assert!(results.contains("rma.scenario\tpass\tall tests passed"));
+    assert!(log.contains("captured-output"));
+}
+
+#[test]
+fn artifact_prune() {
+    use std::fs;
+    use std::process;
+    use artifacts::ArtifactDir;
+
+    let root = ::std::env::temp_dir().join(format!("exclave-prune-{}", process::id()));
+    fs::remove_dir_all(&root).ok();
+    let scenario = UnitName::from_str("burn-in", "scenario").unwrap();
+
+    // The oldest run is still going on another slot, so only the middle one goes.
+    let dirs: Vec<ArtifactDir> = (1..=3)
+        .map(|run| ArtifactDir::create(&root, &scenario, Some(run as u32), run).unwrap())
+        .collect();
+    ArtifactDir::prune(&root, 1, &[dirs[0].path()]).unwrap();
+    let runs = ArtifactDir::list(&root).unwrap();
+    fs::remove_dir_all(&root).ok();
+
+    assert_eq!(runs, vec![dirs[0].path().to_owned(), dirs[2].path().to_owned()]);
+}
+
+#[test]
+fn log_filter() {
+    use logfilter::LogFilter;
+    use unitbroadcaster::{LogEntry, LogSource, LogType};
+
+    let daemon = UnitName::from_str("serial-daemon", "test").unwrap();
+    let flash = UnitName::from_str("flash", "test").unwrap();
+    let scenario = UnitName::from_str("factory", "scenario").unwrap();
+
+    let everything = LogFilter::from_str("Logger", "Filter", "").unwrap();
+    assert!(everything.matches(&LogEntry::new_info(daemon.clone(), "chatter".to_owned())));
+
+    let quiet = LogFilter::from_str("Interface", "LogFilter", "!unit:*-daemon").unwrap();
+    assert!(!quiet.matches(&LogEntry::new_info(daemon.clone(), "chatter".to_owned())));
+    assert!(quiet.matches(&LogEntry::new_info(flash.clone(), "programming".to_owned())));
+
+    let uploader = LogFilter::from_str("Logger", "Filter", "kind:scenario,test type:error").unwrap();
+    assert!(uploader.matches(&LogEntry::new_error(flash.clone(), "failed".to_owned())));
+    assert!(uploader.matches(&LogEntry::new_error(scenario.clone(), "failed".to_owned())));
+    assert!(!uploader.matches(&LogEntry::new_info(flash.clone(), "programming".to_owned())));
+
+    let results = LogFilter::from_str("Logger", "Filter", "message:^(PASS|FAIL)\\b").unwrap();
+    assert!(results.matches(&LogEntry::new_info(scenario.clone(), "PASS factory".to_owned())));
+    assert!(!results.matches(&LogEntry::new_info(scenario.clone(), "starting".to_owned())));
+
+    let output = LogFilter::from_str("Logger", "Filter", "source:stdout,stderr").unwrap();
+    assert!(output.matches(&LogEntry::new(flash.clone(), LogType::Info, LogSource::Stdout, "ok".to_owned())));
+    assert!(!output.matches(&LogEntry::new_status(scenario.clone(), "scenario starting".to_owned())));
+    assert!(LogType::Debug < LogType::Info && LogType::Warn < LogType::Error);
+
+    assert!(LogFilter::from_str("Logger", "Filter", "color:red").is_err());
+    assert!(LogFilter::from_str("Logger", "Filter", "message:(").is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn logger_formats() {
+    use std::fs;
+    use std::process;
+
+    let exclave = Exclave::new(Some(Duration::from_secs(30)));
+    let scenario_name = UnitName::from_str("greet", "scenario").unwrap();
+    let test_name = UnitName::from_str("hello", "test").unwrap();
+
+    let mut outputs = vec![];
+    for format in &["csv", "logfmt", "syslog"] {
+        let logger_name = UnitName::from_str(&format!("{}-log", format), "logger").unwrap();
+        let output_path = ::std::env::temp_dir().join(format!("exclave-{}-{}", format, process::id()));
+        fs::remove_file(&output_path).ok();
+        exclave.add_unit(
+            &logger_name,
+            &format!(
+                r##"[Logger]
+Name={} logger
+Description=Save log entries as {}
+ExecStart=/bin/sh -c "cat > {}"
+Format={}
+StructuredDataID=exclave@99999.1
+"##,
+                format,
+                format,
+                output_path.display(),
+                format
+            ),
+        );
+        exclave.activate(&logger_name);
+        outputs.push((logger_name, output_path));
+    }
+
+    exclave.add_unit(
+        &test_name,
+        r##"[Test]
+Name=Hello
+Description=Say hello
+ExecStart=/bin/sh -c "echo hello-context"
+"##,
+    );
+    exclave.add_unit(
+        &scenario_name,
+        r##"[Scenario]
+Name=Greet
+Description=Run a test whose output gets logged
+Tests=hello
+"##,
+    );
+    exclave.rescan();
+    exclave.start_scenario(&scenario_name);
+    loop {
+        match exclave.run_once().unwrap() {
+            UnitEvent::ManagerRequest(ManagerControlMessage {
+                contents: ManagerControlMessageContents::ScenarioFinished(code, _),
+                ..
+            }) => {
+                assert_eq!(code, 200);
+                break;
+            }
+            UnitEvent::Shutdown => panic!("timed out waiting for scenario to finish"),
+            _ => (),
+        }
+    }
+
+    let mut contents = vec![];
+    for &(ref logger_name, ref output_path) in &outputs {
+        let mut text = String::new();
+        for _ in 0..100 {
+            text = fs::read_to_string(output_path).unwrap_or_default();
+            if text.contains("hello-context") {
+                break;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        exclave.deactivate(logger_name);
+        fs::remove_file(output_path).ok();
+        contents.push(text);
+    }
+
+    let csv = &contents[0];
+    assert!(csv.starts_with("type,unit,unit_type,unix_time,unix_time_nsecs,message,source,scenario,slot,run,result\n"));
+    assert!(csv.lines().any(|l| l.starts_with("info,hello,test,") && l.ends_with(",hello-context,stdout,greet,,1,")));
+
+    let logfmt = &contents[1];
+    assert!(logfmt.lines().any(|l| l.starts_with("time=")
+        && l.ends_with(" level=info unit=hello unit_type=test source=stdout scenario=greet run=1 msg=hello-context")));
+
+    let syslog = &contents[2];
+    assert!(syslog.lines().any(|l| l.starts_with("<14>1 ")
+        && l.ends_with(" [exclave@99999.1 unit=\"hello\" unit_type=\"test\" source=\"stdout\" scenario=\"greet\" run=\"1\"] hello-context")));
+}
+
+#[test]
+fn scenario_abort() {
+    let exclave = Exclave::new(Some(Duration::from_secs(30)));
+    let three_name = UnitName::from_str("three", "scenario").unwrap();
+
+    for n in 1..=3 {
+        exclave.add_unit(
+            &UnitName::from_str(&format!("test{}", n), "test").unwrap(),
+            &make_sleep_test(
+                &format!("test{}-start", n),
+                if n == 2 { Some(20.0) } else { None },
+                &format!("test{}-stop", n),
+                None,
+            ),
+        );
+    }
+    exclave.add_unit(&three_name, THREE_TEST_SCENARIO);
+    exclave.rescan();
+
+    exclave.start_scenario(&three_name);
+
+    // Abort while the second test is still running.  The third test should
+    // never be started.
+    let mut tests_started = vec![];
+    loop {
+        let msg = exclave.run_once().unwrap();
+        println!("Message: {:?}", msg);
+        match msg {
+            UnitEvent::ManagerRequest(ref mrq) => {
+                let ManagerControlMessage {
+                    sender: ref sender_name,
+                    contents: ref msg,
+                    ..
+                } = mrq;
+                match msg {
+                    &ManagerControlMessageContents::TestStarted => {
+                        tests_started.push(sender_name.id().clone());
+                        if tests_started.len() == 2 {
+                            exclave.send_control(&three_name, ManagerControlMessageContents::Abort);
+                        }
+                    }
+                    &ManagerControlMessageContents::ScenarioFinished(code, ref reason) => {
+                        assert!(code > 500);
+                        assert_eq!(reason, "scenario aborted");
+                        break;
+                    }
+                    _ => (),
+                }
+            }
+            UnitEvent::Shutdown => panic!("timed out waiting for scenario to abort"),
+            _ => (),
+        }
+    }
+    assert_eq!(tests_started, vec!["test1", "test2"]);
+}
+
+/// Make a request of the built-in HTTP interface, returning the status line and body.
+fn http_request(address: &str, method: &str, path: &str, body: &str) -> (String, String) {
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    let mut stream = TcpStream::connect(address).unwrap();
+    write!(
+        stream,
+        "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        method,
+        path,
+        address,
+        body.len(),
+        body
+    ).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let mut parts = response.splitn(2, "\r\n\r\n");
+    let head = parts.next().unwrap().to_owned();
+    let body = parts.next().unwrap_or("").to_owned();
+    (head.lines().next().unwrap().to_owned(), body)
+}
+
+#[test]
+fn http_interface() {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+
+    assert_eq!(
+        ::httpserver::websocket_accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+        "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+    );
+
+    let exclave = Exclave::new(Some(Duration::from_secs(30)));
+    let three_name = UnitName::from_str("three", "scenario").unwrap();
+    let interface_name = UnitName::from_str("web", "interface").unwrap();
+
+    for n in 1..=3 {
+        exclave.add_unit(
+            &UnitName::from_str(&format!("test{}", n), "test").unwrap(),
+            &make_sleep_test(&format!("test{}-start", n), None, &format!("test{}-stop", n), None),
+        );
+    }
+    exclave.add_unit(&three_name, THREE_TEST_SCENARIO);
+
+    let address = {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("{}", listener.local_addr().unwrap())
+    };
+    exclave.add_unit(
+        &interface_name,
+        &format!("[Interface]\nName=Web interface\nType=http\nListen={}\n", address),
+    );
+    exclave.rescan();
+    exclave.activate(&interface_name);
+
+    // Wait for the greeting, along with the test lists it asks for.
+    let mut pending = 2;
+    while pending > 0 {
+        match exclave.run_once().unwrap() {
+            UnitEvent::ManagerRequest(ManagerControlMessage {
+                contents: ManagerControlMessageContents::InitialGreeting,
+                ..
+            })
+            | UnitEvent::ManagerRequest(ManagerControlMessage {
+                contents: ManagerControlMessageContents::Tests(_),
+                ..
+            }) => pending -= 1,
+            UnitEvent::Shutdown => panic!("timed out waiting for interface to start"),
+            _ => (),
+        }
+    }
+
+    let (status, body) = http_request(&address, "GET", "/api/scenarios", "");
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    assert_eq!(
+        body,
+        r#"[{"description":"Just run three tests","id":"three","name":"Simple Scenario"}]"#
+    );
+    let (status, body) = http_request(&address, "GET", "/api/scenarios/three/tests", "");
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    assert!(body.starts_with(r#"[{"description":"#));
+    assert!(body.contains(r#""id":"test3""#));
+    let (status, _) = http_request(&address, "GET", "/api/nothing", "");
+    assert_eq!(status, "HTTP/1.1 404 Not Found");
+
+    // Subscribe to events, then start the scenario over HTTP.
+    let mut events = TcpStream::connect(&address).unwrap();
+    write!(
+        events,
+        "GET /api/events HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n",
+        address
+    ).unwrap();
+    let mut events = BufReader::new(events);
+    let mut handshake = vec![];
+    loop {
+        let mut line = String::new();
+        events.read_line(&mut line).unwrap();
+        if line.trim().is_empty() {
+            break;
+        }
+        handshake.push(line.trim().to_owned());
+    }
+    assert_eq!(handshake[0], "HTTP/1.1 101 Switching Protocols");
+    assert!(handshake.contains(&"Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=".to_owned()));
+
+    let (status, _) = http_request(&address, "POST", "/api/start", r#"{"scenario": "three"}"#);
+    assert_eq!(status, "HTTP/1.1 202 Accepted");
+    exclave.wait_for_deactivate(&three_name).unwrap();
+    exclave.run_once().unwrap();
+
+    let (_, body) = http_request(&address, "GET", "/api/history", "");
+    assert!(body.contains(r#""result":200"#));
+    assert!(body.contains(r#""tests":{"test1":"pass","test2":"pass","test3":"pass"}"#));
+
+    // The stream starts with the current state, and then every status message.
+    let mut types = vec![];
+    while !types.contains(&"finish".to_owned()) {
+        let mut header = [0u8; 2];
+        events.read_exact(&mut header).unwrap();
+        assert_eq!(header[0], 0x81);
+        let len = match header[1] {
+            126 => {
+                let mut ext = [0u8; 2];
+                events.read_exact(&mut ext).unwrap();
+                (ext[0] as usize) << 8 | ext[1] as usize
+            }
+            l => l as usize,
+        };
+        let mut payload = vec![0u8; len];
+        events.read_exact(&mut payload).unwrap();
+        let object: ::serde_json::Value = ::serde_json::from_slice(&payload).unwrap();
+        types.push(object["type"].as_str().unwrap().to_owned());
+    }
+    assert_eq!(types[0], "state");
+    assert!(types.contains(&"start".to_owned()));
+    assert!(types.contains(&"pass".to_owned()));
+
+    exclave.deactivate(&interface_name);
+}
+
+#[cfg(unix)]
+#[test]
+fn socket_interface() {
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixStream;
+
+    let exclave = Exclave::new(Some(Duration::from_secs(30)));
+    let interface_name = UnitName::from_str("ctl", "interface").unwrap();
+    let path = ::std::env::temp_dir().join(format!("exclave-test-{}.sock", ::std::process::id()));
+
+    exclave.add_unit(
+        &interface_name,
+        &format!("[Interface]\nName=Control socket\nType=socket\nListen={}\n", path.display()),
+    );
+    exclave.activate(&interface_name);
+    loop {
+        match exclave.run_once().unwrap() {
+            UnitEvent::ManagerRequest(ManagerControlMessage {
+                contents: ManagerControlMessageContents::InitialGreeting,
+                ..
+            }) => break,
+            UnitEvent::Shutdown => panic!("timed out waiting for interface to start"),
+            _ => (),
+        }
+    }
+
+    let mut stream = UnixStream::connect(&path).unwrap();
+    writeln!(stream, r#"{{"verb": "status"}}"#).unwrap();
+    writeln!(stream, r#"{{"verb": "units"}}"#).unwrap();
+    writeln!(stream, r#"{{"verb": "bogus"}}"#).unwrap();
+    let mut replies = BufReader::new(stream).lines().map(|line| {
+        let object: ::serde_json::Value = ::serde_json::from_str(&line.unwrap()).unwrap();
+        object
+    });
+
+    // The socket answers these itself, in order.
+    assert_eq!(replies.next().unwrap()["type"], "state");
+    let accepted = replies.next().unwrap();
+    assert_eq!(accepted["type"], "accepted");
+    assert_eq!(accepted["verb"], "units");
+    let error = replies.next().unwrap();
+    assert_eq!(error["type"], "error");
+    assert_eq!(error["error"], "unrecognized command: bogus");
+
+    // The unit list comes from the manager.
+    loop {
+        match exclave.run_once().unwrap() {
+            UnitEvent::ManagerRequest(ManagerControlMessage {
+                contents: ManagerControlMessageContents::Units,
+                ..
+            }) => break,
+            UnitEvent::Shutdown => panic!("timed out waiting for units request"),
+            _ => (),
+        }
+    }
+    let units = replies.find(|x| x["type"] == "units").unwrap();
+    assert_eq!(units["units"][0]["unit"], "ctl");
+    assert_eq!(units["units"][0]["kind"], "interface");
+    assert_eq!(units["units"][0]["status"], "active");
+    assert_eq!(units["units"][0]["last_failure"], ::serde_json::Value::Null);
+
+    exclave.deactivate(&interface_name);
+    assert!(!path.exists());
+}
+
+#[test]
+fn metrics() {
+    use metrics::Metrics;
+    use unitbroadcaster::UnitStatusEvent;
+
+    let mut metrics = Metrics::new();
+    let jig = UnitName::from_str("generic", "jig").unwrap();
+    let scenario = UnitName::from_str("simple", "scenario").unwrap();
+    let test = UnitName::from_str("flash \"a\"", "test").unwrap();
+    let request = |name: &UnitName, contents| UnitEvent::ManagerRequest(ManagerControlMessage::new(name, contents));
+
+    metrics.process_message(&UnitEvent::Status(UnitStatusEvent::new_active(&jig)));
+    metrics.process_message(&request(&scenario, ManagerControlMessageContents::ScenarioStarted));
+    metrics.process_message(&request(&test, ManagerControlMessageContents::TestStarted));
+    metrics.process_message(&request(&test, ManagerControlMessageContents::TestFinished(1, "bad".to_owned())));
+    metrics.process_message(&request(&scenario, ManagerControlMessageContents::ScenarioFinished(501, "failed".to_owned())));
+    metrics.process_message(&request(&jig, ManagerControlMessageContents::Restart));
+
+    let text = metrics.render();
+    println!("{}", text);
+    assert!(text.contains("# TYPE exclave_scenarios_started_total counter\n"));
+    assert!(text.contains("exclave_scenarios_started_total{scenario=\"simple\"} 1\n"));
+    assert!(text.contains("exclave_scenarios_failed_total{scenario=\"simple\"} 1\n"));
+    assert!(!text.contains("exclave_scenarios_passed_total{"));
+    assert!(text.contains("exclave_test_failures_total{test=\"flash \\\"a\\\"\"} 1\n"));
+    assert!(text.contains("exclave_test_duration_seconds_bucket{test=\"flash \\\"a\\\"\",le=\"0.1\"} 1\n"));
+    assert!(text.contains("exclave_test_duration_seconds_count{test=\"flash \\\"a\\\"\"} 1\n"));
+    assert!(text.contains("exclave_unit_restarts_total{unit=\"generic\",kind=\"jig\"} 1\n"));
+    assert!(text.contains("exclave_active_jig{jig=\"generic\"} 1\n"));
+
+    metrics.process_message(&UnitEvent::Status(UnitStatusEvent::new_deactivate_success(&jig, "done".to_owned())));
+    assert!(!metrics.render().contains("exclave_active_jig{"));
+}
+
+#[test]
+fn yield_stats() {
+    use artifacts::ArtifactDir;
+    use std::fs;
+    use std::process;
+    use yieldstats::{StatsWindow, YieldStats};
+
+    let root = ::std::env::temp_dir().join(format!("exclave-stats-{}", process::id()));
+    fs::remove_dir_all(&root).ok();
+    let scenario = UnitName::from_str("rework", "scenario").unwrap();
+    let flash = UnitName::from_str("flash", "test").unwrap();
+    let boot = UnitName::from_str("boot", "test").unwrap();
+
+    // Three finished runs, plus one that is still going.
+    let results = [("fail 1", "timeout"), ("pass", "ok"), ("fail 1", "timeout")];
+    for (run, &(flash_result, flash_message)) in results.iter().enumerate() {
+        let dir = ArtifactDir::create(&root, &scenario, None, run as u64 + 1).unwrap();
+        dir.record_result(&flash, flash_result, flash_message, None).unwrap();
+        if flash_result == "pass" {
+            dir.record_result(&boot, "pass", "booted", None).unwrap();
+            dir.record_result(&scenario, "pass", "all tests passed", None).unwrap();
+        } else {
+            dir.record_result(&boot, "skip", "dependency failed", None).unwrap();
+            dir.record_result(&scenario, "fail 500", "1 test failed", None).unwrap();
+        }
+    }
+    let dir = ArtifactDir::create(&root, &scenario, None, 4).unwrap();
+    dir.record_result(&flash, "fail 2", "unfinished", None).unwrap();
+
+    // A run whose results can't be read is left out, rather than failing the rest.
+    let dir = ArtifactDir::create(&root, &scenario, None, 5).unwrap();
+    fs::create_dir(dir.path().join("results.tsv")).unwrap();
+
+    assert!(StatsWindow::from_str("soon").is_err());
+    let stats = YieldStats::load(&root, &StatsWindow::from_str("1h").unwrap()).unwrap();
+    assert_eq!(stats.runs, 3);
+    assert_eq!(stats.scenarios[0].scenario, "rework");
+    assert_eq!((stats.scenarios[0].passed, stats.scenarios[0].runs), (1, 3));
+    assert_eq!(stats.tests[0].test, "flash");
+    assert_eq!((stats.tests[0].failures, stats.tests[0].runs), (2, 3));
+    assert_eq!((stats.tests[1].failures, stats.tests[1].runs), (0, 1));
+    assert_eq!(stats.messages[0].message, "timeout");
+    assert_eq!(stats.messages[0].count, 2);
+    let report = stats.report();
+    println!("{}", report.join("\n"));
+    assert!(report.contains(&"  rework: 1 of 3 passed (33.3%)".to_owned()));
+
+    let stats = YieldStats::load(&root, &StatsWindow::from_str("last 1").unwrap()).unwrap();
+    assert_eq!(stats.runs, 1);
+    assert_eq!(stats.tests[0].failures, 1);
+
+    fs::remove_dir_all(&root).ok();
+}
+
+#[cfg(unix)]
+#[test]
+fn sd_notify() {
+    use sdnotify::Notifier;
+    use std::fs;
+    use std::os::unix::net::UnixDatagram;
+
+    let path = ::std::env::temp_dir().join(format!("exclave-notify-{}.sock", ::std::process::id()));
+    fs::remove_file(&path).ok();
+    let systemd = UnixDatagram::bind(&path).unwrap();
+    systemd.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let recv = || {
+        let mut buf = [0; 1024];
+        let len = systemd.recv(&mut buf).unwrap();
+        String::from_utf8_lossy(&buf[..len]).into_owned()
+    };
+
+    let mut notifier = Notifier::connect(path.as_os_str(), Some(Duration::from_millis(200))).unwrap();
+    assert_eq!(recv(), "STATUS=Loading units");
+    assert!(notifier.timeout().unwrap() <= Duration::from_millis(100));
+
+    // Only the first scan makes exclave ready.
+    notifier.process_message(&UnitEvent::RescanFinish);
+    assert_eq!(recv(), "READY=1\nSTATUS=Waiting for a scenario to start");
+    notifier.process_message(&UnitEvent::RescanFinish);
+
+    let scenario = UnitName::from_str("rework", "scenario").unwrap();
+    let test = UnitName::from_str("flash", "test").unwrap();
+    notifier.process_message(&UnitEvent::ManagerRequest(ManagerControlMessage::new(
+        &scenario,
+        ManagerControlMessageContents::ScenarioStarted,
+    )));
+    assert_eq!(recv(), "STATUS=running rework");
+    notifier.process_message(&UnitEvent::ManagerRequest(ManagerControlMessage::new(
+        &test,
+        ManagerControlMessageContents::TestStarted,
+    )));
+    assert_eq!(recv(), "STATUS=running rework: flash");
+    notifier.process_message(&UnitEvent::ManagerRequest(ManagerControlMessage::new(
+        &scenario,
+        ManagerControlMessageContents::ScenarioFinished(200, "all tests passed".to_owned()),
+    )));
+    assert_eq!(recv(), "STATUS=rework finished: all tests passed (200)");
+
+    // The watchdog isn't pinged until half its interval has passed.
+    notifier.keepalive();
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(notifier.timeout(), Some(Duration::from_secs(0)));
+    notifier.keepalive();
+    assert_eq!(recv(), "WATCHDOG=1");
+
+    notifier.process_message(&UnitEvent::Shutdown);
+    assert_eq!(recv(), "STOPPING=1");
+    fs::remove_file(&path).ok();
+}
+
+#[test]
+fn reload_units() {
+    use std::fs;
+    use std::process;
+    use unitbroadcaster::UnitStatusEvent;
+
+    let exclave = Exclave::new(Some(Duration::from_secs(30)));
+    let dir = ::std::env::temp_dir().join(format!("exclave-reload-{}", process::id()));
+    fs::remove_dir_all(&dir).ok();
+    fs::create_dir_all(&dir).unwrap();
+
+    // Load some tests, as the watcher and loader would at startup.
+    for name in &["same", "changed", "deleted"] {
+        let path = dir.join(format!("{}.test", name));
+        fs::write(&path, make_sleep_test(name, None, "done", None)).unwrap();
+        let name = UnitName::from_str(name, "test").unwrap();
+        exclave.library.process_message(&UnitEvent::Status(UnitStatusEvent::new_load_started(&name, &path)));
+    }
+    while exclave.receiver.try_recv().is_ok() {}
+
+    fs::write(dir.join("changed.test"), make_sleep_test("changed", None, "done", Some(1))).unwrap();
+    fs::remove_file(dir.join("deleted.test")).unwrap();
+    fs::write(dir.join("added.test"), make_sleep_test("added", None, "done", None)).unwrap();
+    exclave.library.reload(&[dir.clone()]);
+
+    let mut changes: Vec<String> = exclave
+        .receiver
+        .try_iter()
+        .filter_map(|msg| match msg {
+            UnitEvent::Status(evt) => match *evt.status() {
+                UnitStatus::Added(_) | UnitStatus::Updated(_) | UnitStatus::Removed(_) => {
+                    Some(format!("{} {}", evt.name(), evt.status().as_str()))
+                }
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+    changes.sort();
+    assert_eq!(changes, vec!["added.test added", "changed.test updated", "deleted.test removed"]);
+
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn file_logger_write_error() {
+    use unitbroadcaster::LogEntry;
+
+    let exclave = Exclave::new(Some(Duration::from_secs(30)));
+    let logger_name = UnitName::from_str("full", "logger").unwrap();
+
+    // Every write to /dev/full fails, as if the disk had filled up.
+    exclave.add_unit(
+        &logger_name,
+        r##"[Logger]
+Name=Full disk
+Description=A file logger that can't write anything
+Type=file
+Path=/dev/full
+Restart=on-failure
+RestartSec=10ms
+StartLimitBurst=1
+"##,
+    );
+    exclave.activate(&logger_name);
+    exclave
+        .broadcaster
+        .broadcast(&UnitEvent::Log(LogEntry::new_info(logger_name.clone(), "hello".to_owned())));
+
+    let mut write_errors = 0;
+    let mut restarts = 0;
+    loop {
+        let msg = exclave.run_once().unwrap();
+        println!("Message: {:?}", msg);
+        match msg {
+            UnitEvent::ManagerRequest(ManagerControlMessage {
+                contents: ManagerControlMessageContents::Restart,
+                ref sender,
+                ..
+            }) => {
+                assert_eq!(sender, &logger_name);
+                restarts += 1;
+            }
+            UnitEvent::Log(ref entry) => {
+                if entry.message().starts_with("unable to write to logger") {
+                    write_errors += 1;
+                }
+                if entry.message().contains("not restarting again") {
+                    break;
+                }
+            }
+            UnitEvent::Shutdown => panic!("timed out waiting for logger to give up"),
+            _ => (),
+        }
+    }
+    assert_eq!((write_errors, restarts), (2, 1));
+}
+
+#[test]
+fn answer_parsing() {
+    let words = |s: &str| s.split_whitespace().map(|w| w.to_owned()).collect::<Vec<String>>();
+
+    match Interface::parse_command("answer", words("Yes")) {
+        (ManagerControlMessageContents::Answer(None, ref response), None) => assert_eq!(response, "Yes"),
+        _ => panic!("single-word answer wasn't parsed"),
+    }
+    match Interface::parse_command("answer", words("Serial@2 SN-00A1 rev B")) {
+        (ManagerControlMessageContents::Answer(Some(ref test), ref response), Some(2)) => {
+            assert_eq!(test.id(), "serial");
+            assert_eq!(response, "SN-00A1 rev B");
+        }
+        _ => panic!("answer with a test and slot wasn't parsed"),
+    }
+}
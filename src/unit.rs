@@ -7,9 +7,10 @@ extern crate systemd_parser;
 use std::fmt;
 use std::path::Path;
 use std::io;
+use std::time::Duration;
 
 use self::dependy::DepError;
-use self::humantime::DurationError;
+use self::humantime::{parse_duration, DurationError};
 use self::runny::RunnyError;
 use self::runny::running::RunningError;
 use self::systemd_parser::errors::ParserError;
@@ -135,6 +136,16 @@ impl UnitName {
             kind: UnitKind::Internal,
         }
     }
+
+    /// Name a unit that exists only in memory, such as the scenario built to
+    /// run a single test.  The id is prefixed with "run/", and since unit file
+    /// names can't contain a "/", it can never clash with a loaded unit.
+    pub fn temporary(s: &str, kind: UnitKind) -> Self {
+        UnitName {
+            id: format!("run/{}", s),
+            kind: kind,
+        }
+    }
 }
 
 impl fmt::Display for UnitName {
@@ -150,6 +161,7 @@ pub enum UnitIncompatibleReason {
     TestFileNotPresent(String),
     IncompatibleJig,
     DependencyError(DepError<UnitName>),
+    InvalidParameter(String),
 }
 
 impl fmt::Display for UnitIncompatibleReason {
@@ -165,6 +177,9 @@ impl fmt::Display for UnitIncompatibleReason {
                 write!(f, "Test file {} not present", file_name)
             }
             &UnitIncompatibleReason::IncompatibleJig => write!(f, "Jig not compatible"),
+            &UnitIncompatibleReason::InvalidParameter(ref reason) => {
+                write!(f, "Invalid parameter: {}", reason)
+            }
             &UnitIncompatibleReason::DependencyError(ref dep_error) => match dep_error {
                 &DepError::RequirementsNotFound(ref req) => {
                     write!(f, "Requirement '{}' not found", req)
@@ -249,6 +264,9 @@ pub enum UnitActivateError {
 
     /// We tried to Exec, but an error occurred.
     ExecFailed(RunnyError),
+
+    /// We tried to open a file, but an error occurred.
+    OpenFailed(io::Error),
 }
 
 impl From<RunnyError> for UnitActivateError {
@@ -257,6 +275,12 @@ impl From<RunnyError> for UnitActivateError {
     }
 }
 
+impl From<io::Error> for UnitActivateError {
+    fn from(error: io::Error) -> Self {
+        UnitActivateError::OpenFailed(error)
+    }
+}
+
 impl fmt::Display for UnitActivateError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         use self::runny::RunnyError;
@@ -274,6 +298,7 @@ impl fmt::Display for UnitActivateError {
                     write!(f, "Unable to activate unit: Nix library error: {:?}", e)
                 }
             },
+            &UnitActivateError::OpenFailed(ref e) => write!(f, "Unable to activate unit: {}", e),
             &UnitActivateError::UnitNotFound => write!(f, "Couldn't find unit by id"),
             &UnitActivateError::UnitNotSelected => write!(f, "Tried to activate a deselected unit"),
         }
@@ -289,6 +314,9 @@ pub enum UnitDeactivateError {
 
     /// Runny reported an error of some sort.
     RunningError(RunningError),
+
+    /// The unit stopped because its output couldn't be written.
+    WriteFailed(io::Error),
 }
 
 impl From<RunningError> for UnitDeactivateError {
@@ -303,6 +331,7 @@ impl fmt::Display for UnitDeactivateError {
             &UnitDeactivateError::NonZeroReturn(i) => write!(f, "Nonzero return: {}", i),
             &UnitDeactivateError::RunningError(ref e) => write!(f, "Running error: {:?}", e),
             &UnitDeactivateError::UnitNotFound => write!(f, "Couldn't find unit by id"),
+            &UnitDeactivateError::WriteFailed(ref e) => write!(f, "Write failed: {}", e),
         }
     }
 }
@@ -398,3 +427,110 @@ impl fmt::Display for UnitDescriptionError {
         }
     }
 }
+
+/// Whether a long-running unit should be started again after its process exits.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RestartMode {
+    /// Leave the unit stopped.
+    No,
+
+    /// Restart the unit no matter how it exited.
+    Always,
+
+    /// Restart the unit only if it exited with a nonzero return code.
+    OnFailure,
+}
+
+/// Restart settings shared by Interfaces, Loggers, and Triggers.
+#[derive(Clone, Debug)]
+pub struct RestartPolicy {
+    /// When to restart the unit.
+    pub mode: RestartMode,
+
+    /// How long to wait before restarting.  This doubles for each recent restart.
+    pub delay: Duration,
+
+    /// How many restarts are allowed within `interval`, or 0 for no limit.
+    pub burst: u32,
+
+    /// The window over which restarts are counted.
+    pub interval: Duration,
+}
+
+impl RestartPolicy {
+    pub fn new() -> RestartPolicy {
+        RestartPolicy {
+            mode: RestartMode::No,
+            delay: Duration::from_millis(100),
+            burst: 5,
+            interval: Duration::from_secs(10),
+        }
+    }
+
+    /// Parse one of the restart directives from the named section.
+    /// Returns Ok(false) if the key is not a restart directive.
+    pub fn parse_directive(
+        &mut self,
+        section: &str,
+        key: &str,
+        value: Option<&str>,
+    ) -> Result<bool, UnitDescriptionError> {
+        match key {
+            "Restart" | "RestartSec" | "StartLimitBurst" | "StartLimitIntervalSec" => (),
+            _ => return Ok(false),
+        }
+        let value = match value {
+            Some(v) => v,
+            None => {
+                return Err(UnitDescriptionError::MissingValue(
+                    section.to_owned(),
+                    key.to_owned(),
+                ))
+            }
+        };
+        match key {
+            "Restart" => {
+                self.mode = match value.to_lowercase().as_ref() {
+                    "no" => RestartMode::No,
+                    "always" => RestartMode::Always,
+                    "on-failure" => RestartMode::OnFailure,
+                    other => {
+                        return Err(UnitDescriptionError::InvalidValue(
+                            section.to_owned(),
+                            key.to_owned(),
+                            other.to_owned(),
+                            vec!["no".to_owned(), "always".to_owned(), "on-failure".to_owned()],
+                        ))
+                    }
+                }
+            }
+            "RestartSec" => self.delay = Self::parse_time(value)?,
+            "StartLimitBurst" => self.burst = value.parse()?,
+            _ => self.interval = Self::parse_time(value)?,
+        }
+        Ok(true)
+    }
+
+    /// Determine whether a unit that exited should be started again.
+    pub fn should_restart(&self, success: bool) -> bool {
+        match self.mode {
+            RestartMode::No => false,
+            RestartMode::Always => true,
+            RestartMode::OnFailure => !success,
+        }
+    }
+
+    /// How long to wait before the next restart, given the number of
+    /// restarts that have already happened within the current interval.
+    pub fn backoff(&self, recent_restarts: u32) -> Duration {
+        self.delay * (1 << recent_restarts.min(6))
+    }
+
+    fn parse_time(time_str: &str) -> Result<Duration, DurationError> {
+        if let Ok(val) = time_str.parse::<u64>() {
+            Ok(Duration::from_secs(val))
+        } else {
+            parse_duration(time_str)
+        }
+    }
+}
@@ -87,6 +87,40 @@ impl fmt::Display for UnitStatus {
     }
 }
 
+impl UnitStatus {
+    /// A short name for the status, without any path or reason.
+    pub fn as_str(&self) -> &str {
+        match self {
+            &UnitStatus::Added(_) => "added",
+            &UnitStatus::Updated(_) => "updated",
+            &UnitStatus::LoadStarted(_) => "loading",
+            &UnitStatus::LoadFailed(_) => "load-failed",
+            &UnitStatus::Loaded => "loaded",
+            &UnitStatus::Selected => "selected",
+            &UnitStatus::SelectFailed(_) => "select-failed",
+            &UnitStatus::Deselected(_) => "deselected",
+            &UnitStatus::Active => "active",
+            &UnitStatus::ActivationFailed(_) => "activation-failed",
+            &UnitStatus::DeactivatedSuccessfully(_) => "deactivated",
+            &UnitStatus::DeactivatedUnsuccessfully(_) => "deactivation-failed",
+            &UnitStatus::UnloadStarted(_) => "unloading",
+            &UnitStatus::UpdateStarted(_) => "updating",
+            &UnitStatus::Removed(_) => "removed",
+        }
+    }
+
+    /// If this status means something went wrong, the reason why.
+    pub fn failure(&self) -> Option<&String> {
+        match self {
+            &UnitStatus::LoadFailed(ref reason)
+            | &UnitStatus::SelectFailed(ref reason)
+            | &UnitStatus::ActivationFailed(ref reason)
+            | &UnitStatus::DeactivatedUnsuccessfully(ref reason) => Some(reason),
+            _ => None,
+        }
+    }
+}
+
 #[derive(PartialEq, Eq, Hash, Debug, Clone)]
 pub struct UnitStatusEvent {
     pub name: UnitName,
@@ -245,17 +279,59 @@ impl UnitCategoryEvent {
     }
 }
 
-#[derive(PartialEq, Eq, Hash, Debug, Clone, Serialize)]
+/// The severity of a log entry, from least to most severe.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, PartialOrd, Ord, Serialize)]
 pub enum LogType {
-    Error,
+    Debug,
     Info,
+    Warn,
+    Error,
 }
 
 impl LogType {
     pub fn as_str(&self) -> &str {
         match self {
-            &LogType::Error => "error",
+            &LogType::Debug => "debug",
             &LogType::Info => "info",
+            &LogType::Warn => "warn",
+            &LogType::Error => "error",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<LogType> {
+        match s.to_lowercase().as_ref() {
+            "debug" => Some(LogType::Debug),
+            "info" => Some(LogType::Info),
+            "warn" => Some(LogType::Warn),
+            "error" => Some(LogType::Error),
+            _ => None,
+        }
+    }
+}
+
+/// Where a log entry came from.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Serialize)]
+pub enum LogSource {
+    /// A line printed to stdout by a unit's program.
+    Stdout,
+
+    /// A line printed to stderr by a unit's program.
+    Stderr,
+
+    /// A message generated by Exclave itself.
+    Internal,
+
+    /// A unit changed state, e.g. a scenario or test started or finished.
+    Status,
+}
+
+impl LogSource {
+    pub fn as_str(&self) -> &str {
+        match self {
+            &LogSource::Stdout => "stdout",
+            &LogSource::Stderr => "stderr",
+            &LogSource::Internal => "internal",
+            &LogSource::Status => "status",
         }
     }
 }
@@ -264,35 +340,63 @@ impl LogType {
 pub struct LogEntry {
     unit: UnitName,
     log_type: LogType,
+    log_source: LogSource,
     log_message: String,
     /// Number of seconds since the epoch
     pub unix_time: u64,
 
     /// Number of nanoseconds since the epoch
     pub unix_time_nsecs: u32,
+
+    /// The scenario that was running when this entry was generated
+    scenario: Option<UnitName>,
+
+    /// The jig slot the entry came from
+    slot: Option<u32>,
+
+    /// A number identifying the scenario run, unique while Exclave is running
+    run: Option<u64>,
 }
 
 impl LogEntry {
-    pub fn new_error(id: UnitName, message: String) -> Self {
+    pub fn new(id: UnitName, log_type: LogType, log_source: LogSource, message: String) -> Self {
         let elapsed = Self::elapsed();
         LogEntry {
             unit: id,
-            log_type: LogType::Error,
+            log_type: log_type,
+            log_source: log_source,
             log_message: message,
             unix_time: elapsed.as_secs(),
             unix_time_nsecs: elapsed.subsec_nanos(),
+            scenario: None,
+            slot: None,
+            run: None,
         }
     }
 
+    /// Note which scenario run this entry belongs to.
+    pub fn with_context(mut self, scenario: UnitName, slot: Option<u32>, run: u64) -> Self {
+        self.scenario = Some(scenario);
+        self.slot = slot;
+        self.run = Some(run);
+        self
+    }
+
+    pub fn new_error(id: UnitName, message: String) -> Self {
+        Self::new(id, LogType::Error, LogSource::Internal, message)
+    }
+
+    pub fn new_warn(id: UnitName, message: String) -> Self {
+        Self::new(id, LogType::Warn, LogSource::Internal, message)
+    }
+
     pub fn new_info(id: UnitName, message: String) -> Self {
-        let elapsed = Self::elapsed();
-        LogEntry {
-            unit: id,
-            log_type: LogType::Info,
-            log_message: message,
-            unix_time: elapsed.as_secs(),
-            unix_time_nsecs: elapsed.subsec_nanos(),
-        }
+        Self::new(id, LogType::Info, LogSource::Internal, message)
+    }
+
+    /// A note that a unit has changed state.
+    pub fn new_status(id: UnitName, message: String) -> Self {
+        Self::new(id, LogType::Info, LogSource::Status, message)
     }
 
     pub fn secs(&self) -> u64 {
@@ -311,6 +415,27 @@ impl LogEntry {
         &self.log_type
     }
 
+    pub fn source(&self) -> &LogSource {
+        &self.log_source
+    }
+
+    pub fn scenario(&self) -> &Option<UnitName> {
+        &self.scenario
+    }
+
+    pub fn slot(&self) -> Option<u32> {
+        self.slot
+    }
+
+    pub fn run(&self) -> Option<u64> {
+        self.run
+    }
+
+    /// The time this entry was generated.
+    pub fn time(&self) -> time::SystemTime {
+        time::UNIX_EPOCH + time::Duration::new(self.unix_time, self.unix_time_nsecs)
+    }
+
     pub fn id(&self) -> &UnitName {
         &self.unit
     }
@@ -327,8 +452,10 @@ impl LogEntry {
 impl fmt::Display for LogEntry {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self.log_type {
-            LogType::Error => write!(f, "ERROR {}: {}", self.unit, self.log_message),
+            LogType::Debug => write!(f, "DEBUG {}: {}", self.unit, self.log_message),
             LogType::Info => write!(f, "INFO {}: {}", self.unit, self.log_message),
+            LogType::Warn => write!(f, "WARN {}: {}", self.unit, self.log_message),
+            LogType::Error => write!(f, "ERROR {}: {}", self.unit, self.log_message),
         }
     }
 }
@@ -347,6 +474,10 @@ pub enum UnitEvent {
     /// The system has requested a rescan take place.
     RescanRequest,
 
+    /// The configuration directories should be read again, and compared
+    /// against the units that are loaded.
+    ReloadRequest,
+
     /// A rescan has started.
     RescanStart,
 
@@ -2,7 +2,11 @@
 // not actually be selected, e.g. if they aren't compatible.
 
 use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 
 use config::Config;
@@ -122,6 +126,10 @@ pub struct UnitLibrary {
     /// The unit status is used to determine whether to reload units or not.
     unit_status: RefCell<HashMap<UnitName, UnitStatus>>,
 
+    /// The file each unit was last loaded from, and a hash of its contents,
+    /// so that a reload can tell which files have changed.
+    unit_files: RefCell<HashMap<UnitName, (PathBuf, Option<u64>)>>,
+
     /// Currently available interface descriptions.  The interfaces they describe might not be valid.
     interface_descriptions: RefCell<HashMap<UnitName, InterfaceDescription>>,
 
@@ -157,6 +165,7 @@ impl UnitLibrary {
         UnitLibrary {
             broadcaster: broadcaster.clone(),
             unit_status: RefCell::new(HashMap::new()),
+            unit_files: RefCell::new(HashMap::new()),
 
             interface_descriptions: RefCell::new(HashMap::new()),
             jig_descriptions: RefCell::new(HashMap::new()),
@@ -381,11 +390,78 @@ impl UnitLibrary {
         self.broadcaster.broadcast(&UnitEvent::RescanFinish);
     }
 
+    /// Read the configuration directories again, and compare them against
+    /// the files units were loaded from.  New, changed, and deleted files
+    /// produce the same Added, Updated, and Removed events as the watcher,
+    /// which catches changes that it missed (e.g. on network filesystems).
+    pub fn reload(&self, dirs: &[PathBuf]) {
+        let mut events = vec![];
+        let mut found = HashMap::new();
+        for dir in dirs {
+            let entries = match dir.read_dir() {
+                Ok(entries) => entries,
+                Err(e) => {
+                    self.broadcaster.log("reload", format!("unable to read {}: {}", dir.display(), e));
+                    continue;
+                }
+            };
+            for entry in entries {
+                let path = match entry {
+                    Ok(entry) => entry.path(),
+                    Err(_) => continue,
+                };
+                let name = match UnitName::from_path(&path) {
+                    Ok(name) => name,
+                    Err(_) => continue,
+                };
+                match self.unit_files.borrow().get(&name) {
+                    None => events.extend(UnitStatusEvent::new_added(&path)),
+                    Some(&(ref old_path, old_hash)) => {
+                        if *old_path != path || old_hash != Self::file_hash(&path) {
+                            events.extend(UnitStatusEvent::new_updated(&path));
+                        }
+                    }
+                }
+                found.insert(name, ());
+            }
+        }
+        for (name, &(ref path, _)) in self.unit_files.borrow().iter() {
+            if !found.contains_key(name) {
+                events.extend(UnitStatusEvent::new_removed(path));
+            }
+        }
+
+        self.broadcaster.log("reload", format!("{} unit files changed", events.len()));
+        for event in events {
+            self.broadcaster.broadcast(&UnitEvent::Status(event));
+        }
+    }
+
+    fn file_hash(path: &Path) -> Option<u64> {
+        let contents = fs::read(path).ok()?;
+        let mut hasher = DefaultHasher::new();
+        contents.hash(&mut hasher);
+        Some(hasher.finish())
+    }
+
     pub fn process_message(&self, evt: &UnitEvent) {
         match evt {
             &UnitEvent::Status(ref msg) =>  {
                 let &UnitStatusEvent {ref name, ref status} = msg;
 
+                // Remember where each unit came from, for reload().
+                match status {
+                    &UnitStatus::LoadStarted(ref path) | &UnitStatus::UpdateStarted(ref path) => {
+                        self.unit_files
+                            .borrow_mut()
+                            .insert(name.clone(), (path.clone(), Self::file_hash(path)));
+                    }
+                    &UnitStatus::UnloadStarted(_) => {
+                        self.unit_files.borrow_mut().remove(name);
+                    }
+                    _ => (),
+                }
+
                 match status {
                     &UnitStatus::LoadStarted(ref path) => {
                         process_if!(self, name, status, UnitKind::Interface, path, InterfaceDescription, interface_descriptions);
@@ -400,6 +476,7 @@ impl UnitLibrary {
                         process_if!(self, name, status, UnitKind::Jig, path, JigDescription, jig_descriptions);
                         process_if!(self, name, status, UnitKind::Logger, path, LoggerDescription, logger_descriptions);
                         process_if!(self, name, status, UnitKind::Scenario, path, ScenarioDescription, scenario_descriptions);
+                        process_if!(self, name, status, UnitKind::Test, path, TestDescription, test_descriptions);
                         process_if!(self, name, status, UnitKind::Trigger, path, TriggerDescription, trigger_descriptions);
                     }
                     &UnitStatus::UnloadStarted(ref path) => {
@@ -20,6 +20,7 @@ impl UnitLoader {
             &UnitEvent::Shutdown => return,
             &UnitEvent::Status(ref evt) => self.handle_status(evt),
             &UnitEvent::RescanRequest => (),
+            &UnitEvent::ReloadRequest => (),
             &UnitEvent::RescanStart => (),
             &UnitEvent::RescanFinish => (),
             &UnitEvent::Category(_) => (),
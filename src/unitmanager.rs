@@ -1,22 +1,26 @@
 // The UnitManager contains all units that are Selected.  This includes
 // units that are Active.
-use std::cell::RefCell;
-use std::collections::HashMap;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt;
+use std::path::Path;
 use std::rc::Rc;
 use std::sync::mpsc::{channel, Receiver, Sender};
 use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 
+use artifacts::ArtifactDir;
 use config::Config;
 use unit::{UnitName, UnitKind, UnitActivateError, UnitDeactivateError, UnitSelectError, UnitDeselectError, UnitIncompatibleReason};
-use unitbroadcaster::{UnitBroadcaster, UnitEvent, UnitStatusEvent, UnitStatus, LogEntry};
+use unitbroadcaster::{UnitBroadcaster, UnitEvent, UnitStatusEvent, UnitStatus, LogEntry, LogSource, LogType};
 use units::interface::{Interface, InterfaceDescription};
 use units::jig::{Jig, JigDescription};
 use units::logger::{Logger, LoggerDescription};
-use units::scenario::{Scenario, ScenarioDescription};
+use units::scenario::{RunOptions, Scenario, ScenarioDescription};
 use units::test::{Test, TestDescription};
 use units::trigger::{Trigger, TriggerDescription};
+use yieldstats::{RunRecord, StatsWindow, YieldStats};
 
 macro_rules! load {
     ($slf:ident, $dest:ident, $desc:ident) => {
@@ -90,24 +94,50 @@ pub enum ManagerStatusMessage {
     /// A log message from one of the units, or the system itself.
     Log(LogEntry),
 
+    /// The number of slots (i.e. devices under test) the current jig has.
+    Slots(u32),
+
     /// A test has started running.
-    Running(UnitName),
+    Running(UnitName, Option<u32> /* slot */),
 
     /// A scenario has started
-    Start(UnitName),
+    Start(UnitName, Option<u32> /* slot */),
 
     /// Indicates that a test passed successfully.
-    Pass(UnitName, String /* log message */),
+    Pass(UnitName, String /* log message */, Option<u32> /* slot */),
 
     /// Indicates that a test failed for some reason.
-    Fail(UnitName, i32 /* return code */, String /* log message */),
+    Fail(UnitName, i32 /* return code */, String /* log message */, Option<u32> /* slot */),
 
     /// Indicates that a test was skipped for some reason.
-    Skipped(UnitName, String /* reason */),
+    Skipped(UnitName, String /* reason */, Option<u32> /* slot */),
 
     /// Sent when a scenario has finished running.
-    Finished(UnitName /* Scenario name */, u32 /* Result code */, String /* Reason for finishing */),
+    Finished(UnitName /* Scenario name */, u32 /* Result code */, String /* Reason for finishing */, Option<u32> /* slot */),
+
+    /// A scenario has been paused, and won't start any more tests until resumed or stepped.
+    Paused(UnitName /* Scenario name */, Option<u32> /* slot */),
+
+    /// A paused scenario has been resumed.
+    Resumed(UnitName /* Scenario name */, Option<u32> /* slot */),
+
+    /// The values that tests stored during a scenario run, sent just before it finishes.
+    Values(UnitName /* Scenario name */, BTreeMap<String, String>, Option<u32> /* slot */),
+
+    /// An estimate of how long is left in a scenario, based on how long its tests took before.
+    Eta(UnitName /* Scenario name */, u64 /* Seconds remaining */, Option<u32> /* slot */),
 
+    /// How far along a running test is, as a percentage and a short status.
+    Progress(UnitName /* Test name */, u32 /* Percent */, String /* Status */, Option<u32> /* slot */),
+
+    /// A test is waiting for the operator to answer a question.  If no responses are listed, any answer will do.
+    Prompt(UnitName /* Test name */, String /* Prompt */, Vec<String> /* Allowed responses */, Option<u32> /* slot */),
+
+    /// Every known unit, with its current status and the reason it last failed, if it has.
+    Units(Vec<(UnitName, UnitStatus, Option<String> /* last failure */)>),
+
+    /// Yield and failure statistics over recorded runs.
+    Stats(YieldStats),
 }
 
 /// Messages for Unit -> Library communication
@@ -125,6 +155,18 @@ pub enum ManagerControlMessageContents {
     /// Get a list of tests, either from the current scenario (None) or a specific scenario (Some)
     Tests(Option<UnitName>),
 
+    /// Get a list of every known unit and its status.
+    Units,
+
+    /// Read the configuration directories again, picking up any new, changed, or removed unit files.
+    Reload,
+
+    /// Get yield and failure statistics over the runs saved in the artifact directory.
+    Stats(StatsWindow),
+
+    /// Statistics gathered on another thread, to be sent on to the unit that asked for them.
+    StatsLoaded(Result<YieldStats, String>),
+
     /// An error message from a particular interface.
     Error(String /* Error message contents */),
 
@@ -137,6 +179,15 @@ pub enum ManagerControlMessageContents {
     /// Indicates the child (Interface, Test, etc.) has exited.
     ChildExited,
 
+    /// Start a unit again after it exited, once its restart delay has passed.
+    Restart,
+
+    /// Have every logger sync what it has written so far, such as to disk.
+    SyncLogs,
+
+    /// How long tests took the last time they passed, read from the runs saved in the artifact directory.
+    TestDurations(Vec<(UnitName, Duration)>),
+
     /// Client sent an unimplemented message.
     Unimplemented(String /* verb */, String /* rest of line */),
 
@@ -146,8 +197,11 @@ pub enum ManagerControlMessageContents {
     /// Send an ERROR message to the logging system
     LogError(String /* log message */),
 
+    /// A line of output from a unit's program, logged as INFO for stdout or ERROR for stderr
+    Output(LogSource, String /* line of output */),
+
     /// Start running a scenario, or the default scenario if None
-    StartScenario(Option<UnitName>),
+    StartScenario(Option<UnitName>, RunOptions),
 
     /// Start running a given test.
     StartTest(UnitName),
@@ -158,6 +212,12 @@ pub enum ManagerControlMessageContents {
     /// Sent when a test has started running.
     TestStarted,
 
+    /// Sent when a scenario has started running.
+    ScenarioStarted,
+
+    /// Sent when a paused scenario holds on to its next test instead of starting it.
+    ScenarioPaused(UnitName /* Held test */),
+
     /// Indicates that a test was skipped, and why.
     Skip(UnitName, String /* reason */),
 
@@ -167,6 +227,37 @@ pub enum ManagerControlMessageContents {
     /// Indicates that a test has finished
     TestFinished(i32 /* Finish code */, String /* The last printed line */),
 
+    /// Run a single test, plus any tests it requires.
+    RunTest(UnitName),
+
+    /// Pause the running scenario before it starts the next test.
+    Pause,
+
+    /// Resume a paused scenario.
+    Resume,
+
+    /// Run one more test of a paused scenario, then pause again.
+    Step,
+
+    /// Stop the running scenario early, failing the test that is running.
+    Abort,
+
+    /// Store a value for later tests in the running scenario to use.
+    SetValue(String /* Key */, String /* Value */),
+
+    /// The values stored during a scenario run, sent by the scenario as it finishes.
+    Values(BTreeMap<String, String>),
+
+    /// A test reported its progress, as a percentage and a short status.
+    Progress(u32 /* Percent */, String /* Status */),
+
+    /// A test wants the operator to answer a question, either because it is a manual test or because
+    /// it printed an EXCLAVE-PROMPT line.
+    Prompt(String /* Prompt */, Vec<String> /* Allowed responses */),
+
+    /// The operator's answer to a manual test.  If no test is named, the oldest prompt is answered.
+    Answer(Option<UnitName> /* Test name */, String /* Response */),
+
     /// Shutdown the entire system
     Shutdown(Option<String>),
 }
@@ -175,6 +266,9 @@ pub enum ManagerControlMessageContents {
 pub struct ManagerControlMessage {
     pub sender: UnitName,
     pub contents: ManagerControlMessageContents,
+
+    /// The jig slot this message refers to, if the jig has more than one.
+    pub slot: Option<u32>,
 }
 
 impl ManagerControlMessage {
@@ -182,6 +276,15 @@ impl ManagerControlMessage {
         ManagerControlMessage {
             sender: id.clone(),
             contents: contents,
+            slot: None,
+        }
+    }
+
+    pub fn new_with_slot(id: &UnitName, slot: Option<u32>, contents: ManagerControlMessageContents) -> Self {
+        ManagerControlMessage {
+            sender: id.clone(),
+            contents: contents,
+            slot: slot,
         }
     }
 }
@@ -214,6 +317,41 @@ pub struct UnitManager {
     /// The currently-selected Scenario, if any
     current_scenario: Rc<RefCell<Option<Rc<RefCell<Scenario>>>>>,
 
+    /// Scenarios running on individual slots of a multi-slot jig, indexed by slot number.
+    /// Entries are removed when the scenario finishes.
+    slot_scenarios: RefCell<HashMap<u32, Rc<RefCell<Scenario>>>>,
+
+    /// A temporary scenario created to run a single test, if one is running.
+    test_run: RefCell<Option<Rc<RefCell<Scenario>>>>,
+
+    /// How long each test took when it last passed.
+    test_durations: RefCell<HashMap<UnitName, Duration>>,
+
+    /// When each running test was started, indexed by test name and slot.
+    test_start_times: RefCell<HashMap<(UnitName, Option<u32>), Instant>>,
+
+    /// Tests that are waiting for an answer, oldest first, along with the responses they allow.
+    prompts: RefCell<Vec<(UnitName, Option<u32>, Vec<String>)>>,
+
+    /// When each long-running unit was recently restarted, for enforcing start limits.
+    restarts: RefCell<HashMap<UnitName, Vec<Instant>>>,
+
+    /// Units that are waiting out their restart delay.
+    pending_restarts: RefCell<HashSet<UnitName>>,
+
+    /// The latest status of every known unit, including ones that failed to
+    /// load, along with the reason it last failed.
+    unit_status: RefCell<HashMap<UnitName, (UnitStatus, Option<String>)>>,
+
+    /// The scenario in progress on each slot and its run number, for tagging log entries and events.
+    runs: RefCell<HashMap<Option<u32>, (UnitName, u64)>>,
+
+    /// The most recently assigned run number.
+    last_run: Cell<u64>,
+
+    /// Where the output and results of the run in progress on each slot are saved.
+    artifacts: RefCell<HashMap<Option<u32>, ArtifactDir>>,
+
     /// The currently-selected Jig, if any
     current_jig: Rc<RefCell<Option<Rc<RefCell<Jig>>>>>,
 
@@ -246,6 +384,17 @@ impl UnitManager {
             active: Rc::new(RefCell::new(HashMap::new())),
 
             current_scenario: Rc::new(RefCell::new(None)),
+            slot_scenarios: RefCell::new(HashMap::new()),
+            test_run: RefCell::new(None),
+            test_durations: RefCell::new(HashMap::new()),
+            test_start_times: RefCell::new(HashMap::new()),
+            prompts: RefCell::new(vec![]),
+            restarts: RefCell::new(HashMap::new()),
+            pending_restarts: RefCell::new(HashSet::new()),
+            unit_status: RefCell::new(HashMap::new()),
+            runs: RefCell::new(HashMap::new()),
+            last_run: Cell::new(0),
+            artifacts: RefCell::new(HashMap::new()),
             current_jig: Rc::new(RefCell::new(None)),
 
             control_sender: sender,
@@ -263,6 +412,39 @@ impl UnitManager {
         self.control_sender.clone()
     }
 
+    /// Read how long each test took the last time it passed from the runs saved in
+    /// the artifact directory, so that estimates are available from the first run.
+    /// The runs are read on another thread, and the durations are sent back as a
+    /// TestDurations message.
+    pub fn load_test_durations(&self) {
+        let root = match *self.cfg.lock().unwrap().artifact_root() {
+            Some(ref r) => r.clone(),
+            None => return,
+        };
+        let control_sender = self.control_sender.clone();
+        thread::spawn(move || {
+            let mut durations: HashMap<UnitName, Duration> = HashMap::new();
+            // Newest first, so that each test keeps its most recent pass.
+            for path in ArtifactDir::list(&root).unwrap_or_default().iter().rev() {
+                let run = match RunRecord::load(path) {
+                    Ok(Some(r)) => r,
+                    _ => continue,
+                };
+                for &(ref name, ref result, _, duration) in &run.tests {
+                    if let (Ok(name), Some(duration), "pass") = (UnitName::from_str(name, "test"), duration, result.as_str()) {
+                        durations.entry(name).or_insert(duration);
+                    }
+                }
+            }
+            control_sender
+                .send(ManagerControlMessage::new(
+                    &UnitName::internal("artifacts"),
+                    ManagerControlMessageContents::TestDurations(durations.into_iter().collect()),
+                ))
+                .ok();
+        });
+    }
+
     pub fn load_interface(&self, description: &InterfaceDescription) -> Result<UnitName, UnitIncompatibleReason> {
         load!(self, interfaces, description)
     }
@@ -414,6 +596,8 @@ impl UnitManager {
     }
 
     pub fn deselect(&self, id: &UnitName, reason: &str) {
+        // A unit that is being removed shouldn't come back on its own.
+        self.pending_restarts.borrow_mut().remove(id);
         self.deactivate(id, "unit is being deselcted");
 
         // Don't deselect a unit that hasn't been selected.
@@ -471,9 +655,7 @@ impl UnitManager {
 
     fn deselect_jig(&self, id: &UnitName) -> Result<(), UnitDeselectError> {
         // If the specified jig isn't the current jig, then there's nothing to do.
-        let mut current_jig_opt = self.current_jig.borrow_mut();
-
-        let current_jig = match *current_jig_opt {
+        let current_jig = match *self.current_jig.borrow() {
             None => return Ok(()),
             Some(ref s) => {
                 let current_jig = s.borrow();
@@ -484,13 +666,16 @@ impl UnitManager {
             }
         };
 
+        // The jig's slots go away with it, so stop anything running on them.
+        self.stop_slot_scenarios(None, "jig is deselecting");
+
         // If there is a default scenario, make sure it's deselected.
         if let Some(new_scenario_id) = current_jig.borrow().default_scenario().clone() {
             self.deselect(&new_scenario_id, "jig is deselecting");
         }
 
         current_jig.borrow_mut().deselect()?;
-        *current_jig_opt = None;
+        *self.current_jig.borrow_mut() = None;
         Ok(())
     }
 
@@ -621,19 +806,236 @@ impl UnitManager {
                 Err(UnitActivateError::UnitNotSelected)
             } else {
                 // Activate this scenario.
-                self.bc.broadcast(&UnitEvent::Log(LogEntry::new_info(id.clone(), format!("scenario starting"))));
+                self.begin_run(&mut *s.borrow_mut(), None);
+                self.broadcast_run_status(id, None, format!("scenario starting"));
                 s.borrow_mut().activate(self, &*self.cfg.lock().unwrap())
             }
         }
     }
 
     fn activate_test(&self, id: &UnitName) -> Result<(), UnitActivateError> {
+        let environment = match self.unslotted_scenario() {
+            Some(sc) => sc.borrow().environment().clone(),
+            None => HashMap::new(),
+        };
         match self.tests.borrow().get(id) {
             None => Err(UnitActivateError::UnitNotFound),
-            Some(ref s) => s.borrow_mut().activate(self, &*self.cfg.lock().unwrap()),
+            Some(ref s) => s.borrow_mut().activate(self, &*self.cfg.lock().unwrap(), &environment),
         }
     }
 
+    /// Return the number of slots the current jig has.  Defaults to 1 if no jig is selected.
+    pub fn slot_count(&self) -> u32 {
+        match *self.current_jig.borrow() {
+            Some(ref jig) => jig.borrow().slots(),
+            None => 1,
+        }
+    }
+
+    pub fn get_slot_scenario(&self, slot: u32) -> Option<Rc<RefCell<Scenario>>> {
+        self.slot_scenarios.borrow().get(&slot).map(|x| x.clone())
+    }
+
+    /// Start a copy of the named scenario on a jig slot.  If no slot is given,
+    /// the first slot that isn't running anything is used.
+    fn start_slot_scenario(&self, sender_name: &UnitName, scenario: &Rc<RefCell<Scenario>>, slot_opt: Option<u32>, options: &RunOptions) {
+        let scenario_name = scenario.borrow().id().clone();
+        let slot_count = self.slot_count();
+        let slot = match slot_opt {
+            Some(s) => {
+                if s < 1 || s > slot_count {
+                    self.bc.broadcast(&UnitEvent::Log(LogEntry::new_error(sender_name.clone(), format!("unable to start scenario: slot {} does not exist (jig has {} slots)", s, slot_count))));
+                    return;
+                }
+                if self.get_slot_scenario(s).is_some() {
+                    self.bc.broadcast(&UnitEvent::Log(LogEntry::new_error(sender_name.clone(), format!("unable to start scenario: slot {} not idle", s))));
+                    return;
+                }
+                s
+            },
+            None => {
+                // A slot is busy from the moment its scenario is started until it finishes.
+                let free_slot = (1..(slot_count + 1)).find(|s| self.get_slot_scenario(*s).is_none());
+                match free_slot {
+                    Some(s) => s,
+                    None => {
+                        self.bc.broadcast(&UnitEvent::Log(LogEntry::new_error(sender_name.clone(), "unable to start scenario: all slots are busy".to_owned())));
+                        return;
+                    }
+                }
+            }
+        };
+
+        let instance = {
+            let instance = scenario.borrow().instance_for_slot(self, slot).and_then(|mut instance| {
+                instance.set_run_options(self, options)?;
+                Ok(instance)
+            });
+            match instance {
+                Ok(s) => Rc::new(RefCell::new(s)),
+                Err(e) => {
+                    self.bc.broadcast(&UnitEvent::Log(LogEntry::new_error(scenario_name.clone(), format!("unable to start scenario on slot {}: {}", slot, e))));
+                    return;
+                }
+            }
+        };
+
+        self.slot_scenarios.borrow_mut().insert(slot, instance.clone());
+        self.begin_run(&mut *instance.borrow_mut(), Some(slot));
+        self.broadcast_run_status(&scenario_name, Some(slot), format!("scenario starting on slot {}", slot));
+        let result = instance.borrow_mut().activate(self, &*self.cfg.lock().unwrap());
+        if let Err(e) = result {
+            self.slot_scenarios.borrow_mut().remove(&slot);
+            self.runs.borrow_mut().remove(&Some(slot));
+            self.bc.broadcast(&UnitEvent::Log(LogEntry::new_error(scenario_name.clone(), format!("unable to start scenario on slot {}: {}", slot, e))));
+            return;
+        }
+        self.record_assumptions(&*instance.borrow(), Some(slot));
+        self.broadcast_message(ManagerStatusMessage::Start(scenario_name, Some(slot)));
+        self.broadcast_eta(Some(slot));
+    }
+
+    /// Stop the copies of a scenario running on jig slots, or every slot's
+    /// scenario if no name is given.  Their tests are stopped, and each run
+    /// finishes as a failure with the reason as its message.
+    fn stop_slot_scenarios(&self, scenario_name: Option<&UnitName>, reason: &str) {
+        let mut slots: Vec<u32> = self.slot_scenarios
+            .borrow()
+            .iter()
+            .filter(|&(_, sc)| scenario_name.map(|name| sc.borrow().id() == name).unwrap_or(true))
+            .map(|(slot, _)| *slot)
+            .collect();
+        slots.sort();
+
+        for slot in slots {
+            let slot_scenario = match self.slot_scenarios.borrow_mut().remove(&slot) {
+                Some(sc) => sc,
+                None => continue,
+            };
+            let scenario = slot_scenario.borrow();
+            for test in scenario.tests().values() {
+                if let Err(e) = test.borrow().deactivate(self) {
+                    self.bc.broadcast(&UnitEvent::Log(LogEntry::new_error(test.borrow().id().clone(), format!("unable to deactivate: {}", e))));
+                }
+            }
+            if let Err(e) = scenario.deactivate() {
+                self.bc.broadcast(&UnitEvent::Log(LogEntry::new_error(scenario.id().clone(), format!("unable to deactivate: {}", e))));
+            }
+            self.broadcast_run_status(scenario.id(), Some(slot), format!("scenario stopped on slot {}: {}", slot, reason));
+            self.broadcast_finished(scenario.id(), 500, &format!("scenario stopped: {}", reason), Some(slot));
+        }
+    }
+
+    /// Run a single test, along with the tests it requires, as a temporary scenario.
+    /// Tests assumed by the current jig's default scenario are assumed here as well.
+    fn run_test(&self, sender_name: &UnitName, test_name: &UnitName, slot: Option<u32>) {
+        let unit_directory = match self.get_test_named(test_name) {
+            Some(test) => test.borrow().unit_directory().clone(),
+            None => {
+                self.bc.broadcast(&UnitEvent::Log(LogEntry::new_error(sender_name.clone(), format!("unable to run test: test {} not found", test_name))));
+                return;
+            }
+        };
+        let (jig_id, default_scenario) = match *self.current_jig.borrow() {
+            Some(ref jig) => (Some(jig.borrow().id().clone()), jig.borrow().default_scenario().clone()),
+            None => (None, None),
+        };
+        let assumptions = match default_scenario.and_then(|name| self.get_scenario_named(&name)) {
+            Some(sc) => sc.borrow().assumptions().clone(),
+            None => vec![],
+        };
+
+        let scenario = {
+            let description = ScenarioDescription::for_test(test_name, jig_id.as_ref(), &assumptions, &unit_directory);
+            let scenario = description.load(self, &*self.cfg.lock().unwrap());
+            match scenario {
+                Ok(s) => Rc::new(RefCell::new(s)),
+                Err(e) => {
+                    self.bc.broadcast(&UnitEvent::Log(LogEntry::new_error(sender_name.clone(), format!("unable to run test {}: {}", test_name, e))));
+                    return;
+                }
+            }
+        };
+
+        if slot.is_some() || self.slot_count() > 1 {
+            self.start_slot_scenario(sender_name, &scenario, slot, &RunOptions::default());
+            return;
+        }
+
+        if self.scenario_is_running() {
+            self.bc.broadcast(&UnitEvent::Log(LogEntry::new_error(sender_name.clone(), "unable to run test: scenario not idle".to_owned())));
+            return;
+        }
+
+        let scenario_name = scenario.borrow().id().clone();
+        *self.test_run.borrow_mut() = Some(scenario.clone());
+        self.begin_run(&mut *scenario.borrow_mut(), None);
+        self.broadcast_run_status(&scenario_name, None, format!("running test {}", test_name));
+        let result = scenario.borrow_mut().activate(self, &*self.cfg.lock().unwrap());
+        if let Err(e) = result {
+            *self.test_run.borrow_mut() = None;
+            self.runs.borrow_mut().remove(&None);
+            self.bc.broadcast(&UnitEvent::Log(LogEntry::new_error(scenario_name, format!("unable to run test: {}", e))));
+            return;
+        }
+        self.broadcast_message(ManagerStatusMessage::Start(scenario_name, None));
+        self.broadcast_eta(None);
+    }
+
+    /// Returns true if the selected scenario, or a single test run, is in progress.
+    fn scenario_is_running(&self) -> bool {
+        if self.test_run.borrow().is_some() {
+            return true;
+        }
+        match *self.current_scenario.borrow() {
+            Some(ref scenario) => scenario.borrow().is_running(),
+            None => false,
+        }
+    }
+
+    /// The scenario that receives messages not tied to a jig slot.
+    /// This is a single test run, if one is in progress, or else the selected scenario.
+    fn unslotted_scenario(&self) -> Option<Rc<RefCell<Scenario>>> {
+        if let Some(ref sc) = *self.test_run.borrow() {
+            return Some(sc.clone());
+        }
+        self.current_scenario.borrow().clone()
+    }
+
+    /// Return the scenarios that are currently running on a given slot.  If no slot
+    /// is specified, all running scenarios are returned.
+    fn running_scenarios(&self, slot: Option<u32>) -> Vec<Rc<RefCell<Scenario>>> {
+        let mut scenarios = vec![];
+        if let Some(slot) = slot {
+            if let Some(sc) = self.get_slot_scenario(slot) {
+                scenarios.push(sc);
+            }
+            return scenarios;
+        }
+
+        if let Some(sc) = self.unslotted_scenario() {
+            if sc.borrow().is_running() {
+                scenarios.push(sc);
+            }
+        }
+        let mut slots: Vec<u32> = self.slot_scenarios.borrow().keys().map(|x| *x).collect();
+        slots.sort();
+        for slot in slots {
+            if let Some(sc) = self.get_slot_scenario(slot) {
+                scenarios.push(sc);
+            }
+        }
+        scenarios
+    }
+
+    /// Find the copy of a test that is running as part of the scenario on the given slot.
+    fn get_slot_test(&self, slot: u32, test_name: &UnitName) -> Option<(Rc<RefCell<Test>>, HashMap<String, String>)> {
+        let scenario = self.get_slot_scenario(slot)?;
+        let scenario = scenario.borrow();
+        let test = scenario.tests().get(test_name)?.clone();
+        Some((test, scenario.environment().clone()))
+    }
+
     pub fn deactivate(&self, id: &UnitName, reason: &str) {
 
         // Don't deactivate an inactive unit.
@@ -661,6 +1063,66 @@ impl UnitManager {
         }
     }
 
+    /// Clean up after a long-running unit whose process went away, and
+    /// schedule it to be started again if its restart policy allows.
+    fn child_exited(&self, id: &UnitName) {
+        // Units that were deliberately deactivated also report their exit.
+        if ! self.active.borrow().contains_key(id) {
+            return;
+        }
+
+        let (result, policy) = match *id.kind() {
+            UnitKind::Interface => (self.deactivate_interface(id),
+                                    self.interfaces.borrow().get(id).map(|x| x.borrow().restart_policy().clone())),
+            UnitKind::Logger => (self.deactivate_logger(id),
+                                 self.loggers.borrow().get(id).map(|x| x.borrow().restart_policy().clone())),
+            UnitKind::Trigger => (self.deactivate_trigger(id),
+                                  self.triggers.borrow().get(id).map(|x| x.borrow().restart_policy().clone())),
+            _ => (Ok(()), None),
+        };
+        self.active.borrow_mut().remove(id);
+
+        let reason = match result {
+            Ok(_) => "Unit unexpectedly exited".to_owned(),
+            Err(ref e) => format!("Unit unexpectedly exited: {}", e),
+        };
+        self.bc.broadcast(&UnitEvent::Status(UnitStatusEvent::new_active_failed(id, reason)));
+
+        let policy = match policy {
+            Some(p) => p,
+            None => return,
+        };
+        if ! policy.should_restart(result.is_ok()) {
+            return;
+        }
+
+        let now = Instant::now();
+        let recent_restarts = {
+            let mut restarts = self.restarts.borrow_mut();
+            let history = restarts.entry(id.clone()).or_insert_with(Vec::new);
+            history.retain(|t| now.duration_since(*t) < policy.interval);
+            if policy.burst > 0 && history.len() as u32 >= policy.burst {
+                self.bc.broadcast(&UnitEvent::Log(LogEntry::new_error(id.clone(),
+                    format!("restarted {} times within {}s, not restarting again", history.len(), policy.interval.as_secs()))));
+                return;
+            }
+            history.push(now);
+            history.len() as u32 - 1
+        };
+
+        let delay = policy.backoff(recent_restarts);
+        self.bc.broadcast(&UnitEvent::Log(LogEntry::new_warn(id.clone(),
+            format!("restarting in {}ms", delay.as_millis()))));
+        self.pending_restarts.borrow_mut().insert(id.clone());
+
+        let control_sender = self.control_sender.clone();
+        let id = id.clone();
+        thread::spawn(move || {
+            thread::sleep(delay);
+            control_sender.send(ManagerControlMessage::new(&id, ManagerControlMessageContents::Restart)).ok();
+        });
+    }
+
     fn deactivate_interface(&self, id: &UnitName) -> Result<(), UnitDeactivateError> {
         let interfaces = self.interfaces.borrow();
         match interfaces.get(id) {
@@ -700,12 +1162,16 @@ impl UnitManager {
         match *current_scenario_opt {
             None => Ok(()),
             Some(ref s) => {
-                let current_scenario = s.borrow_mut();
+                let mut current_scenario = s.borrow_mut();
                 if current_scenario.id() != id {
                     Ok(())
                 }
                 else {
-                    self.bc.broadcast(&UnitEvent::Log(LogEntry::new_info(id.clone(), format!("scenario ended"))));
+                    self.broadcast_run_status(id, None, format!("scenario ended"));
+                    // Options given when starting the scenario only apply to that run.
+                    if let Err(e) = current_scenario.clear_run_options(self) {
+                        self.bc.broadcast(&UnitEvent::Log(LogEntry::new_error(id.clone(), format!("unable to clear run options: {}", e))));
+                    }
                     current_scenario.deactivate()
                 }
             }
@@ -779,6 +1245,7 @@ impl UnitManager {
     }
 
     fn unload_scenario(&self, id: &UnitName) {
+        self.stop_slot_scenarios(Some(id), "scenario is being unloaded");
         self.deactivate(id, "scenario is being unloaded");
         self.deselect(id, "scenario is being unloaded");
 
@@ -815,18 +1282,34 @@ impl UnitManager {
     pub fn process_message(&self, msg: &UnitEvent) {
         match msg {
             &UnitEvent::ManagerRequest(ref req) => self.manager_request(req),
-            &UnitEvent::Status(ref stat) => self.status_message(stat),
+            &UnitEvent::Status(ref stat) => {
+                for (_, logger) in self.loggers.borrow().iter() {
+                    logger.borrow().output_unit_status(stat).ok();
+                }
+                self.status_message(stat);
+            },
             &UnitEvent::Log(ref log) => {
+                if let Some(run) = log.run() {
+                    if let Some(dir) = self.artifacts.borrow().values().find(|d| d.run() == run) {
+                        dir.record_log(log).ok();
+                    }
+                }
                 let mut units_to_deactivate = vec![];
                 for (interface_id, interface) in self.interfaces.borrow().iter() {
+                    if ! interface.borrow().log_filter().matches(log) {
+                        continue;
+                    }
                     let log_status_msg = ManagerStatusMessage::Log(log.clone());
                     if let Err(e) = interface.borrow().output_message(log_status_msg) {
                         units_to_deactivate.push((interface_id.clone(), format!("unable to send message to interface: {:?}", e)));
                     }
                 }
                 for (logger_id, logger) in self.loggers.borrow().iter() {
+                    if ! logger.borrow().log_filter().matches(log) {
+                        continue;
+                    }
                     let log_status_msg = ManagerStatusMessage::Log(log.clone());
-                    if let Err(e) = logger.borrow().output_message(log_status_msg) {
+                    if let Err(e) = logger.borrow().output_message(log_status_msg, None) {
                         units_to_deactivate.push((logger_id.clone(), format!("unable to send message to logger: {:?}", e)));
                     }
                 }
@@ -840,6 +1323,19 @@ impl UnitManager {
 
     fn status_message(&self, msg: &UnitStatusEvent) {
         let &UnitStatusEvent {ref name, ref status} = msg;
+
+        // Keep track of every unit's status, so that it can be queried.
+        if let &UnitStatus::Removed(_) = status {
+            self.unit_status.borrow_mut().remove(name);
+        } else {
+            let mut unit_status = self.unit_status.borrow_mut();
+            let entry = unit_status.entry(name.clone()).or_insert((status.clone(), None));
+            entry.0 = status.clone();
+            if let Some(reason) = status.failure() {
+                entry.1 = Some(reason.clone());
+            }
+        }
+
         match status {
             &UnitStatus::Loaded => match name.kind() {
                 &UnitKind::Jig => self.broadcast_jig_named(name),
@@ -857,13 +1353,30 @@ impl UnitManager {
     }
 
     fn manager_request(&self, msg: &ManagerControlMessage) {
-        let &ManagerControlMessage {sender: ref sender_name, contents: ref msg} = msg;
+        let &ManagerControlMessage {sender: ref sender_name, contents: ref msg, slot} = msg;
 
         match *msg {
             ManagerControlMessageContents::Scenarios => self.send_scenarios_to(sender_name),
             ManagerControlMessageContents::Tests(ref scenario_name) => self.send_tests_to(sender_name, scenario_name),
-            ManagerControlMessageContents::Log(ref txt) => self.bc.broadcast(&UnitEvent::Log(LogEntry::new_info(sender_name.clone(), txt.clone()))),
-            ManagerControlMessageContents::LogError(ref txt) => self.bc.broadcast(&UnitEvent::Log(LogEntry::new_error(sender_name.clone(), txt.clone()))),
+            ManagerControlMessageContents::Units => self.send_units_to(sender_name),
+            ManagerControlMessageContents::Stats(ref window) => self.load_stats(sender_name, window),
+            ManagerControlMessageContents::StatsLoaded(ref stats) => self.send_stats_to(sender_name, stats.clone()),
+            ManagerControlMessageContents::Reload => {
+                self.bc.broadcast(&UnitEvent::Log(LogEntry::new_info(sender_name.clone(), "reloading units".to_owned())));
+                self.bc.broadcast(&UnitEvent::ReloadRequest);
+            }
+            ManagerControlMessageContents::Log(ref txt) => self.bc.broadcast(&UnitEvent::Log(self.log_context(LogEntry::new_info(sender_name.clone(), txt.clone()), slot))),
+            ManagerControlMessageContents::LogError(ref txt) => self.bc.broadcast(&UnitEvent::Log(self.log_context(LogEntry::new_error(sender_name.clone(), txt.clone()), slot))),
+            ManagerControlMessageContents::Output(ref source, ref txt) => {
+                let log_type = match *source {
+                    LogSource::Stderr => LogType::Error,
+                    _ => LogType::Info,
+                };
+                self.bc.broadcast(&UnitEvent::Log(self.log_context(LogEntry::new(sender_name.clone(), log_type, source.clone(), txt.clone()), slot)));
+                if let Some(dir) = self.artifacts.borrow().get(&slot) {
+                    dir.record_output(sender_name, source, txt).ok();
+                }
+            },
             ManagerControlMessageContents::Scenario(ref new_scenario_name) => {
                 if self.get_scenario_named(new_scenario_name).is_some() {
                     self.select(new_scenario_name);
@@ -885,26 +1398,82 @@ impl UnitManager {
                 if let Some(ref sc) = *self.current_scenario.borrow() {
                     self.send_scenario_to(sender_name, &sc.borrow().id().clone());
                 }
+                // Let late joiners know about runs that are waiting to be resumed.
+                for scenario_rc in self.running_scenarios(None) {
+                    let scenario = scenario_rc.borrow();
+                    if scenario.is_paused() {
+                        self.send_messages_to(sender_name, vec![ManagerStatusMessage::Paused(scenario.id().clone(), scenario.slot())]);
+                    }
+                }
+            },
+            ManagerControlMessageContents::ChildExited => self.child_exited(sender_name),
+            ManagerControlMessageContents::Restart => {
+                if self.pending_restarts.borrow_mut().remove(sender_name) {
+                    self.activate(sender_name);
+                }
             },
-            ManagerControlMessageContents::ChildExited => {
-                self.bc.broadcast(&UnitEvent::Status(UnitStatusEvent::new_active_failed(sender_name, "Unit unexpectedly exited".to_owned())));
+            // Tests that have passed since starting up already have a more recent duration.
+            ManagerControlMessageContents::TestDurations(ref durations) => {
+                let mut test_durations = self.test_durations.borrow_mut();
+                for &(ref test_name, duration) in durations {
+                    test_durations.entry(test_name.clone()).or_insert(duration);
+                }
+            }
+            ManagerControlMessageContents::SyncLogs => {
+                for (_, logger) in self.loggers.borrow().iter() {
+                    logger.borrow().sync();
+                }
+
+                // The run's log entries have all been saved by now, so close
+                // its artifact directory unless another run has started.
+                let finished = match (self.artifacts.borrow().get(&slot), self.runs.borrow().get(&slot)) {
+                    (Some(dir), Some(&(_, run))) => dir.run() != run,
+                    (Some(_), None) => true,
+                    _ => false,
+                };
+                if finished {
+                    self.artifacts.borrow_mut().remove(&slot);
+                }
             },
             ManagerControlMessageContents::AdvanceScenario(result) => {
-                match *self.current_scenario.borrow() {
+                if let Some(slot) = slot {
+                    if let Some(ref slot_scenario) = self.get_slot_scenario(slot) {
+                        slot_scenario.borrow().advance(sender_name, result, &self.control_sender);
+                    }
+                    return;
+                }
+                match self.unslotted_scenario() {
                     None => (),
-                    Some(ref current_scenario) => current_scenario.borrow_mut().advance(sender_name, result, &self.control_sender),
+                    Some(current_scenario) => current_scenario.borrow_mut().advance(sender_name, result, &self.control_sender),
                 }
             },
             ManagerControlMessageContents::Unimplemented(ref verb, ref remainder) => {
                 self.bc.broadcast(&UnitEvent::Log(LogEntry::new_error(sender_name.clone(), format!("unimplemented verb: {} (args: {})", verb, remainder))));
             },
-            ManagerControlMessageContents::StartScenario(ref scenario_name_opt) => {
-                // If a scenario exists and is running, don't start a new one.
-                if let Some(ref scenario) = *self.current_scenario.borrow() {
-                    if scenario.borrow().is_running() {
-                        self.bc.broadcast(&UnitEvent::Log(LogEntry::new_error(sender_name.clone(), "unable to start scenario: scenario not idle".to_owned())));
-                        return;
+            ManagerControlMessageContents::StartScenario(ref scenario_name_opt, ref options) => {
+                // Multi-slot jigs run a separate copy of the scenario on each slot.
+                if slot.is_some() || self.slot_count() > 1 {
+                    let scenario_name = match *scenario_name_opt {
+                        Some(ref scenario_name) => scenario_name.clone(),
+                        None => match *self.current_scenario.borrow() {
+                            None => {
+                                self.bc.broadcast(&UnitEvent::Log(LogEntry::new_error(sender_name.clone(), "unable to start scenario: no scenario selected and no scenario specified".to_owned())));
+                                return;
+                            },
+                            Some(ref scenario) => scenario.borrow().id().clone()
+                        }
+                    };
+                    match self.get_scenario_named(&scenario_name) {
+                        Some(scenario) => self.start_slot_scenario(sender_name, &scenario, slot, options),
+                        None => self.bc.broadcast(&UnitEvent::Log(LogEntry::new_error(sender_name.clone(), format!("unable to find scenario {}", scenario_name)))),
                     }
+                    return;
+                }
+
+                // If a scenario exists and is running, don't start a new one.
+                if self.scenario_is_running() {
+                    self.bc.broadcast(&UnitEvent::Log(LogEntry::new_error(sender_name.clone(), "unable to start scenario: scenario not idle".to_owned())));
+                    return;
                 }
                 let scenario_name = if let Some(ref scenario_name) = *scenario_name_opt {
                     self.select(scenario_name);
@@ -919,21 +1488,131 @@ impl UnitManager {
                     }
                 };
 
+                if let Some(scenario) = self.get_scenario_named(&scenario_name) {
+                    let result = scenario.borrow_mut().set_run_options(self, options);
+                    if let Err(e) = result {
+                        self.bc.broadcast(&UnitEvent::Log(LogEntry::new_error(sender_name.clone(), format!("unable to start scenario: {}", e))));
+                        return;
+                    }
+                }
+
                 self.activate(&scenario_name);
-                self.broadcast_message(ManagerStatusMessage::Start(scenario_name));
+                if let Some(scenario) = self.get_scenario_named(&scenario_name) {
+                    self.record_assumptions(&*scenario.borrow(), None);
+                }
+                self.broadcast_message(ManagerStatusMessage::Start(scenario_name, None));
+                self.broadcast_eta(None);
             },
             ManagerControlMessageContents::Skip(ref test_name, ref reason) => {
-                self.broadcast_skipped(test_name, reason);
+                self.record_result(test_name, slot, "skip", reason, None);
+                self.broadcast_skipped(test_name, reason, slot);
             },
+            ManagerControlMessageContents::SetValue(ref key, ref value) => {
+                let scenario = match slot {
+                    Some(s) => self.get_slot_scenario(s),
+                    None => self.unslotted_scenario(),
+                };
+                match scenario {
+                    Some(sc) => {
+                        sc.borrow_mut().set_value(key, value);
+                        self.bc.broadcast(&UnitEvent::Log(LogEntry::new_info(sender_name.clone(), format!("set {}={}", key, value))));
+                    }
+                    None => self.bc.broadcast(&UnitEvent::Log(LogEntry::new_error(sender_name.clone(), format!("unable to set {}: no scenario is running", key)))),
+                }
+            }
+            ManagerControlMessageContents::Progress(percent, ref status) => {
+                self.broadcast_message(ManagerStatusMessage::Progress(sender_name.clone(), percent, status.clone(), slot));
+            }
+            ManagerControlMessageContents::Prompt(ref prompt, ref responses) => {
+                // A test only asks one thing at a time.
+                self.prompts.borrow_mut().retain(|&(ref name, s, _)| !(name == sender_name && s == slot));
+                self.prompts.borrow_mut().push((sender_name.clone(), slot, responses.clone()));
+                if responses.is_empty() {
+                    self.bc.broadcast(&UnitEvent::Log(LogEntry::new_info(sender_name.clone(), prompt.clone())));
+                } else {
+                    self.bc.broadcast(&UnitEvent::Log(LogEntry::new_info(sender_name.clone(), format!("{} [{}]", prompt, responses.join("/")))));
+                }
+                self.broadcast_message(ManagerStatusMessage::Prompt(sender_name.clone(), prompt.clone(), responses.clone(), slot));
+            }
+            ManagerControlMessageContents::Answer(ref test_name_opt, ref response) => {
+                // Find the prompt being answered, matching the test name and slot if they were given.
+                let pending = self.prompts.borrow().iter().find(|&&(ref name, s, _)| {
+                    test_name_opt.as_ref().map_or(true, |n| n == name) && (slot.is_none() || slot == s)
+                }).cloned();
+                let (test_name, test_slot, responses) = match pending {
+                    Some(p) => p,
+                    None => {
+                        self.bc.broadcast(&UnitEvent::Log(LogEntry::new_error(sender_name.clone(), "unable to answer: no test is waiting for an answer".to_owned())));
+                        return;
+                    }
+                };
+                let test = match test_slot {
+                    Some(s) => self.get_slot_test(s, &test_name).map(|(test, _)| test),
+                    None => self.get_test_named(&test_name),
+                };
+                let result = match test {
+                    _ if !responses.is_empty() && !responses.contains(&response.to_lowercase()) => {
+                        Err(format!("expected one of: {}", responses.join(", ")))
+                    }
+                    Some(test) => test.borrow().answer(response),
+                    None => Err("test not found".to_owned()),
+                };
+                match result {
+                    Ok(()) => {
+                        self.prompts.borrow_mut().retain(|&(ref name, s, _)| !(*name == test_name && s == test_slot));
+                        self.bc.broadcast(&UnitEvent::Log(LogEntry::new_info(sender_name.clone(), format!("answered {} for {}", response, test_name))));
+                    }
+                    Err(e) => self.bc.broadcast(&UnitEvent::Log(LogEntry::new_error(sender_name.clone(), format!("unable to answer {}: {}", test_name, e)))),
+                }
+            }
+            ManagerControlMessageContents::Values(ref values) => {
+                let list: Vec<String> = values.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+                self.bc.broadcast(&UnitEvent::Log(LogEntry::new_info(sender_name.clone(), format!("values: {}", list.join(" ")))));
+                self.broadcast_message(ManagerStatusMessage::Values(sender_name.clone(), values.clone(), slot));
+            }
+            // The manager has already announced the start, but other listeners
+            // on the broadcaster (such as metrics) count these.
+            ManagerControlMessageContents::ScenarioStarted => (),
+            ManagerControlMessageContents::ScenarioPaused(ref test_name) => {
+                self.bc.broadcast(&UnitEvent::Log(self.log_context(LogEntry::new_info(sender_name.clone(), format!("paused before starting {}", test_name)), slot)));
+                self.broadcast_message(ManagerStatusMessage::Paused(sender_name.clone(), slot));
+            }
             ManagerControlMessageContents::TestStarted => {
-                self.broadcast_message(ManagerStatusMessage::Running(sender_name.clone()));
+                self.test_start_times.borrow_mut().insert((sender_name.clone(), slot), Instant::now());
+                self.broadcast_message(ManagerStatusMessage::Running(sender_name.clone(), slot));
+                self.broadcast_eta(slot);
             }
             ManagerControlMessageContents::TestFinished(result, ref message) => {
+                let duration = self.record_test_duration(sender_name, slot, result);
+                self.prompts.borrow_mut().retain(|&(ref name, s, _)| !(name == sender_name && s == slot));
+                self.record_result(sender_name, slot, &match result {
+                    0 => "pass".to_owned(),
+                    i => format!("fail {}", i),
+                }, message, duration);
                 self.broadcast_message(match result {
-                    0 => ManagerStatusMessage::Pass(sender_name.clone(), message.clone()),
-                    i => ManagerStatusMessage::Fail(sender_name.clone(), i, message.clone()),
+                    0 => ManagerStatusMessage::Pass(sender_name.clone(), message.clone(), slot),
+                    i => ManagerStatusMessage::Fail(sender_name.clone(), i, message.clone(), slot),
                 });
             }
+            ManagerControlMessageContents::ScenarioFinished(code, ref message) if slot.is_some() => {
+                let slot = slot.unwrap();
+                if let Some(slot_scenario) = self.slot_scenarios.borrow_mut().remove(&slot) {
+                    self.broadcast_run_status(sender_name, Some(slot), format!("scenario ended on slot {}", slot));
+                    if let Err(e) = slot_scenario.borrow().deactivate() {
+                        self.bc.broadcast(&UnitEvent::Log(LogEntry::new_error(sender_name.clone(), format!("unable to deactivate: {}", e))));
+                    }
+                }
+                self.broadcast_finished(sender_name, code, message, Some(slot));
+            }
+            ManagerControlMessageContents::ScenarioFinished(code, ref message) if self.test_run.borrow().is_some() => {
+                if let Some(test_run) = self.test_run.borrow_mut().take() {
+                    self.broadcast_run_status(sender_name, None, "test run ended".to_owned());
+                    if let Err(e) = test_run.borrow().deactivate() {
+                        self.bc.broadcast(&UnitEvent::Log(LogEntry::new_error(sender_name.clone(), format!("unable to deactivate: {}", e))));
+                    }
+                }
+                self.broadcast_finished(sender_name, code, message, None);
+            }
             ManagerControlMessageContents::ScenarioFinished(code, ref message) => {
                 // Deactivate the current scenario.
                 // Since a scenario is finishing, the current scenario MUST not be None.
@@ -941,20 +1620,82 @@ impl UnitManager {
                     let cs = self.current_scenario.borrow();
                     self.deactivate(cs.as_ref().unwrap().borrow().id(), &message);
                 }
-                self.broadcast_finished(sender_name, code, message);
+                self.broadcast_finished(sender_name, code, message, None);
+            }
+            ManagerControlMessageContents::StartTest(ref test_name) if slot.is_some() => {
+                let slot = slot.unwrap();
+                match self.get_slot_test(slot, test_name) {
+                    None => self.bc.broadcast(&UnitEvent::Log(LogEntry::new_error(test_name.clone(), format!("test not found on slot {}", slot)))),
+                    Some((test, environment)) => {
+                        let result = test.borrow_mut().activate(self, &*self.cfg.lock().unwrap(), &environment);
+                        if let Err(e) = result {
+                            self.bc.broadcast(&UnitEvent::Log(LogEntry::new_error(test_name.clone(), format!("unable to activate on slot {}: {}", slot, e))));
+                        }
+                    }
+                }
             }
             ManagerControlMessageContents::StartTest(ref test_name) => {
                 self.activate(test_name);
             }
+            ManagerControlMessageContents::StopTest(ref test_name) if slot.is_some() => {
+                // Scenarios on slots aren't tracked as active units, so only tests need stopping.
+                if let Some((test, _)) = self.get_slot_test(slot.unwrap(), test_name) {
+                    if let Err(e) = test.borrow_mut().deactivate(self) {
+                        self.bc.broadcast(&UnitEvent::Log(LogEntry::new_error(test_name.clone(), format!("unable to deactivate: {}", e))));
+                    }
+                }
+            }
             ManagerControlMessageContents::StopTest(ref test_name) => {
                 self.deactivate(test_name, "controller requested test stop");
             }
+            ManagerControlMessageContents::RunTest(ref test_name) => self.run_test(sender_name, test_name, slot),
+            ManagerControlMessageContents::Pause
+            | ManagerControlMessageContents::Resume
+            | ManagerControlMessageContents::Step => {
+                let running_scenarios = self.running_scenarios(slot);
+                if running_scenarios.is_empty() {
+                    self.bc.broadcast(&UnitEvent::Log(LogEntry::new_error(sender_name.clone(), "unable to pause or resume: no scenario is running".to_owned())));
+                    return;
+                }
+                for scenario_rc in running_scenarios {
+                    // The scenario reports being paused once it holds on to a test.
+                    let scenario = scenario_rc.borrow();
+                    match *msg {
+                        ManagerControlMessageContents::Pause => scenario.pause(),
+                        ManagerControlMessageContents::Step => {
+                            // Stepping lets a held test run, so the scenario is running again until it finishes.
+                            let was_paused = scenario.is_paused();
+                            scenario.step(&self.control_sender);
+                            if was_paused {
+                                self.broadcast_message(ManagerStatusMessage::Resumed(scenario.id().clone(), scenario.slot()));
+                            }
+                        }
+                        _ => {
+                            scenario.resume(&self.control_sender);
+                            self.broadcast_message(ManagerStatusMessage::Resumed(scenario.id().clone(), scenario.slot()));
+                        }
+                    }
+                }
+            }
+            ManagerControlMessageContents::Abort => {
+                let running_scenarios = self.running_scenarios(slot);
+                if running_scenarios.is_empty() {
+                    self.bc.broadcast(&UnitEvent::Log(LogEntry::new_error(sender_name.clone(), "unable to abort: no scenario is running".to_owned())));
+                    return;
+                }
+                for scenario_rc in running_scenarios {
+                    let scenario = scenario_rc.borrow();
+                    self.bc.broadcast(&UnitEvent::Log(self.log_context(LogEntry::new_info(sender_name.clone(), format!("aborting {}", scenario.id())), scenario.slot())));
+                    scenario.abort(&self.control_sender);
+                }
+            }
             ManagerControlMessageContents::Shutdown(ref reason) => {
                 let txt = match reason {
                     &None => format!("shutdown requested (no reason given)"),
                     &Some(ref s) => format!("shutdown requested: {}", s),
                 };
                 self.bc.broadcast(&UnitEvent::Log(LogEntry::new_info(sender_name.clone(), txt)));
+                self.stop_slot_scenarios(None, "shutting down");
                 self.bc.broadcast(&UnitEvent::Shutdown);
             }
         }
@@ -969,11 +1710,15 @@ impl UnitManager {
             None => vec![ManagerStatusMessage::Jig(None)],
             Some(ref jig_rc) => {
                 let jig = jig_rc.borrow();
-                vec![
+                let mut messages = vec![
                     ManagerStatusMessage::Jig(Some(jig.id().clone())),
                     ManagerStatusMessage::Describe(jig.id().clone(), FieldType::Name, jig.name().clone()),
                     ManagerStatusMessage::Describe(jig.id().clone(), FieldType::Description, jig.description().clone())
-                ]
+                ];
+                if jig.slots() > 1 {
+                    messages.push(ManagerStatusMessage::Slots(jig.slots()));
+                }
+                messages
             }
         };
         self.send_messages_to(sender_name, messages);
@@ -1031,6 +1776,57 @@ impl UnitManager {
         }
     }
 
+    /// Send a list of every known unit, with its status and the reason it last failed.
+    pub fn send_units_to(&self, sender_name: &UnitName) {
+        let mut units: Vec<(UnitName, UnitStatus, Option<String>)> = self.unit_status
+            .borrow()
+            .iter()
+            .map(|(name, &(ref status, ref failure))| (name.clone(), status.clone(), failure.clone()))
+            .collect();
+        units.sort_by(|a, b| a.0.cmp(&b.0));
+        self.send_messages_to(sender_name, vec![ManagerStatusMessage::Units(units)]);
+    }
+
+    /// Gather statistics on another thread, as reading every saved run can take a
+    /// while.  They come back as a StatsLoaded message from the unit that asked.
+    fn load_stats(&self, sender_name: &UnitName, window: &StatsWindow) {
+        let root = match *self.cfg.lock().unwrap().artifact_root() {
+            Some(ref r) => r.clone(),
+            None => {
+                return self.send_stats_to(sender_name, Err("statistics are only available with --artifact-dir".to_owned()))
+            }
+        };
+        let control_sender = self.control_sender.clone();
+        let sender_name = sender_name.clone();
+        let window = window.clone();
+        thread::spawn(move || {
+            let stats = YieldStats::load(&root, &window).map_err(|e| format!("unable to read run results: {}", e));
+            control_sender
+                .send(ManagerControlMessage::new(&sender_name, ManagerControlMessageContents::StatsLoaded(stats)))
+                .ok();
+        });
+    }
+
+    /// Send yield statistics over the runs saved in the artifact directory.
+    /// Anything other than an interface, such as the terminal, gets them as
+    /// log entries.
+    pub fn send_stats_to(&self, sender_name: &UnitName, stats: Result<YieldStats, String>) {
+        match (stats, sender_name.kind()) {
+            (Err(e), _) => self.bc.broadcast(&UnitEvent::Log(LogEntry::new_error(sender_name.clone(), e))),
+            (Ok(stats), &UnitKind::Interface) => {
+                // The interface may have gone away while the statistics were gathered.
+                if self.interfaces.borrow().contains_key(sender_name) {
+                    self.send_messages_to(sender_name, vec![ManagerStatusMessage::Stats(stats)])
+                }
+            }
+            (Ok(stats), _) => {
+                for line in stats.report() {
+                    self.bc.broadcast(&UnitEvent::Log(LogEntry::new_info(sender_name.clone(), line)));
+                }
+            }
+        }
+    }
+
     fn broadcast_selected_jig(&self) {
         let jig_opt = self.current_jig.borrow();
         match *jig_opt {
@@ -1038,9 +1834,12 @@ impl UnitManager {
             Some(ref j) => {
                 let jig = j.borrow();
                 for (interface_id, _) in self.interfaces.borrow().iter() {
-                    let messages = vec![
+                    let mut messages = vec![
                         ManagerStatusMessage::Jig(Some(jig.id().clone()))
                     ];
+                    if jig.slots() > 1 {
+                        messages.push(ManagerStatusMessage::Slots(jig.slots()));
+                    }
                     self.send_messages_to(interface_id, messages);
                 }
             }
@@ -1124,24 +1923,166 @@ impl UnitManager {
         }
     }
 
-    fn broadcast_skipped(&self, unit_id: &UnitName, reason: &String) {
-        let msg = ManagerStatusMessage::Skipped(unit_id.clone(), reason.clone());
-        for (interface_id, _) in self.interfaces.borrow().iter() {
-            self.send_messages_to(interface_id, vec![msg.clone()]);
+    fn broadcast_skipped(&self, unit_id: &UnitName, reason: &String, slot: Option<u32>) {
+        self.broadcast_message(ManagerStatusMessage::Skipped(unit_id.clone(), reason.clone(), slot));
+    }
+
+    fn broadcast_finished(&self, unit_id: &UnitName, code: u32, message: &String, slot: Option<u32>) {
+        // Scenario results are HTTP-style codes, with 200 meaning success.
+        self.record_result(unit_id, slot, &match code {
+            200 => "pass".to_owned(),
+            i => format!("fail {}", i),
+        }, message, None);
+        self.broadcast_message(ManagerStatusMessage::Finished(unit_id.clone(), code, message.clone(), slot));
+        self.runs.borrow_mut().remove(&slot);
+
+        // Sent as a message so that it comes after any log entries already broadcast.
+        self.control_sender
+            .send(ManagerControlMessage::new_with_slot(unit_id, slot, ManagerControlMessageContents::SyncLogs))
+            .ok();
+    }
+
+    /// Give a scenario run that is starting on a slot its own run number,
+    /// along with a directory to save its output in if one was configured.
+    fn begin_run(&self, scenario: &mut Scenario, slot: Option<u32>) {
+        let run = self.last_run.get() + 1;
+        self.last_run.set(run);
+        self.runs.borrow_mut().insert(slot, (scenario.id().clone(), run));
+
+        self.artifacts.borrow_mut().remove(&slot);
+        let (root, retain) = {
+            let cfg = self.cfg.lock().unwrap();
+            (cfg.artifact_root().clone(), cfg.artifact_retain())
+        };
+        if let Some(root) = root {
+            match ArtifactDir::create(&root, scenario.id(), slot, run) {
+                Ok(dir) => {
+                    scenario.set_artifact_dir(Some(dir.path()));
+                    self.artifacts.borrow_mut().insert(slot, dir);
+                    let artifacts = self.artifacts.borrow();
+                    let in_progress: Vec<&Path> = artifacts.values().map(|x| x.path()).collect();
+                    if let Err(e) = ArtifactDir::prune(&root, retain, &in_progress) {
+                        self.bc.broadcast(&UnitEvent::Log(LogEntry::new_error(
+                            scenario.id().clone(),
+                            format!("unable to remove old artifact directories: {}", e),
+                        )));
+                    }
+                    return;
+                }
+                Err(e) => self.bc.broadcast(&UnitEvent::Log(LogEntry::new_error(
+                    scenario.id().clone(),
+                    format!("unable to create artifact directory: {}", e),
+                ))),
+            }
         }
+        scenario.set_artifact_dir(None);
     }
 
-    fn broadcast_finished(&self, unit_id: &UnitName, code: u32, message: &String) {
-        let msg = ManagerStatusMessage::Finished(unit_id.clone(), code, message.clone());
-        for (interface_id, _) in self.interfaces.borrow().iter() {
-            self.send_messages_to(interface_id, vec![msg.clone()]);
+    /// Save a result to the artifact directory of the run in progress on a slot.
+    fn record_result(&self, unit_id: &UnitName, slot: Option<u32>, result: &str, message: &str, duration: Option<Duration>) {
+        if let Some(dir) = self.artifacts.borrow().get(&slot) {
+            dir.record_result(unit_id, result, message, duration).ok();
         }
     }
 
+    /// Add the tests that were assumed when the run was started to its results.
+    fn record_assumptions(&self, scenario: &Scenario, slot: Option<u32>) {
+        for test_name in &scenario.run_options().assume {
+            self.record_result(test_name, slot, "assume", "assumed to have passed when starting the scenario", None);
+        }
+    }
+
+    /// Tag a log entry with the scenario run in progress on its slot, if there is one.
+    fn log_context(&self, entry: LogEntry, slot: Option<u32>) -> LogEntry {
+        match self.runs.borrow().get(&slot) {
+            Some(&(ref scenario_name, run)) => entry.with_context(scenario_name.clone(), slot, run),
+            None => entry,
+        }
+    }
+
+    /// The scenario run that a scenario or test result belongs to, if it is part of one.
+    fn result_run(&self, msg: &ManagerStatusMessage) -> Option<(UnitName, u64)> {
+        let slot = match *msg {
+            ManagerStatusMessage::Start(_, slot)
+            | ManagerStatusMessage::Running(_, slot)
+            | ManagerStatusMessage::Pass(_, _, slot)
+            | ManagerStatusMessage::Fail(_, _, _, slot)
+            | ManagerStatusMessage::Skipped(_, _, slot)
+            | ManagerStatusMessage::Finished(_, _, _, slot) => slot,
+            _ => return None,
+        };
+        self.runs.borrow().get(&slot).cloned()
+    }
+
+    /// Log a scenario run starting or stopping.
+    fn broadcast_run_status(&self, scenario_name: &UnitName, slot: Option<u32>, message: String) {
+        let mut entry = LogEntry::new_status(scenario_name.clone(), message);
+        if let Some(&(_, run)) = self.runs.borrow().get(&slot) {
+            entry = entry.with_context(scenario_name.clone(), slot, run);
+        }
+        self.bc.broadcast(&UnitEvent::Log(entry));
+    }
+
+    /// Work out how long a test took, and if it passed, remember it so later runs
+    /// can estimate how long is left.  Failures aren't remembered, since they tend
+    /// to end early, and neither are daemons, as they keep running until the
+    /// scenario finishes.
+    fn record_test_duration(&self, test_name: &UnitName, slot: Option<u32>, result: i32) -> Option<Duration> {
+        let duration = self.test_start_times.borrow_mut().remove(&(test_name.clone(), slot))?.elapsed();
+        let is_daemon = self.get_test_named(test_name).map(|t| t.borrow().is_daemon()).unwrap_or(false);
+        if result == 0 && !is_daemon {
+            self.test_durations.borrow_mut().insert(test_name.clone(), duration);
+        }
+        Some(duration)
+    }
+
+    /// Estimate how long is left in the scenario running on a slot (or the unslotted
+    /// scenario), and tell interfaces about it.  Nothing is sent unless every test
+    /// that's left has run before.
+    fn broadcast_eta(&self, slot: Option<u32>) {
+        let scenario = match slot {
+            Some(s) => self.get_slot_scenario(s),
+            None => self.unslotted_scenario(),
+        };
+        let scenario = match scenario {
+            Some(sc) => sc,
+            None => return,
+        };
+        let scenario = scenario.borrow();
+
+        let mut remaining = Duration::from_secs(0);
+        for test in scenario.remaining_tests() {
+            let test = test.borrow();
+            if test.is_daemon() {
+                continue;
+            }
+            let last = match self.test_durations.borrow().get(test.id()) {
+                Some(&d) => d,
+                None => return,
+            };
+            let elapsed = match self.test_start_times.borrow().get(&(test.id().clone(), slot)) {
+                Some(start_time) => start_time.elapsed(),
+                None => Duration::from_secs(0),
+            };
+            if last > elapsed {
+                remaining += last - elapsed;
+            }
+        }
+
+        let seconds = remaining.as_secs() + if remaining.subsec_nanos() >= 500_000_000 { 1 } else { 0 };
+        self.bc.broadcast(&UnitEvent::Log(LogEntry::new_info(scenario.id().clone(), format!("estimated time remaining: {}s", seconds))));
+        self.broadcast_message(ManagerStatusMessage::Eta(scenario.id().clone(), seconds, slot));
+    }
+
     fn broadcast_message(&self, msg: ManagerStatusMessage) {
         for (interface_id, _) in self.interfaces.borrow().iter() {
             self.send_messages_to(interface_id, vec![msg.clone()]);
         }
+        // Loggers only write out the results they asked for.
+        let run = self.result_run(&msg);
+        for (_, logger) in self.loggers.borrow().iter() {
+            logger.borrow().output_message(msg.clone(), run.clone()).ok();
+        }
     }
 
     /// Send a Vec<ManagerStatusMessage> to a specific endpoint.
@@ -1,7 +1,9 @@
 extern crate runny;
+extern crate serde_json;
 extern crate systemd_parser;
 
 use std::cell::RefCell;
+use std::convert::TryFrom;
 use std::fs::File;
 use std::io::{BufRead, BufReader, Error, ErrorKind, Read, Write};
 use std::path::{Path, PathBuf};
@@ -10,13 +12,20 @@ use std::thread;
 use std::time::Duration;
 
 use config::Config;
+#[cfg(unix)]
+use controlsocket::ControlSocket;
+use httpserver::HttpServer;
+use logfilter::LogFilter;
 use unit::{
     UnitActivateError, UnitDeactivateError, UnitDescriptionError, UnitDeselectError,
-    UnitIncompatibleReason, UnitName, UnitSelectError,
+    RestartPolicy, UnitIncompatibleReason, UnitName, UnitSelectError,
 };
+use unitbroadcaster::LogSource;
+use units::scenario::RunOptions;
 use unitmanager::{
     ManagerControlMessage, ManagerControlMessageContents, ManagerStatusMessage, UnitManager,
 };
+use yieldstats::{percent, StatsWindow};
 
 use self::runny::running::{Running, RunningOutput};
 use self::runny::Runny;
@@ -28,6 +37,27 @@ enum InterfaceFormat {
     JSON,
 }
 
+#[derive(Clone, Copy, PartialEq)]
+enum InterfaceType {
+    /// Run ExecStart, and talk to it over stdin and stdout
+    Program,
+
+    /// Serve a REST API and a WebSocket event stream over HTTP
+    Http,
+
+    /// Accept connections on a Unix domain socket, each speaking the text or JSON protocol
+    Socket,
+}
+
+/// An interface that exclave serves itself, rather than running a program.
+pub trait InterfaceServer {
+    /// Pass a status message on to the server's clients.
+    fn output_message(&self, msg: ManagerStatusMessage) -> Result<(), Error>;
+
+    /// Stop accepting connections and disconnect all clients.
+    fn stop(&self);
+}
+
 /// A struct defining an in-memory representation of a .Interface file
 #[derive(Clone)]
 pub struct InterfaceDescription {
@@ -43,6 +73,12 @@ pub struct InterfaceDescription {
     /// A Vec<String> of jig names that this test is compatible with.
     jigs: Vec<UnitName>,
 
+    /// Whether this is an external program or the built-in HTTP server
+    kind: InterfaceType,
+
+    /// The address or socket path to listen on, for built-in interfaces
+    listen: Option<String>,
+
     /// Path to the command to start the interface
     exec_start: String,
 
@@ -54,6 +90,12 @@ pub struct InterfaceDescription {
 
     /// The path of the unit file
     unit_directory: PathBuf,
+
+    /// What to do when the process exits on its own
+    restart: RestartPolicy,
+
+    /// Which log entries this interface should receive
+    log_filter: LogFilter,
 }
 
 impl InterfaceDescription {
@@ -63,6 +105,10 @@ impl InterfaceDescription {
         // Parse the file into a systemd unit_file object
         let mut contents = String::with_capacity(8192);
         File::open(path)?.read_to_string(&mut contents)?;
+        Self::from_string(&contents, unit_name, path)
+    }
+
+    pub fn from_string(contents: &str, unit_name: UnitName, path: &Path) -> Result<InterfaceDescription, UnitDescriptionError> {
         let unit_file = systemd_parser::parse_string(&contents)?;
 
         if !unit_file.has_category("Interface") {
@@ -75,9 +121,13 @@ impl InterfaceDescription {
             description: "".to_owned(),
             jigs: vec![],
             format: InterfaceFormat::Text,
+            kind: InterfaceType::Program,
+            listen: None,
             exec_start: "".to_owned(),
             working_directory: None,
             unit_directory: path.parent().unwrap().to_owned(),
+            restart: RestartPolicy::new(),
+            log_filter: LogFilter::default(),
         };
 
         for entry in unit_file.lookup_by_category("Interface") {
@@ -116,6 +166,42 @@ impl InterfaceDescription {
                             }
                         }
                     }
+                    "Type" => {
+                        interface_description.kind = match directive.value() {
+                            None => InterfaceType::Program,
+                            Some(s) => match s.to_string().to_lowercase().as_ref() {
+                                "program" => InterfaceType::Program,
+                                "http" => InterfaceType::Http,
+                                "socket" => InterfaceType::Socket,
+                                other => {
+                                    return Err(UnitDescriptionError::InvalidValue(
+                                        "Interface".to_owned(),
+                                        "Type".to_owned(),
+                                        other.to_owned(),
+                                        vec!["program".to_owned(), "http".to_owned(), "socket".to_owned()],
+                                    ))
+                                }
+                            },
+                        }
+                    }
+                    "Listen" => {
+                        interface_description.listen = match directive.value() {
+                            Some(s) => Some(s.to_owned()),
+                            None => {
+                                return Err(UnitDescriptionError::MissingValue(
+                                    "Interface".to_owned(),
+                                    "Listen".to_owned(),
+                                ))
+                            }
+                        }
+                    }
+                    "LogFilter" => {
+                        interface_description.log_filter = LogFilter::from_str(
+                            "Interface",
+                            "LogFilter",
+                            directive.value().unwrap_or(""),
+                        )?
+                    }
                     "Format" => {
                         interface_description.format = match directive.value() {
                             None => InterfaceFormat::Text,
@@ -133,7 +219,9 @@ impl InterfaceDescription {
                             },
                         }
                     }
-                    &_ => (),
+                    key => {
+                        interface_description.restart.parse_directive("Interface", key, directive.value())?;
+                    }
                 },
                 &_ => (),
             }
@@ -181,6 +269,7 @@ impl InterfaceDescription {
 pub struct Interface {
     desc: InterfaceDescription,
     process: RefCell<Option<Running>>,
+    server: RefCell<Option<Box<dyn InterfaceServer>>>,
     terminate_timeout: Duration,
 }
 
@@ -189,6 +278,7 @@ impl Interface {
         Interface {
             desc: desc.clone(),
             process: RefCell::new(None),
+            server: RefCell::new(None),
             terminate_timeout: config.terminate_timeout().clone(),
         }
     }
@@ -197,6 +287,14 @@ impl Interface {
         &self.desc.id
     }
 
+    pub fn restart_policy(&self) -> &RestartPolicy {
+        &self.desc.restart
+    }
+
+    pub fn log_filter(&self) -> &LogFilter {
+        &self.desc.log_filter
+    }
+
     pub fn select(&self) -> Result<(), UnitSelectError> {
         Ok(())
     }
@@ -210,6 +308,26 @@ impl Interface {
         manager: &UnitManager,
         config: &Config,
     ) -> Result<(), UnitActivateError> {
+        if self.desc.kind != InterfaceType::Program {
+            let control = manager.get_control_channel();
+            let server: Box<dyn InterfaceServer> = match self.desc.kind {
+                InterfaceType::Socket => Self::start_socket(&self.desc.listen, self.id(), control)?,
+                _ => Box::new(HttpServer::start(
+                    self.desc.listen.as_ref().map(|x| x.as_str()).unwrap_or("127.0.0.1:8080"),
+                    self.id(),
+                    control,
+                )?),
+            };
+            *self.server.borrow_mut() = Some(server);
+            manager
+                .get_control_channel()
+                .send(ManagerControlMessage::new(
+                    self.id(),
+                    ManagerControlMessageContents::InitialGreeting,
+                )).ok();
+            return Ok(());
+        }
+
         let mut running = Runny::new(&self.desc.exec_start)
             .directory(&Some(config.working_directory(&self.desc.unit_directory, &self.desc.working_directory)))
             .start()?;
@@ -231,7 +349,12 @@ impl Interface {
                 thread::spawn(move || Self::text_read_stderr(thr_sender_id, thr_sender, stderr));
             }
             InterfaceFormat::JSON => {
-                ();
+                let thr_sender_id = control_sender_id.clone();
+                let thr_sender = control_sender.clone();
+                thread::spawn(move || Self::json_read(thr_sender_id, thr_sender, stdout));
+                let thr_sender_id = control_sender_id.clone();
+                let thr_sender = control_sender.clone();
+                thread::spawn(move || Self::text_read_stderr(thr_sender_id, thr_sender, stderr));
             }
         };
 
@@ -247,7 +370,29 @@ impl Interface {
         Ok(())
     }
 
+    #[cfg(unix)]
+    fn start_socket(
+        path: &Option<String>,
+        id: &UnitName,
+        control: Sender<ManagerControlMessage>,
+    ) -> Result<Box<dyn InterfaceServer>, Error> {
+        let path = path.as_ref().map(|x| x.as_str()).unwrap_or("/run/exclave.sock");
+        Ok(Box::new(ControlSocket::start(Path::new(path), id, control)?))
+    }
+
+    #[cfg(not(unix))]
+    fn start_socket(
+        _: &Option<String>,
+        _: &UnitName,
+        _: Sender<ManagerControlMessage>,
+    ) -> Result<Box<dyn InterfaceServer>, Error> {
+        Err(Error::new(ErrorKind::Other, "socket interfaces are only supported on Unix"))
+    }
+
     pub fn deactivate(&self) -> Result<(), UnitDeactivateError> {
+        if let Some(server) = self.server.borrow_mut().take() {
+            server.stop();
+        }
         if let Some(process) = self.process.borrow_mut().take() {
             match process.terminate(Some(self.terminate_timeout)) {
                 Ok(retval) => match retval {
@@ -263,14 +408,162 @@ impl Interface {
 
     /// Cause a MessageControlContents to be written out.
     pub fn output_message(&self, msg: ManagerStatusMessage) -> Result<(), Error> {
+        if let Some(ref server) = *self.server.borrow() {
+            return server.output_message(msg);
+        }
         match self.desc.format {
             InterfaceFormat::Text => self.text_write(msg),
             InterfaceFormat::JSON => self.json_write(msg),
         }
     }
 
-    fn json_write(&self, _: ManagerStatusMessage) -> Result<(), Error> {
-        unimplemented!();
+    /// Write a ManagerStatusMessage as a single-line JSON object.
+    fn json_write(&self, msg: ManagerStatusMessage) -> Result<(), Error> {
+        let mut process_opt = self.process.borrow_mut();
+
+        if process_opt.is_none() {
+            return Err(Error::new(ErrorKind::Other, "no process running"));
+        }
+
+        let process = process_opt.as_mut().unwrap();
+        writeln!(process, "{}", Self::json_object(msg))
+    }
+
+    /// Convert a ManagerStatusMessage into the JSON object sent to JSON-format interfaces.
+    pub fn json_object(msg: ManagerStatusMessage) -> serde_json::Value {
+        match msg {
+            ManagerStatusMessage::Jig(j) => json!({
+                "type": "jig",
+                "jig": j.map(|x| x.id().to_owned()),
+            }),
+            ManagerStatusMessage::Hello(id) => json!({"type": "hello", "id": id}),
+            ManagerStatusMessage::Tests(scenario, tests) => json!({
+                "type": "tests",
+                "scenario": scenario.id(),
+                "tests": tests.iter().map(|x| x.id().to_owned()).collect::<Vec<String>>(),
+            }),
+            ManagerStatusMessage::Scenario(name) => json!({
+                "type": "scenario",
+                "scenario": name.map(|x| x.id().to_owned()),
+            }),
+            ManagerStatusMessage::Scenarios(list) => json!({
+                "type": "scenarios",
+                "scenarios": list.iter().map(|x| x.id().to_owned()).collect::<Vec<String>>(),
+            }),
+            ManagerStatusMessage::Describe(id, field, value) => json!({
+                "type": "describe",
+                "kind": format!("{}", id.kind()),
+                "field": format!("{}", field),
+                "id": id.id(),
+                "value": value,
+            }),
+            ManagerStatusMessage::Log(l) => json!({"type": "log", "entry": l}),
+            ManagerStatusMessage::Slots(count) => json!({"type": "slots", "slots": count}),
+            ManagerStatusMessage::Running(test, slot) => json!({
+                "type": "running",
+                "test": test.id(),
+                "slot": slot,
+            }),
+            ManagerStatusMessage::Skipped(test, reason, slot) => json!({
+                "type": "skip",
+                "test": test.id(),
+                "reason": reason,
+                "slot": slot,
+            }),
+            ManagerStatusMessage::Finished(scenario, result, reason, slot) => json!({
+                "type": "finish",
+                "scenario": scenario.id(),
+                "result": result,
+                "reason": reason,
+                "slot": slot,
+            }),
+            ManagerStatusMessage::Fail(test, code, reason, slot) => json!({
+                "type": "fail",
+                "test": test.id(),
+                "code": code,
+                "reason": reason,
+                "slot": slot,
+            }),
+            ManagerStatusMessage::Pass(test, reason, slot) => json!({
+                "type": "pass",
+                "test": test.id(),
+                "reason": reason,
+                "slot": slot,
+            }),
+            ManagerStatusMessage::Start(scenario, slot) => json!({
+                "type": "start",
+                "scenario": scenario.id(),
+                "slot": slot,
+            }),
+            ManagerStatusMessage::Paused(scenario, slot) => json!({
+                "type": "paused",
+                "scenario": scenario.id(),
+                "slot": slot,
+            }),
+            ManagerStatusMessage::Resumed(scenario, slot) => json!({
+                "type": "resumed",
+                "scenario": scenario.id(),
+                "slot": slot,
+            }),
+            ManagerStatusMessage::Eta(scenario, seconds, slot) => json!({
+                "type": "eta",
+                "scenario": scenario.id(),
+                "seconds": seconds,
+                "slot": slot,
+            }),
+            ManagerStatusMessage::Progress(test, percent, status, slot) => json!({
+                "type": "progress",
+                "test": test.id(),
+                "percent": percent,
+                "status": status,
+                "slot": slot,
+            }),
+            ManagerStatusMessage::Prompt(test, prompt, responses, slot) => json!({
+                "type": "prompt",
+                "test": test.id(),
+                "prompt": prompt,
+                "responses": responses,
+                "slot": slot,
+            }),
+            ManagerStatusMessage::Values(scenario, values, slot) => json!({
+                "type": "values",
+                "scenario": scenario.id(),
+                "values": values,
+                "slot": slot,
+            }),
+            ManagerStatusMessage::Units(units) => json!({
+                "type": "units",
+                "units": units.iter().map(|&(ref name, ref status, ref failure)| json!({
+                    "unit": name.id(),
+                    "kind": format!("{}", name.kind()),
+                    "status": status.as_str(),
+                    "detail": format!("{}", status),
+                    "last_failure": failure,
+                })).collect::<Vec<serde_json::Value>>(),
+            }),
+            ManagerStatusMessage::Stats(stats) => json!({
+                "type": "stats",
+                "window": format!("{}", stats.window),
+                "runs": stats.runs,
+                "scenarios": stats.scenarios.iter().map(|x| json!({
+                    "scenario": x.scenario,
+                    "runs": x.runs,
+                    "passed": x.passed,
+                    "yield": percent(x.passed, x.runs),
+                })).collect::<Vec<serde_json::Value>>(),
+                "tests": stats.tests.iter().map(|x| json!({
+                    "test": x.test,
+                    "runs": x.runs,
+                    "failures": x.failures,
+                    "failure_rate": percent(x.failures, x.runs),
+                })).collect::<Vec<serde_json::Value>>(),
+                "messages": stats.messages.iter().map(|x| json!({
+                    "test": x.test,
+                    "message": x.message,
+                    "count": x.count,
+                })).collect::<Vec<serde_json::Value>>(),
+            }),
+        }
     }
 
     fn cfti_escape(msg: &String) -> String {
@@ -289,7 +582,11 @@ impl Interface {
         }
 
         let process = process_opt.as_mut().unwrap();
+        Self::text_format(process, msg)
+    }
 
+    /// Write a ManagerStatusMessage as a line of the text protocol.
+    pub fn text_format<W: Write>(process: &mut W, msg: ManagerStatusMessage) -> Result<(), Error> {
         match msg {
             ManagerStatusMessage::Jig(j) => match j {
                 Some(jig_name) => writeln!(
@@ -327,44 +624,113 @@ impl Interface {
             ),
             ManagerStatusMessage::Log(l) => writeln!(
                 process,
-                "LOG {}\t{}\t{}\t{}\t{}\t{}",
+                "LOG {}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
                 l.kind().as_str(),
                 Self::cfti_escape(l.id().id()),
                 l.id().kind(),
                 l.secs(),
                 l.nsecs(),
-                Self::cfti_escape(l.message())
+                Self::cfti_escape(l.message()),
+                l.source().as_str(),
+                l.scenario().as_ref().map(|x| Self::cfti_escape(x.id())).unwrap_or_default(),
+                l.slot().map(|x| x.to_string()).unwrap_or_default(),
+                l.run().map(|x| x.to_string()).unwrap_or_default()
             ),
-            ManagerStatusMessage::Running(test) => {
-                writeln!(process, "RUNNING {}", Self::cfti_escape(test.id()))
+            ManagerStatusMessage::Slots(count) => writeln!(process, "SLOTS {}", count),
+            ManagerStatusMessage::Running(test, slot) => {
+                writeln!(process, "RUNNING {}{}", Self::cfti_escape(test.id()), Self::slot_suffix(slot))
             }
-            ManagerStatusMessage::Skipped(test, reason) => writeln!(
+            ManagerStatusMessage::Skipped(test, reason, slot) => writeln!(
                 process,
-                "SKIP {} {}",
+                "SKIP {}{} {}",
                 Self::cfti_escape(test.id()),
+                Self::slot_suffix(slot),
                 Self::cfti_escape(&reason)
             ),
-            ManagerStatusMessage::Finished(scenario, result, reason) => writeln!(
+            ManagerStatusMessage::Finished(scenario, result, reason, slot) => writeln!(
                 process,
-                "FINISH {} {} {}",
+                "FINISH {}{} {} {}",
                 Self::cfti_escape(scenario.id()),
+                Self::slot_suffix(slot),
                 result,
                 Self::cfti_escape(&reason)
             ),
-            ManagerStatusMessage::Fail(test, _code, reason) => writeln!(
+            ManagerStatusMessage::Fail(test, _code, reason, slot) => writeln!(
                 process,
-                "FAIL {} {}",
+                "FAIL {}{} {}",
                 Self::cfti_escape(test.id()),
+                Self::slot_suffix(slot),
                 Self::cfti_escape(&reason)
             ),
-            ManagerStatusMessage::Pass(test, reason) => writeln!(
+            ManagerStatusMessage::Pass(test, reason, slot) => writeln!(
                 process,
-                "PASS {} {}",
+                "PASS {}{} {}",
                 Self::cfti_escape(test.id()),
+                Self::slot_suffix(slot),
                 Self::cfti_escape(&reason)
             ),
-            ManagerStatusMessage::Start(scenario) => {
-                writeln!(process, "START {}", Self::cfti_escape(scenario.id()))
+            ManagerStatusMessage::Start(scenario, slot) => {
+                writeln!(process, "START {}{}", Self::cfti_escape(scenario.id()), Self::slot_suffix(slot))
+            }
+            ManagerStatusMessage::Paused(scenario, slot) => {
+                writeln!(process, "PAUSED {}{}", Self::cfti_escape(scenario.id()), Self::slot_suffix(slot))
+            }
+            ManagerStatusMessage::Resumed(scenario, slot) => {
+                writeln!(process, "RESUMED {}{}", Self::cfti_escape(scenario.id()), Self::slot_suffix(slot))
+            }
+            ManagerStatusMessage::Eta(scenario, seconds, slot) => writeln!(
+                process,
+                "ETA {}{} {}",
+                Self::cfti_escape(scenario.id()),
+                Self::slot_suffix(slot),
+                seconds
+            ),
+            ManagerStatusMessage::Progress(test, percent, status, slot) => writeln!(
+                process,
+                "PROGRESS {}{} {} {}",
+                Self::cfti_escape(test.id()),
+                Self::slot_suffix(slot),
+                percent,
+                Self::cfti_escape(&status)
+            ),
+            ManagerStatusMessage::Prompt(test, prompt, responses, slot) => writeln!(
+                process,
+                "PROMPT {}{} {} {}",
+                Self::cfti_escape(test.id()),
+                Self::slot_suffix(slot),
+                Self::cfti_escape(&responses.join(",")),
+                Self::cfti_escape(&prompt)
+            ),
+            ManagerStatusMessage::Values(scenario, values, slot) => {
+                write!(process, "VALUES {}{}", Self::cfti_escape(scenario.id()), Self::slot_suffix(slot))?;
+                for (key, value) in &values {
+                    write!(process, " {}={}", Self::cfti_escape(key), Self::cfti_escape(value))?;
+                }
+                writeln!(process, "")
+            }
+            ManagerStatusMessage::Units(units) => {
+                for &(ref name, ref status, ref failure) in &units {
+                    writeln!(
+                        process,
+                        "UNIT {} {} {}",
+                        Self::cfti_escape(&format!("{}", name)),
+                        status.as_str(),
+                        Self::cfti_escape(failure.as_ref().unwrap_or(&"".to_owned()))
+                    )?;
+                }
+                writeln!(process, "UNITS {}", units.len())
+            }
+            ManagerStatusMessage::Stats(stats) => {
+                for x in &stats.scenarios {
+                    writeln!(process, "YIELD {} {} {}", x.scenario, x.passed, x.runs)?;
+                }
+                for x in &stats.tests {
+                    writeln!(process, "FAILURES {} {} {}", x.test, x.failures, x.runs)?;
+                }
+                for x in &stats.messages {
+                    writeln!(process, "FAILMSG {} {} {}", x.test, x.count, Self::cfti_escape(&x.message))?;
+                }
+                writeln!(process, "STATS {} {}", stats.runs, stats.window)
             } /*
             //            BroadcastMessageContents::Hello(name) => writeln!(stdin,
             //                                                "HELLO {}", name),
@@ -377,7 +743,26 @@ impl Interface {
         }
     }
 
-    fn cfti_unescape(msg: String) -> String {
+    /// Jig slots are appended to unit names as "name@slot".
+    fn slot_suffix(slot: Option<u32>) -> String {
+        match slot {
+            Some(s) => format!("@{}", s),
+            None => "".to_owned(),
+        }
+    }
+
+    /// Split a "name@slot" word into its name and slot number.
+    pub fn split_slot(word: &str) -> Result<(&str, Option<u32>), String> {
+        match word.rfind('@') {
+            None => Ok((word, None)),
+            Some(idx) => match word[idx + 1..].parse::<u32>() {
+                Ok(slot) => Ok((&word[..idx], Some(slot))),
+                Err(e) => Err(format!("Invalid slot number {}: {}", &word[idx + 1..], e)),
+            },
+        }
+    }
+
+    pub fn cfti_unescape(msg: String) -> String {
         let mut out = String::new();
         let mut was_bs = false;
 
@@ -420,7 +805,7 @@ impl Interface {
             // If the send fails, that means the other end has closed the pipe.
             if let Err(_) = control.send(ManagerControlMessage::new(
                 &id,
-                ManagerControlMessageContents::LogError(line),
+                ManagerControlMessageContents::Output(LogSource::Stderr, line),
             )) {
                 break;
             }
@@ -443,76 +828,180 @@ impl Interface {
             let verb = words[0].to_lowercase();
             words.remove(0);
 
-            let response = match verb.as_str() {
-                "scenarios" => ManagerControlMessageContents::Scenarios,
-                "scenario" => match UnitName::from_str(
-                    words
-                        .get(0)
-                        .unwrap_or(&"".to_owned())
-                        .to_lowercase()
-                        .as_str(),
-                    "scenario",
-                ) {
-                    Err(e) => ManagerControlMessageContents::Error(format!(
-                        "Invalid scenario name: {}",
-                        e
-                    )),
-                    Ok(o) => ManagerControlMessageContents::Scenario(o),
-                },
-                "tests" => {
-                    if words.is_empty() {
-                        ManagerControlMessageContents::Tests(None)
-                    } else {
-                        match UnitName::from_str(words[0].to_lowercase().as_str(), "test") {
-                            Ok(scenario_name) => {
-                                ManagerControlMessageContents::Tests(Some(scenario_name))
+            let (response, slot) = Self::parse_command(&verb, words);
+
+            // If the send fails, that means the other end has closed the pipe.
+            if let Err(_) = control.send(ManagerControlMessage::new_with_slot(&id, slot, response)) {
+                break;
+            }
+        }
+        control
+            .send(ManagerControlMessage::new(
+                &id,
+                ManagerControlMessageContents::ChildExited,
+            )).expect("interface couldn't send exit message to controller");
+    }
+
+    /// Turn a verb and its arguments into a message for the manager, along with the
+    /// jig slot it applies to.  Used by both the text and JSON protocols.
+    pub fn parse_command(verb: &str, words: Vec<String>) -> (ManagerControlMessageContents, Option<u32>) {
+        let mut slot = None;
+        let response = match verb {
+            "scenarios" => ManagerControlMessageContents::Scenarios,
+            "scenario" => match UnitName::from_str(
+                words
+                    .get(0)
+                    .unwrap_or(&"".to_owned())
+                    .to_lowercase()
+                    .as_str(),
+                "scenario",
+            ) {
+                Err(e) => ManagerControlMessageContents::Error(format!(
+                    "Invalid scenario name: {}",
+                    e
+                )),
+                Ok(o) => ManagerControlMessageContents::Scenario(o),
+            },
+            "tests" => {
+                if words.is_empty() {
+                    ManagerControlMessageContents::Tests(None)
+                } else {
+                    match UnitName::from_str(words[0].to_lowercase().as_str(), "test") {
+                        Ok(scenario_name) => {
+                            ManagerControlMessageContents::Tests(Some(scenario_name))
+                        }
+                        Err(e) => ManagerControlMessageContents::Error(format!(
+                            "Invalid test name specified: {}",
+                            e
+                        )),
+                    }
+                }
+            }
+            "jig" => ManagerControlMessageContents::Jig,
+            "units" => ManagerControlMessageContents::Units,
+            "reload" => ManagerControlMessageContents::Reload,
+            "stats" => match StatsWindow::from_str(&words.join(" ")) {
+                Ok(window) => ManagerControlMessageContents::Stats(window),
+                Err(e) => ManagerControlMessageContents::Error(e),
+            },
+            "log" => ManagerControlMessageContents::Log(words.join(" ")),
+            "start" => {
+                // The scenario name is optional, and any "key=value" words that follow are run options.
+                let (word, option_words) = match words.split_first() {
+                    Some((first, rest)) if !first.contains('=') => (first.to_lowercase(), rest),
+                    _ => ("".to_owned(), &words[..]),
+                };
+                match (Self::split_slot(&word), RunOptions::from_words(option_words)) {
+                    (Err(e), _) | (_, Err(e)) => ManagerControlMessageContents::Error(e),
+                    (Ok((name, name_slot)), Ok(options)) => {
+                        slot = name_slot;
+                        if name.is_empty() {
+                            ManagerControlMessageContents::StartScenario(None, options)
+                        } else {
+                            match UnitName::from_str(name, "scenario") {
+                                Err(e) => ManagerControlMessageContents::Error(format!(
+                                    "Invalid scenario name: {}",
+                                    e
+                                )),
+                                Ok(o) => ManagerControlMessageContents::StartScenario(Some(o), options),
                             }
-                            Err(e) => ManagerControlMessageContents::Error(format!(
-                                "Invalid test name specified: {}",
-                                e
-                            )),
                         }
                     }
                 }
-                "jig" => ManagerControlMessageContents::Jig,
-                "log" => ManagerControlMessageContents::Log(words.join(" ")),
-                "start" => {
-                    if words.is_empty() {
-                        ManagerControlMessageContents::StartScenario(None)
-                    } else {
-                        match UnitName::from_str(
-                            words
-                                .get(0)
-                                .unwrap_or(&"".to_owned())
-                                .to_lowercase()
-                                .as_str(),
-                            "scenario",
-                        ) {
+            }
+            "run" => {
+                let word = words.get(0).map(|x| x.to_lowercase()).unwrap_or("".to_owned());
+                match Self::split_slot(&word) {
+                    Err(e) => ManagerControlMessageContents::Error(e),
+                    Ok((name, word_slot)) => {
+                        slot = word_slot;
+                        match UnitName::from_str(name, "test") {
                             Err(e) => ManagerControlMessageContents::Error(format!(
-                                "Invalid scenario name: {}",
+                                "Invalid test name: {}",
                                 e
                             )),
-                            Ok(o) => ManagerControlMessageContents::StartScenario(Some(o)),
+                            Ok(o) => ManagerControlMessageContents::RunTest(o),
+                        }
+                    }
+                }
+            }
+            "answer" => {
+                // Either "ANSWER response", or "ANSWER test[@slot] response..." where the
+                // response is the rest of the line, with its case left alone.
+                let (test_word, response) = match words.len() {
+                    0 => ("".to_owned(), None),
+                    1 => ("".to_owned(), Some(words[0].clone())),
+                    _ => (words[0].to_lowercase(), Some(words[1..].join(" "))),
+                };
+                match (Self::split_slot(&test_word), response) {
+                    (_, None) => ManagerControlMessageContents::Error("No answer given".to_owned()),
+                    (Err(e), _) => ManagerControlMessageContents::Error(e),
+                    (Ok((name, word_slot)), Some(response)) => {
+                        slot = word_slot;
+                        if name.is_empty() {
+                            ManagerControlMessageContents::Answer(None, response)
+                        } else {
+                            match UnitName::from_str(name, "test") {
+                                Err(e) => ManagerControlMessageContents::Error(format!(
+                                    "Invalid test name: {}",
+                                    e
+                                )),
+                                Ok(o) => ManagerControlMessageContents::Answer(Some(o), response),
+                            }
                         }
                     }
                 }
-                "shutdown" => {
-                    if words.is_empty() {
-                        ManagerControlMessageContents::Shutdown(None)
-                    } else {
-                        ManagerControlMessageContents::Shutdown(Some(words.join(" ")))
+            }
+            "pause" | "resume" | "step" | "abort" => {
+                let word = words.get(0).map(|x| x.to_owned()).unwrap_or("".to_owned());
+                match Self::split_slot(&word) {
+                    Err(e) => ManagerControlMessageContents::Error(e),
+                    Ok((_, word_slot)) => {
+                        slot = word_slot;
+                        match verb {
+                            "pause" => ManagerControlMessageContents::Pause,
+                            "resume" => ManagerControlMessageContents::Resume,
+                            "abort" => ManagerControlMessageContents::Abort,
+                            _ => ManagerControlMessageContents::Step,
+                        }
                     }
                 }
-                /*
-                "abort" => ControlMessageContents::AbortTests,
-                "pong" => ControlMessageContents::Pong(words[0].to_lowercase()),
-                "hello" => ControlMessageContents::Hello(words.join(" ")),
-                */
-                v => ManagerControlMessageContents::Unimplemented(v.to_owned(), words.join(" ")),
+            }
+            "shutdown" => {
+                if words.is_empty() {
+                    ManagerControlMessageContents::Shutdown(None)
+                } else {
+                    ManagerControlMessageContents::Shutdown(Some(words.join(" ")))
+                }
+            }
+            /*
+            "pong" => ControlMessageContents::Pong(words[0].to_lowercase()),
+            "hello" => ControlMessageContents::Hello(words.join(" ")),
+            */
+            v => ManagerControlMessageContents::Unimplemented(v.to_owned(), words.join(" ")),
+        };
+        (response, slot)
+    }
+
+    /// Read commands as one JSON object per line, e.g.
+    /// {"verb": "start", "args": ["scenario"], "slot": 2, "assume": ["test"], "skip": ["test"],
+    ///  "parameters": {"serial": "1234"}}
+    fn json_read(id: UnitName, control: Sender<ManagerControlMessage>, stdout: RunningOutput) {
+        for line in BufReader::new(stdout).lines() {
+            let line = line.expect("Unable to get next line");
+
+            // Don't crash if we get a blank line.
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let (response, slot) = match serde_json::from_str::<serde_json::Value>(&line) {
+                Err(e) => (ManagerControlMessageContents::Error(format!("Invalid JSON: {}", e)), None),
+                Ok(object) => Self::json_command(&object),
             };
 
             // If the send fails, that means the other end has closed the pipe.
-            if let Err(_) = control.send(ManagerControlMessage::new(&id, response)) {
+            if let Err(_) = control.send(ManagerControlMessage::new_with_slot(&id, slot, response)) {
                 break;
             }
         }
@@ -522,4 +1011,36 @@ impl Interface {
                 ManagerControlMessageContents::ChildExited,
             )).expect("interface couldn't send exit message to controller");
     }
+
+    /// Turn a JSON command object into a message for the manager, along with
+    /// the jig slot it applies to.
+    pub fn json_command(object: &serde_json::Value) -> (ManagerControlMessageContents, Option<u32>) {
+        let verb = object["verb"].as_str().unwrap_or("").to_lowercase();
+        let mut words: Vec<String> = match object["args"].as_array() {
+            Some(args) => args.iter()
+                .map(|x| x.as_str().map(|s| s.to_owned()).unwrap_or(x.to_string()))
+                .collect(),
+            None => vec![],
+        };
+        for key in &["assume", "skip"] {
+            if let Some(list) = object[*key].as_array() {
+                let names: Vec<&str> = list.iter().filter_map(|x| x.as_str()).collect();
+                words.push(format!("{}={}", key, names.join(",")));
+            }
+        }
+        if let Some(parameters) = object["parameters"].as_object() {
+            for (key, value) in parameters {
+                let value = value.as_str().map(|s| s.to_owned()).unwrap_or(value.to_string());
+                words.push(format!("{}={}", key, value));
+            }
+        }
+        let (response, word_slot) = Self::parse_command(&verb, words);
+        match object.get("slot") {
+            None | Some(&serde_json::Value::Null) => (response, word_slot),
+            Some(value) => match value.as_u64().and_then(|s| u32::try_from(s).ok()) {
+                Some(s) => (response, Some(s)),
+                None => (ManagerControlMessageContents::Error(format!("Invalid slot number {}", value)), None),
+            },
+        }
+    }
 }
@@ -39,6 +39,9 @@ pub struct JigDescription {
 
     /// A file whose existence indicates this jig is compatible
     test_file: Option<String>,
+
+    /// The number of devices that may be tested at once
+    slots: u32,
 }
 
 impl JigDescription {
@@ -67,6 +70,7 @@ impl JigDescription {
             unit_directory: path.parent().unwrap().to_owned(),
             test_program: None,
             test_file: None,
+            slots: 1,
         };
 
         for entry in unit_file.lookup_by_category("Jig") {
@@ -99,6 +103,20 @@ impl JigDescription {
                             None => None,
                         }
                     }
+                    "Slots" => {
+                        jig_description.slots = match directive.value() {
+                            Some(s) => s.parse::<u32>()?,
+                            None => 1,
+                        };
+                        if jig_description.slots == 0 {
+                            return Err(UnitDescriptionError::InvalidValue(
+                                "Jig".to_owned(),
+                                "Slots".to_owned(),
+                                "0".to_owned(),
+                                vec!["1 or more".to_owned()],
+                            ));
+                        }
+                    }
                     &_ => (),
                 },
                 &_ => (),
@@ -193,6 +211,11 @@ impl Jig {
         &self.description.default_scenario
     }
 
+    /// How many devices this jig can test concurrently.
+    pub fn slots(&self) -> u32 {
+        self.description.slots
+    }
+
     pub fn select(&self) -> Result<(), UnitSelectError> {
         Ok(())
     }
@@ -1,30 +1,144 @@
+extern crate humantime;
 extern crate runny;
 extern crate serde_json;
 extern crate systemd_parser;
 
 use std::cell::RefCell;
-use std::fs::File;
-use std::io::{BufRead, BufReader, Error, ErrorKind, Read, Write};
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Error, ErrorKind, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
+use std::process;
 use std::sync::mpsc::Sender;
-use std::time::Duration;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use std::thread;
 
 use config::Config;
+use logfile::{LogFile, LogOutput, RotationPolicy};
+use logfilter::LogFilter;
 use unit::{UnitActivateError, UnitDeactivateError, UnitDescriptionError, UnitDeselectError,
-           UnitIncompatibleReason, UnitName, UnitSelectError};
-use unitbroadcaster::LogEntry;
+           RestartPolicy, UnitIncompatibleReason, UnitKind, UnitName, UnitSelectError};
+use unitbroadcaster::{LogEntry, LogSource, LogType, UnitStatusEvent};
 use unitmanager::{ManagerControlMessage, ManagerControlMessageContents, ManagerStatusMessage,
                   UnitManager};
 
+use self::humantime::{format_rfc3339_nanos, format_rfc3339_seconds};
 use self::systemd_parser::items::DirectiveEntry;
 use self::runny::Runny;
-use self::runny::running::{Running, RunningOutput};
+use self::runny::running::{Running, RunningInput, RunningOutput};
 
 #[derive(Clone, Copy)]
 enum LoggerFormat {
     TSV,
     JSON,
+    CSV,
+    Logfmt,
+    Syslog,
+}
+
+/// Where a logger sends its log entries
+#[derive(Clone, Copy, PartialEq)]
+enum LoggerType {
+    /// Write to the stdin of the program named by ExecStart
+    Program,
+
+    /// Write directly to the file named by Path
+    File,
+}
+
+/// The structured data ID used in syslog records if StructuredDataID= isn't given.
+/// 32473 is the Private Enterprise Number that RFC 5612 sets aside for examples.
+const DEFAULT_SD_ID: &str = "exclave@32473";
+
+/// The CSV header, naming the same fields as TSV records carry.
+const CSV_HEADER: &str = "type,unit,unit_type,unix_time,unix_time_nsecs,message,source,scenario,slot,run,result";
+
+/// Which events, besides log entries, a logger has asked to receive
+#[derive(Clone, Copy, Default)]
+struct LoggerEvents {
+    /// Scenario and test starts and results
+    results: bool,
+
+    /// Units changing status, such as becoming active or failing to load
+    status: bool,
+}
+
+/// A scenario or test starting or finishing, or a unit changing status,
+/// for loggers that asked for them with Events=.
+#[derive(Serialize)]
+struct EventRecord {
+    /// One of "start", "running", "pass", "fail", "skip", "finish", or "status"
+    event: &'static str,
+    unit: UnitName,
+    unix_time: u64,
+    unix_time_nsecs: u32,
+    scenario: Option<UnitName>,
+    slot: Option<u32>,
+    run: Option<u64>,
+
+    /// The test's return code, or the scenario's result code
+    result: Option<i64>,
+    message: String,
+}
+
+impl EventRecord {
+    fn new(event: &'static str, unit: &UnitName, slot: Option<u32>, result: Option<i64>, message: String) -> EventRecord {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        EventRecord {
+            event: event,
+            unit: unit.clone(),
+            unix_time: now.as_secs(),
+            unix_time_nsecs: now.subsec_nanos(),
+            scenario: match *unit.kind() {
+                UnitKind::Scenario => Some(unit.clone()),
+                _ => None,
+            },
+            slot: slot,
+            run: None,
+            result: result,
+            message: message,
+        }
+    }
+
+    /// Tag the record with the scenario run it belongs to.
+    fn with_run(mut self, run: Option<(UnitName, u64)>) -> EventRecord {
+        if let Some((scenario, run)) = run {
+            self.scenario = Some(scenario);
+            self.run = Some(run);
+        }
+        self
+    }
+
+    /// Failures are logged as warnings, and everything else as info.
+    fn kind(&self) -> LogType {
+        match (self.event, self.result) {
+            ("fail", _) => LogType::Warn,
+            ("finish", Some(code)) if code != 200 => LogType::Warn,
+            _ => LogType::Info,
+        }
+    }
+
+    fn time(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::new(self.unix_time, self.unix_time_nsecs)
+    }
+
+    /// The scenario, slot, run, and result, with empty strings for any that are missing.
+    fn context_strings(&self) -> (String, String, String, String) {
+        (
+            self.scenario.as_ref().map(|x| x.id().to_owned()).unwrap_or_default(),
+            self.slot.map(|x| x.to_string()).unwrap_or_default(),
+            self.run.map(|x| x.to_string()).unwrap_or_default(),
+            self.result.map(|x| x.to_string()).unwrap_or_default(),
+        )
+    }
+}
+
+/// What to do with a new log entry when the queue and spool are both full
+#[derive(Clone, Copy)]
+enum OverflowPolicy {
+    DropOldest,
+    DropNewest,
 }
 
 /// A struct defining an in-memory representation of a .logger file
@@ -42,18 +156,51 @@ pub struct LoggerDescription {
     /// A Vec<String> of jig names that this test is compatible with.
     jigs: Vec<UnitName>,
 
+    /// Whether this logger runs a program or writes to a file itself
+    logger_type: LoggerType,
+
     /// Path to the command to start the logger
     exec_start: String,
 
+    /// The file to write to, for loggers with Type=file
+    path: Option<PathBuf>,
+
+    /// When to rotate the file, for loggers with Type=file
+    rotation: RotationPolicy,
+
     /// The format expected by the logger
     format: LoggerFormat,
 
+    /// The RFC 5424 structured data ID that syslog records are sent under
+    sd_id: String,
+
     /// The working directory to start from when running the logger
     working_directory: Option<PathBuf>,
 
     /// The path to the unit file
     unit_directory: PathBuf,
 
+    /// What to do when the process exits on its own
+    restart: RestartPolicy,
+
+    /// How many log entries to hold in memory while the logger is busy or down
+    queue_size: usize,
+
+    /// A file to hold log entries once the in-memory queue is full
+    spool_file: Option<PathBuf>,
+
+    /// How many lines the spool file may grow to
+    spool_size: usize,
+
+    /// What to do when both the queue and the spool are full
+    overflow: OverflowPolicy,
+
+    /// Which log entries this logger should receive
+    filter: LogFilter,
+
+    /// Which other events this logger should receive
+    events: LoggerEvents,
+
     /// How long to wait for a terminate() call
     terminate_timeout: Duration,
 }
@@ -65,6 +212,10 @@ impl LoggerDescription {
         // Parse the file into a systemd unit_file object
         let mut contents = String::with_capacity(8192);
         File::open(path)?.read_to_string(&mut contents)?;
+        Self::from_string(&contents, unit_name, path)
+    }
+
+    pub fn from_string(contents: &str, unit_name: UnitName, path: &Path) -> Result<LoggerDescription, UnitDescriptionError> {
         let unit_file = systemd_parser::parse_string(&contents)?;
 
         if !unit_file.has_category("Logger") {
@@ -77,9 +228,20 @@ impl LoggerDescription {
             description: "".to_owned(),
             jigs: vec![],
             format: LoggerFormat::TSV,
+            sd_id: DEFAULT_SD_ID.to_owned(),
+            logger_type: LoggerType::Program,
             exec_start: "".to_owned(),
+            path: None,
+            rotation: RotationPolicy::new(),
             working_directory: None,
             unit_directory: path.parent().unwrap().to_owned(),
+            restart: RestartPolicy::new(),
+            queue_size: 1000,
+            spool_file: None,
+            spool_size: 100_000,
+            overflow: OverflowPolicy::DropOldest,
+            filter: LogFilter::default(),
+            events: LoggerEvents::default(),
             terminate_timeout: Duration::from_secs(5),
         };
 
@@ -112,31 +274,155 @@ impl LoggerDescription {
                             }
                         }
                     }
+                    "Type" => {
+                        logger_description.logger_type = match directive.value() {
+                            None => LoggerType::Program,
+                            Some(s) => match s.to_string().to_lowercase().as_ref() {
+                                "program" => LoggerType::Program,
+                                "file" => LoggerType::File,
+                                other => {
+                                    return Err(UnitDescriptionError::InvalidValue(
+                                        "Logger".to_owned(),
+                                        "Type".to_owned(),
+                                        other.to_owned(),
+                                        vec!["program".to_owned(), "file".to_owned()],
+                                    ))
+                                }
+                            },
+                        }
+                    }
+                    "Path" => logger_description.path = directive.value().map(PathBuf::from),
                     "Format" => {
                         logger_description.format = match directive.value() {
                             None => LoggerFormat::TSV,
                             Some(s) => match s.to_string().to_lowercase().as_ref() {
                                 "tsv" => LoggerFormat::TSV,
                                 "json" => LoggerFormat::JSON,
+                                "csv" => LoggerFormat::CSV,
+                                "logfmt" => LoggerFormat::Logfmt,
+                                "syslog" => LoggerFormat::Syslog,
                                 other => {
                                     return Err(UnitDescriptionError::InvalidValue(
                                         "Logger".to_owned(),
                                         "Format".to_owned(),
                                         other.to_owned(),
-                                        vec!["tsv".to_owned(), "json".to_owned()],
+                                        vec![
+                                            "tsv".to_owned(),
+                                            "json".to_owned(),
+                                            "csv".to_owned(),
+                                            "logfmt".to_owned(),
+                                            "syslog".to_owned(),
+                                        ],
                                     ))
                                 }
                             },
                         }
                     }
-                    &_ => (),
+                    "StructuredDataID" => {
+                        logger_description.sd_id = match directive.value() {
+                            None => DEFAULT_SD_ID.to_owned(),
+                            Some(s) if Self::is_sd_id(s) => s.to_owned(),
+                            Some(s) => {
+                                return Err(UnitDescriptionError::InvalidValue(
+                                    "Logger".to_owned(),
+                                    "StructuredDataID".to_owned(),
+                                    s.to_owned(),
+                                    vec!["name@number".to_owned()],
+                                ))
+                            }
+                        }
+                    }
+                    "QueueSize" => {
+                        logger_description.queue_size = match directive.value() {
+                            Some(s) => s.parse::<usize>()?.max(1),
+                            None => 1000,
+                        }
+                    }
+                    "Filter" => {
+                        logger_description.filter =
+                            LogFilter::from_str("Logger", "Filter", directive.value().unwrap_or(""))?
+                    }
+                    "Events" => {
+                        logger_description.events = LoggerEvents::default();
+                        for event in directive
+                            .value()
+                            .unwrap_or("")
+                            .split(|c: char| c == ',' || c.is_whitespace())
+                            .filter(|x| !x.is_empty())
+                        {
+                            match event.to_lowercase().as_ref() {
+                                "results" => logger_description.events.results = true,
+                                "status" => logger_description.events.status = true,
+                                other => {
+                                    return Err(UnitDescriptionError::InvalidValue(
+                                        "Logger".to_owned(),
+                                        "Events".to_owned(),
+                                        other.to_owned(),
+                                        vec!["results".to_owned(), "status".to_owned()],
+                                    ))
+                                }
+                            }
+                        }
+                    }
+                    "SpoolFile" => {
+                        logger_description.spool_file = directive.value().map(PathBuf::from)
+                    }
+                    "SpoolSize" => {
+                        logger_description.spool_size = match directive.value() {
+                            Some(s) => s.parse()?,
+                            None => 100_000,
+                        }
+                    }
+                    "Overflow" => {
+                        logger_description.overflow = match directive.value() {
+                            None => OverflowPolicy::DropOldest,
+                            Some(s) => match s.to_string().to_lowercase().as_ref() {
+                                "drop-oldest" => OverflowPolicy::DropOldest,
+                                "drop-newest" => OverflowPolicy::DropNewest,
+                                other => {
+                                    return Err(UnitDescriptionError::InvalidValue(
+                                        "Logger".to_owned(),
+                                        "Overflow".to_owned(),
+                                        other.to_owned(),
+                                        vec!["drop-oldest".to_owned(), "drop-newest".to_owned()],
+                                    ))
+                                }
+                            },
+                        }
+                    }
+                    key => {
+                        if !logger_description.rotation.parse_directive("Logger", key, directive.value())? {
+                            logger_description.restart.parse_directive("Logger", key, directive.value())?;
+                        }
+                    }
                 },
                 &_ => (),
             }
         }
+
+        if logger_description.logger_type == LoggerType::File && logger_description.path.is_none() {
+            return Err(UnitDescriptionError::MissingValue(
+                "Logger".to_owned(),
+                "Path".to_owned(),
+            ));
+        }
         Ok(logger_description)
     }
 
+    /// Whether an ID has the "name@number" form RFC 5424 requires of structured
+    /// data IDs that aren't registered with IANA.
+    fn is_sd_id(id: &str) -> bool {
+        let (name, number) = match id.find('@') {
+            Some(idx) => (&id[..idx], &id[idx + 1..]),
+            None => return false,
+        };
+        id.len() <= 32
+            && !name.is_empty()
+            && name.chars().all(|c| c.is_ascii_graphic() && c != '=' && c != ']' && c != '"' && c != '@')
+            && !number.is_empty()
+            && number.split('.').all(|x| !x.is_empty() && x.chars().all(|c| c.is_ascii_digit()))
+    }
+
     /// Returns true if this test is supported on the named jig.
     pub fn supports_jig(&self, name: &UnitName) -> bool {
         self.jigs.contains(name)
@@ -174,16 +460,188 @@ impl LoggerDescription {
     }
 }
 
+/// Log lines waiting to be written to the logger process.  The oldest lines
+/// are held in memory, and once that fills up, newer lines are appended to
+/// the spool file until there is room for them again.  Lines that have been
+/// read back stay at the start of the spool file until it reaches
+/// `spool_limit` lines, at which point it is rewritten without them.  Only
+/// half of the spool holds unread lines, so that there is always room for
+/// several lines to be read back between rewrites.
+struct LogQueue {
+    memory: VecDeque<String>,
+    memory_limit: usize,
+
+    spool_path: Option<PathBuf>,
+    spool_limit: usize,
+
+    /// The number of lines in the spool file that haven't been read back yet
+    spooled: usize,
+
+    /// The number of lines at the start of the spool file that have been read back
+    spool_read: usize,
+
+    /// Where the next unread line in the spool file starts
+    spool_offset: u64,
+
+    overflow: OverflowPolicy,
+
+    /// How many lines were thrown away since the logger was last started
+    dropped: u64,
+
+    /// Bumped whenever the logger process changes, so stale writer threads know to exit
+    generation: u64,
+
+    /// Set when everything queued so far should be synced to its destination
+    sync_requested: bool,
+
+    /// Why the writer thread stopped, if it couldn't write to the logger
+    write_error: Option<Error>,
+}
+
+impl LogQueue {
+    fn new(desc: &LoggerDescription, spool_path: Option<PathBuf>) -> LogQueue {
+        // Pick up anything left in the spool from a previous run.
+        let spooled = spool_path
+            .as_ref()
+            .and_then(|p| File::open(p).ok())
+            .map(|f| BufReader::new(f).lines().count())
+            .unwrap_or(0);
+        let mut queue = LogQueue {
+            memory: VecDeque::new(),
+            memory_limit: desc.queue_size,
+            spool_path: spool_path,
+            spool_limit: desc.spool_size,
+            spooled: spooled,
+            spool_read: 0,
+            spool_offset: 0,
+            overflow: desc.overflow,
+            dropped: 0,
+            generation: 0,
+            sync_requested: false,
+            write_error: None,
+        };
+        queue.refill();
+        queue
+    }
+
+    fn is_full(&self) -> bool {
+        self.memory.len() >= self.memory_limit
+            && (self.spool_path.is_none() || self.spooled >= (self.spool_limit + 1) / 2)
+    }
+
+    fn push(&mut self, line: String) {
+        if self.is_full() {
+            self.dropped += 1;
+            match self.overflow {
+                OverflowPolicy::DropNewest => return,
+                OverflowPolicy::DropOldest => {
+                    self.memory.pop_front();
+                    self.refill();
+                }
+            }
+        }
+
+        // Lines can only go into memory if nothing is waiting in the spool,
+        // otherwise they would be written out of order.
+        if self.spooled == 0 && self.memory.len() < self.memory_limit {
+            self.memory.push_back(line);
+        } else if self.spool(&line).is_err() {
+            self.dropped += 1;
+        }
+    }
+
+    fn spool(&mut self, line: &str) -> Result<(), Error> {
+        if self.spool_read > 0 && self.spooled + self.spool_read >= self.spool_limit {
+            self.compact()?;
+        }
+        let path = match self.spool_path {
+            Some(ref p) => p,
+            None => return Err(Error::new(ErrorKind::Other, "no spool file")),
+        };
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{}", line)?;
+        self.spooled += 1;
+        Ok(())
+    }
+
+    /// Rewrite the spool file without the lines that have already been read back.
+    fn compact(&mut self) -> Result<(), Error> {
+        let path = match self.spool_path {
+            Some(ref p) => p,
+            None => return Err(Error::new(ErrorKind::Other, "no spool file")),
+        };
+        let mut compacted_path = path.as_os_str().to_owned();
+        compacted_path.push(".tmp");
+        let compacted_path = PathBuf::from(compacted_path);
+
+        let mut unread = File::open(path)?;
+        unread.seek(SeekFrom::Start(self.spool_offset))?;
+        io::copy(&mut unread, &mut File::create(&compacted_path)?)?;
+        fs::rename(&compacted_path, path)?;
+
+        self.spool_read = 0;
+        self.spool_offset = 0;
+        Ok(())
+    }
+
+    /// Move lines from the spool file back into memory, oldest first.
+    fn refill(&mut self) {
+        if self.spooled == 0 || self.memory.len() >= self.memory_limit {
+            return;
+        }
+        let path = match self.spool_path {
+            Some(ref p) => p.clone(),
+            None => return,
+        };
+
+        if let Ok(mut file) = File::open(&path) {
+            if file.seek(SeekFrom::Start(self.spool_offset)).is_ok() {
+                let mut reader = BufReader::new(file);
+                while self.spooled > 0 && self.memory.len() < self.memory_limit {
+                    let mut line = String::new();
+                    match reader.read_line(&mut line) {
+                        Ok(0) | Err(_) => break,
+                        Ok(count) => {
+                            self.spool_offset += count as u64;
+                            self.spooled -= 1;
+                            self.spool_read += 1;
+                            self.memory.push_back(line.trim_end_matches('\n').to_owned());
+                        }
+                    }
+                }
+            }
+        }
+
+        // Once everything has been read back (or the spool has gone missing),
+        // start the spool over.
+        if self.spooled == 0 || self.memory.len() < self.memory_limit {
+            self.spooled = 0;
+            self.spool_read = 0;
+            self.spool_offset = 0;
+            File::create(&path).ok();
+        }
+    }
+}
+
 pub struct Logger {
     description: LoggerDescription,
     process: RefCell<Option<Running>>,
+    queue: Arc<(Mutex<LogQueue>, Condvar)>,
+    hostname: String,
 }
 
 impl Logger {
-    pub fn new(desc: &LoggerDescription, _: &UnitManager, _config: &Config) -> Logger {
+    pub fn new(desc: &LoggerDescription, _: &UnitManager, config: &Config) -> Logger {
+        let spool_path = desc.spool_file.as_ref().map(|f| {
+            config
+                .working_directory(&desc.unit_directory, &desc.working_directory)
+                .join(f)
+        });
         Logger {
             description: desc.clone(),
             process: RefCell::new(None),
+            queue: Arc::new((Mutex::new(LogQueue::new(desc, spool_path)), Condvar::new())),
+            hostname: Self::hostname(),
         }
     }
 
@@ -191,6 +649,14 @@ impl Logger {
         &self.description.id
     }
 
+    pub fn restart_policy(&self) -> &RestartPolicy {
+        &self.description.restart
+    }
+
+    pub fn log_filter(&self) -> &LogFilter {
+        &self.description.filter
+    }
+
     pub fn select(&self) -> Result<(), UnitSelectError> {
         Ok(())
     }
@@ -199,13 +665,13 @@ impl Logger {
         Ok(())
     }
 
-    fn text_read(id: UnitName, control: Sender<ManagerControlMessage>, output: RunningOutput) {
+    fn text_read(id: UnitName, control: Sender<ManagerControlMessage>, output: RunningOutput, source: LogSource) {
         for line in BufReader::new(output).lines() {
             let line = line.expect("Unable to get next line");
             // If the send fails, that means the other end has closed the pipe.
             if let Err(_) = control.send(ManagerControlMessage::new(
                 &id,
-                ManagerControlMessageContents::LogError(line),
+                ManagerControlMessageContents::Output(source.clone(), line),
             )) {
                 break;
             }
@@ -217,28 +683,57 @@ impl Logger {
         manager: &UnitManager,
         config: &Config,
     ) -> Result<(), UnitActivateError> {
-        let mut running = Runny::new(self.description.exec_start.as_str())
-            .directory(&Some(
-                config.working_directory(&self.description.unit_directory, &self.description.working_directory),
-            ))
-            .start()?;
+        let working_directory =
+            config.working_directory(&self.description.unit_directory, &self.description.working_directory);
+        let header = match self.description.format {
+            LoggerFormat::CSV => Some(CSV_HEADER.to_owned()),
+            _ => None,
+        };
 
-        // Have stdout and stderr log their output.
-        let control_sender = manager.get_control_channel();
-        let control_sender_id = self.id().clone();
-        let stdout = running.take_output();
-        let stderr = running.take_error();
-        let thr_sender_id = control_sender_id.clone();
-        let thr_sender = control_sender.clone();
-        thread::spawn(move || Self::text_read(thr_sender_id, thr_sender, stdout));
-        thread::spawn(move || {
-            Self::text_read(control_sender_id, control_sender, stderr)
-        });
+        // A file logger starts each new file with the header, while a program
+        // is sent the header every time it's started.
+        let (output, header): (Box<dyn LogOutput>, Option<String>) = match self.description.logger_type {
+            LoggerType::File => {
+                let path = working_directory.join(self.description.path.as_ref().unwrap());
+                (Box::new(LogFile::open(&path, &self.description.rotation, header)?), None)
+            }
+            LoggerType::Program => (Box::new(self.start_program(manager, working_directory)?), header),
+        };
 
         let control_sender = manager.get_control_channel();
         let control_sender_id = self.id().clone();
 
-        *self.process.borrow_mut() = Some(running);
+        // Hand the output to a new writer thread, which drains whatever was
+        // queued while the logger wasn't running.
+        let dropped = {
+            let &(ref lock, ref cvar) = &*self.queue;
+            let mut queue = lock.lock().unwrap();
+            queue.generation += 1;
+            let generation = queue.generation;
+            let thr_queue = self.queue.clone();
+            let thr_sender = control_sender.clone();
+            let thr_sender_id = control_sender_id.clone();
+            thread::spawn(move || {
+                if let Err(e) = Self::write_queue(&thr_queue, generation, output, header) {
+                    Self::write_failed(&thr_queue, generation, e, &thr_sender_id, &thr_sender);
+                }
+            });
+            cvar.notify_all();
+            let dropped = queue.dropped;
+            queue.dropped = 0;
+            dropped
+        };
+        if dropped > 0 {
+            control_sender
+                .send(ManagerControlMessage::new(
+                    &control_sender_id,
+                    ManagerControlMessageContents::LogError(format!(
+                        "dropped {} log entries while the logger was unavailable",
+                        dropped
+                    )),
+                ))
+                .ok();
+        }
 
         // Send some initial configuration to the client.
         control_sender
@@ -251,8 +746,49 @@ impl Logger {
         Ok(())
     }
 
+    /// Start the logger program, returning its stdin.
+    fn start_program(&self, manager: &UnitManager, working_directory: PathBuf) -> Result<RunningInput, UnitActivateError> {
+        let mut running = Runny::new(self.description.exec_start.as_str())
+            .directory(&Some(working_directory))
+            .start()?;
+
+        // Have stdout and stderr log their output.
+        let control_sender = manager.get_control_channel();
+        let control_sender_id = self.id().clone();
+        let stdout = running.take_output();
+        let stderr = running.take_error();
+        let thr_sender_id = control_sender_id.clone();
+        let thr_sender = control_sender.clone();
+        thread::spawn(move || {
+            Self::text_read(thr_sender_id.clone(), thr_sender.clone(), stdout, LogSource::Stdout);
+            // Once stdout closes, the logger process has gone away.
+            thr_sender
+                .send(ManagerControlMessage::new(
+                    &thr_sender_id,
+                    ManagerControlMessageContents::ChildExited,
+                ))
+                .ok();
+        });
+        thread::spawn(move || {
+            Self::text_read(control_sender_id, control_sender, stderr, LogSource::Stderr)
+        });
+
+        let input = running.take_input();
+        *self.process.borrow_mut() = Some(running);
+        Ok(input)
+    }
+
     pub fn deactivate(&self) -> Result<(), UnitDeactivateError> {
-        if let Some(process) = self.process.borrow_mut().take() {
+        // Retire the writer thread.  Anything it hasn't written stays queued.
+        let write_error = {
+            let &(ref lock, ref cvar) = &*self.queue;
+            let mut queue = lock.lock().unwrap();
+            queue.generation += 1;
+            cvar.notify_all();
+            queue.write_error.take()
+        };
+
+        let result = if let Some(process) = self.process.borrow_mut().take() {
             match process.terminate(Some(self.description.terminate_timeout)) {
                 Ok(retval) => match retval {
                     0 => Ok(()),
@@ -262,30 +798,249 @@ impl Logger {
             }
         } else {
             Ok(())
+        };
+
+        // A failed write counts as a failure, so that Restart=on-failure applies.
+        match (result, write_error) {
+            (Ok(()), Some(e)) => Err(UnitDeactivateError::WriteFailed(e)),
+            (result, _) => result,
         }
     }
 
+    /// Ask the writer thread to sync everything queued so far, such as at
+    /// the end of a scenario.  For file loggers this makes sure the entries
+    /// are on disk.
+    pub fn sync(&self) {
+        let &(ref lock, ref cvar) = &*self.queue;
+        lock.lock().unwrap().sync_requested = true;
+        cvar.notify_all();
+    }
+
     /// Cause a MessageControlContents to be written out.
-    pub fn output_message(&self, msg: ManagerStatusMessage) -> Result<(), Error> {
-        let mut process_opt = self.process.borrow_mut();
+    /// Log entries are queued, and written by the writer thread as the
+    /// logger process is able to accept them.  Scenario and test results
+    /// are only written if the logger asked for them, tagged with the
+    /// scenario and run number in `run`.
+    pub fn output_message(&self, msg: ManagerStatusMessage, run: Option<(UnitName, u64)>) -> Result<(), Error> {
+        let line = match msg {
+            ManagerStatusMessage::Log(l) =>
+                match self.description.format {
+                    LoggerFormat::TSV => self.tsv_format(l),
+                    LoggerFormat::JSON => self.json_format(l)?,
+                    LoggerFormat::CSV => self.csv_format(l),
+                    LoggerFormat::Logfmt => self.logfmt_format(l),
+                    LoggerFormat::Syslog => self.syslog_format(l),
+                },
+            msg => match self.result_record(msg) {
+                Some(record) => self.event_format(record.with_run(run))?,
+                None => return Ok(()),
+            },
+        };
+
+        self.enqueue(line);
+        Ok(())
+    }
 
-        if process_opt.is_none() {
-            return Err(Error::new(ErrorKind::Other, "no process running"));
+    /// Write out a unit's change in status, if the logger asked for them.
+    pub fn output_unit_status(&self, event: &UnitStatusEvent) -> Result<(), Error> {
+        if !self.description.events.status {
+            return Ok(());
         }
+        let record = EventRecord::new("status", event.name(), None, None, format!("{}", event.status()));
+        let line = self.event_format(record)?;
+        self.enqueue(line);
+        Ok(())
+    }
 
-        let process = process_opt.as_mut().unwrap();
+    fn enqueue(&self, line: String) {
+        let &(ref lock, ref cvar) = &*self.queue;
+        lock.lock().unwrap().push(line);
+        cvar.notify_all();
+    }
 
-        match msg {
-            ManagerStatusMessage::Log(l) =>
-                match self.description.format {
-                    LoggerFormat::TSV => self.tsv_write(l, process),
-                    LoggerFormat::JSON => self.json_write(l, process),
-                },
-            _ => Ok(()),
+    /// Turn a scenario or test result into a record, if the logger asked for them.
+    fn result_record(&self, msg: ManagerStatusMessage) -> Option<EventRecord> {
+        if !self.description.events.results {
+            return None;
+        }
+        Some(match msg {
+            ManagerStatusMessage::Start(scenario, slot) => EventRecord::new("start", &scenario, slot, None, "".to_owned()),
+            ManagerStatusMessage::Running(test, slot) => EventRecord::new("running", &test, slot, None, "".to_owned()),
+            ManagerStatusMessage::Pass(test, message, slot) => EventRecord::new("pass", &test, slot, Some(0), message),
+            ManagerStatusMessage::Fail(test, code, message, slot) => {
+                EventRecord::new("fail", &test, slot, Some(code as i64), message)
+            }
+            ManagerStatusMessage::Skipped(test, reason, slot) => EventRecord::new("skip", &test, slot, None, reason),
+            ManagerStatusMessage::Finished(scenario, code, reason, slot) => {
+                EventRecord::new("finish", &scenario, slot, Some(code as i64), reason)
+            }
+            _ => return None,
+        })
+    }
+
+    /// Format an event record using the same fields as log entries, with
+    /// the event in place of the log type and "event" as the source.
+    fn event_format(&self, r: EventRecord) -> Result<String, Error> {
+        let (scenario, slot, run, result) = r.context_strings();
+        Ok(match self.description.format {
+            LoggerFormat::TSV => format!(
+                "{}\t{}\t{}\t{}\t{}\t{}\tevent\t{}\t{}\t{}\t{}",
+                r.event,
+                Self::cfti_escape(r.unit.id()),
+                Self::cfti_escape(&format!("{}", r.unit.kind())),
+                r.unix_time,
+                r.unix_time_nsecs,
+                Self::cfti_escape(&r.message),
+                Self::cfti_escape(&scenario),
+                slot,
+                run,
+                result
+            ),
+            LoggerFormat::JSON => serde_json::to_string(&r)?,
+            LoggerFormat::CSV => format!(
+                "{},{},{},{},{},{},event,{},{},{},{}",
+                r.event,
+                Self::csv_escape(r.unit.id()),
+                r.unit.kind(),
+                r.unix_time,
+                r.unix_time_nsecs,
+                Self::csv_escape(&r.message),
+                Self::csv_escape(&scenario),
+                slot,
+                run,
+                result
+            ),
+            LoggerFormat::Logfmt => {
+                let mut line = format!(
+                    "time={} level={} event={} unit={} unit_type={} source=event",
+                    format_rfc3339_nanos(r.time()),
+                    r.kind().as_str(),
+                    r.event,
+                    Self::logfmt_escape(r.unit.id()),
+                    r.unit.kind()
+                );
+                if let Some(ref scenario) = r.scenario {
+                    line.push_str(&format!(" scenario={}", Self::logfmt_escape(scenario.id())));
+                }
+                if let Some(slot) = r.slot {
+                    line.push_str(&format!(" slot={}", slot));
+                }
+                if let Some(run) = r.run {
+                    line.push_str(&format!(" run={}", run));
+                }
+                if let Some(result) = r.result {
+                    line.push_str(&format!(" result={}", result));
+                }
+                line.push_str(&format!(" msg={}", Self::logfmt_escape(&r.message)));
+                line
+            }
+            LoggerFormat::Syslog => {
+                let mut data = format!(
+                    "[{} event=\"{}\" unit=\"{}\" unit_type=\"{}\" source=\"event\"",
+                    self.description.sd_id,
+                    r.event,
+                    Self::sd_escape(r.unit.id()),
+                    r.unit.kind()
+                );
+                if let Some(ref scenario) = r.scenario {
+                    data.push_str(&format!(" scenario=\"{}\"", Self::sd_escape(scenario.id())));
+                }
+                if let Some(slot) = r.slot {
+                    data.push_str(&format!(" slot=\"{}\"", slot));
+                }
+                if let Some(run) = r.run {
+                    data.push_str(&format!(" run=\"{}\"", run));
+                }
+                if let Some(result) = r.result {
+                    data.push_str(&format!(" result=\"{}\"", result));
+                }
+                data.push(']');
+                self.syslog_line(&r.kind(), r.time(), r.unix_time_nsecs, &data, &r.message)
+            }
+        })
+    }
+
+    /// Runs in its own thread, writing queued lines to the logger's output.
+    /// A line is only removed from the queue once it has been written, so
+    /// lines that were in flight when the logger died are sent again.
+    fn write_queue(
+        queue: &Arc<(Mutex<LogQueue>, Condvar)>,
+        generation: u64,
+        mut output: Box<dyn LogOutput>,
+        header: Option<String>,
+    ) -> Result<(), Error> {
+        let &(ref lock, ref cvar) = &**queue;
+        if let Some(header) = header {
+            output.write_all(format!("{}\n", header).as_bytes()).and_then(|_| output.flush())?;
+        }
+        loop {
+            let line = {
+                let mut queue = lock.lock().unwrap();
+                while queue.generation == generation && queue.memory.is_empty() && !queue.sync_requested {
+                    queue = cvar.wait(queue).unwrap();
+                }
+                if queue.generation != generation {
+                    output.sync().ok();
+                    return Ok(());
+                }
+                if queue.memory.is_empty() {
+                    queue.sync_requested = false;
+                    None
+                } else {
+                    Some(queue.memory.front().unwrap().clone())
+                }
+            };
+
+            // Only sync once everything queued has been written.
+            let line = match line {
+                Some(l) => l,
+                None => {
+                    output.sync().ok();
+                    continue;
+                }
+            };
+
+            // Write the line in one go, so a file is never rotated partway through it.
+            output.write_all(format!("{}\n", line).as_bytes()).and_then(|_| output.flush())?;
+
+            let mut queue = lock.lock().unwrap();
+            if queue.generation != generation {
+                return Ok(());
+            }
+            queue.memory.pop_front();
+            queue.refill();
         }
     }
 
-    fn json_write(&self, entry: LogEntry, process: &mut Running) -> Result<(), Error> {
+    /// Log why the writer thread stopped, and report the logger as exited so
+    /// that its Restart= setting applies.  Nothing is reported if the logger
+    /// has been deactivated since the writer thread was started.
+    fn write_failed(
+        queue: &Arc<(Mutex<LogQueue>, Condvar)>,
+        generation: u64,
+        error: Error,
+        id: &UnitName,
+        control: &Sender<ManagerControlMessage>,
+    ) {
+        let &(ref lock, _) = &**queue;
+        // Sent while holding the lock, so the exit can't be reported after a restart.
+        let mut queue = lock.lock().unwrap();
+        if queue.generation != generation {
+            return;
+        }
+        control
+            .send(ManagerControlMessage::new(
+                id,
+                ManagerControlMessageContents::LogError(format!("unable to write to logger: {}", error)),
+            ))
+            .ok();
+        control
+            .send(ManagerControlMessage::new(id, ManagerControlMessageContents::ChildExited))
+            .ok();
+        queue.write_error = Some(error);
+    }
+
+    fn json_format(&self, entry: LogEntry) -> Result<String, Error> {
         /*
         let mut object = json::JsonValue::new_object();
         object["message_class"] = msg.message_class.into();
@@ -296,7 +1051,7 @@ impl Logger {
         object["message"] = log.into();
         writeln!(&mut stdin, "{}", json::stringify(object))
         */
-        writeln!(process, "{}", serde_json::to_string(&entry)?)
+        Ok(serde_json::to_string(&entry)?)
     }
 
     fn cfti_escape(msg: &String) -> String {
@@ -306,17 +1061,171 @@ impl Logger {
             .replace("\r", "\\r")
     }
 
-    /// Write a ManagerStatusMessage to a TSV-formatted output.
-    fn tsv_write(&self, l: LogEntry, process: &mut Running) -> Result<(), Error> {
-        writeln!(
-            process,
-            "{}\t{}\t{}\t{}\t{}\t{}",
+    /// Format a ManagerStatusMessage as a line of TSV.
+    fn tsv_format(&self, l: LogEntry) -> String {
+        let (scenario, slot, run) = Self::context_strings(&l);
+        format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
             l.kind().as_str(),
             Self::cfti_escape(l.id().id()),
             Self::cfti_escape(&format!("{}", l.id().kind())),
             l.secs(),
             l.nsecs(),
-            Self::cfti_escape(l.message())
+            Self::cfti_escape(l.message()),
+            l.source().as_str(),
+            Self::cfti_escape(&scenario),
+            slot,
+            run
+        )
+    }
+
+    /// The scenario, slot, and run of an entry, with empty strings for any that are missing.
+    fn context_strings(l: &LogEntry) -> (String, String, String) {
+        (
+            l.scenario().as_ref().map(|x| x.id().to_owned()).unwrap_or_default(),
+            l.slot().map(|x| x.to_string()).unwrap_or_default(),
+            l.run().map(|x| x.to_string()).unwrap_or_default(),
+        )
+    }
+
+    /// Quote a CSV field if it needs it.  Newlines are escaped as they are
+    /// for TSV, so that each record stays on one line.
+    fn csv_escape(field: &String) -> String {
+        let field = Self::cfti_escape(field);
+        if field.contains(|c| c == ',' || c == '"') {
+            format!("\"{}\"", field.replace("\"", "\"\""))
+        } else {
+            field
+        }
+    }
+
+    /// Format a ManagerStatusMessage as a CSV record, with fields in the same
+    /// order as TSV.  The result field is only used by events, and left empty.
+    fn csv_format(&self, l: LogEntry) -> String {
+        let (scenario, slot, run) = Self::context_strings(&l);
+        format!(
+            "{},{},{},{},{},{},{},{},{},{},",
+            l.kind().as_str(),
+            Self::csv_escape(l.id().id()),
+            l.id().kind(),
+            l.secs(),
+            l.nsecs(),
+            Self::csv_escape(l.message()),
+            l.source().as_str(),
+            Self::csv_escape(&scenario),
+            slot,
+            run
+        )
+    }
+
+    /// Quote a logfmt value if it is empty or contains spaces, quotes, or '='.
+    fn logfmt_escape(value: &str) -> String {
+        if !value.is_empty()
+            && !value.contains(|c: char| c == ' ' || c == '"' || c == '=' || c == '\\' || c.is_control())
+        {
+            return value.to_owned();
+        }
+        format!(
+            "\"{}\"",
+            value
+                .replace("\\", "\\\\")
+                .replace("\"", "\\\"")
+                .replace("\n", "\\n")
+                .replace("\r", "\\r")
+                .replace("\t", "\\t")
+        )
+    }
+
+    /// Format a ManagerStatusMessage as a line of logfmt key=value pairs.
+    fn logfmt_format(&self, l: LogEntry) -> String {
+        let mut line = format!(
+            "time={} level={} unit={} unit_type={} source={}",
+            format_rfc3339_nanos(l.time()),
+            l.kind().as_str(),
+            Self::logfmt_escape(l.id().id()),
+            l.id().kind(),
+            l.source().as_str()
+        );
+        if let Some(ref scenario) = *l.scenario() {
+            line.push_str(&format!(" scenario={}", Self::logfmt_escape(scenario.id())));
+        }
+        if let Some(slot) = l.slot() {
+            line.push_str(&format!(" slot={}", slot));
+        }
+        if let Some(run) = l.run() {
+            line.push_str(&format!(" run={}", run));
+        }
+        line.push_str(&format!(" msg={}", Self::logfmt_escape(l.message())));
+        line
+    }
+
+    /// Escape an RFC 5424 structured data parameter value.
+    fn sd_escape(value: &str) -> String {
+        value
+            .replace("\\", "\\\\")
+            .replace("\"", "\\\"")
+            .replace("]", "\\]")
+    }
+
+    /// Format a ManagerStatusMessage as an RFC 5424 syslog line.  The facility
+    /// is "user", and the unit and run details go in structured data.
+    fn syslog_format(&self, l: LogEntry) -> String {
+        let mut data = format!(
+            "[{} unit=\"{}\" unit_type=\"{}\" source=\"{}\"",
+            self.description.sd_id,
+            Self::sd_escape(l.id().id()),
+            l.id().kind(),
+            l.source().as_str()
+        );
+        if let Some(ref scenario) = *l.scenario() {
+            data.push_str(&format!(" scenario=\"{}\"", Self::sd_escape(scenario.id())));
+        }
+        if let Some(slot) = l.slot() {
+            data.push_str(&format!(" slot=\"{}\"", slot));
+        }
+        if let Some(run) = l.run() {
+            data.push_str(&format!(" run=\"{}\"", run));
+        }
+        data.push(']');
+        self.syslog_line(l.kind(), l.time(), l.nsecs(), &data, l.message())
+    }
+
+    /// Put together a syslog line from its structured data and message.
+    fn syslog_line(&self, kind: &LogType, time: SystemTime, nsecs: u32, data: &str, message: &String) -> String {
+        let severity = match *kind {
+            LogType::Error => 3,
+            LogType::Warn => 4,
+            LogType::Info => 6,
+            LogType::Debug => 7,
+        };
+        // RFC 5424 allows at most microseconds in timestamps.
+        let seconds = format_rfc3339_seconds(time).to_string();
+        let timestamp = format!("{}.{:06}Z", seconds.trim_end_matches('Z'), nsecs / 1000);
+
+        format!(
+            "<{}>1 {} {} exclave {} - {} {}",
+            8 + severity,
+            timestamp,
+            self.hostname,
+            process::id(),
+            data,
+            Self::cfti_escape(message)
         )
     }
+
+    /// The name of this machine for syslog records, or "-" if it can't be found.
+    fn hostname() -> String {
+        let mut name = String::new();
+        if let Ok(mut file) = File::open("/etc/hostname") {
+            file.read_to_string(&mut name).ok();
+        }
+        let name = name.trim();
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            "-".to_owned()
+        } else {
+            name.to_owned()
+        }
+    }
 }
+
+impl LogOutput for RunningInput {}
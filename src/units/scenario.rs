@@ -5,27 +5,89 @@ extern crate systemd_parser;
 
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::fmt;
 use std::fs::File;
 use std::io::{BufRead, BufReader, Read};
-use std::sync::mpsc::Sender;
+use std::sync::mpsc::{channel, Receiver, Sender};
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::thread;
 use std::time::{Duration, Instant};
 
-use self::dependy::{Dependy, Dependency};
+use self::dependy::{Dependy, Dependency, DepError};
 use self::humantime::{parse_duration, DurationError};
 use self::runny::Runny;
 use self::runny::running::Running;
 use self::systemd_parser::items::DirectiveEntry;
 
-use config::Config;
+use config::{command_with_environment, Config};
 use unit::{UnitActivateError, UnitDeactivateError, UnitDescriptionError, UnitIncompatibleReason,
-           UnitName, UnitSelectError, UnitDeselectError};
+           UnitKind, UnitName, UnitSelectError, UnitDeselectError};
+use unitbroadcaster::LogSource;
 use unitmanager::{ManagerControlMessage, ManagerControlMessageContents,
                   UnitManager};
 use units::test::Test;
 
+/// Overrides that apply to a single run of a scenario, given when it is started.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Default)]
+pub struct RunOptions {
+    /// Tests that are considered to have passed, in addition to the scenario's own assumptions.
+    pub assume: Vec<UnitName>,
+
+    /// Tests that will not be run.  Tests that require them will be skipped as well.
+    pub skip: Vec<UnitName>,
+
+    /// Values for the parameters declared by the scenario, such as a serial number.
+    pub parameters: BTreeMap<String, String>,
+}
+
+impl RunOptions {
+    /// Parse "assume=test1,test2" and "skip=test3" arguments.  Any other
+    /// "key=value" argument is taken as a scenario parameter.
+    pub fn from_words(words: &[String]) -> Result<RunOptions, String> {
+        let mut options = RunOptions::default();
+        for word in words {
+            let mut kv = word.splitn(2, '=');
+            let key = kv.next().unwrap_or("");
+            let value = match kv.next() {
+                Some(v) => v,
+                None => return Err(format!("expected key=value, got {}", word)),
+            };
+            match key.to_lowercase().as_str() {
+                "assume" | "skip" => {
+                    let list = UnitName::from_list(value, "test").map_err(|e| format!("invalid test name: {}", e))?;
+                    if key.to_lowercase() == "assume" {
+                        options.assume.extend(list);
+                    } else {
+                        options.skip.extend(list);
+                    }
+                }
+                "" => return Err(format!("missing parameter name in {}", word)),
+                _ => {
+                    options.parameters.insert(key.to_owned(), value.to_owned());
+                }
+            }
+        }
+        Ok(options)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.assume.is_empty() && self.skip.is_empty() && self.parameters.is_empty()
+    }
+}
+
+impl fmt::Display for RunOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names = |list: &Vec<UnitName>| list.iter().map(|x| x.to_string()).collect::<Vec<String>>().join(",");
+        write!(f, "assume={} skip={}", names(&self.assume), names(&self.skip))?;
+        for (key, value) in &self.parameters {
+            write!(f, " {}={}", key, value)?;
+        }
+        Ok(())
+    }
+}
+
 struct AssumptionDependency {
     name: UnitName,
     requirements: Vec<UnitName>,
@@ -80,6 +142,10 @@ pub struct ScenarioDescription {
     /// A Vec<String> of tests that are considered to have passed without running them.
     assumptions: Vec<UnitName>,
 
+    /// Parameters that may be given when the scenario is started, along with their
+    /// default values.  Parameters without a default must be given.
+    parameters: BTreeMap<String, Option<String>>,
+
     /// The maximum duration, if any, for this scenario
     timeout: Option<Duration>,
 
@@ -136,6 +202,7 @@ impl ScenarioDescription {
             jigs: vec![],
             tests: vec![],
             assumptions: vec![],
+            parameters: BTreeMap::new(),
 
             timeout: None,
 
@@ -190,6 +257,25 @@ impl ScenarioDescription {
                                 None => vec![],
                             }
                         }
+                        "Parameters" => {
+                            scenario_description.parameters = BTreeMap::new();
+                            for item in directive.value().unwrap_or("").split(|c: char| c == ',' || c.is_whitespace()) {
+                                let mut kv = item.splitn(2, '=');
+                                let name = kv.next().unwrap_or("");
+                                if name.is_empty() {
+                                    continue;
+                                }
+                                if name == "assume" || name == "skip" {
+                                    return Err(UnitDescriptionError::InvalidValue(
+                                        "Scenario".to_owned(),
+                                        "Parameters".to_owned(),
+                                        name.to_owned(),
+                                        vec!["any name other than assume or skip".to_owned()],
+                                    ));
+                                }
+                                scenario_description.parameters.insert(name.to_owned(), kv.next().map(|x| x.to_owned()));
+                            }
+                        }
                         "ExecStart" => {
                             scenario_description.exec_start = match directive.value() {
                                 None => None,
@@ -298,6 +384,38 @@ impl ScenarioDescription {
         }
     }
 
+    /// Create a temporary scenario that runs a single test, along with everything it requires.
+    /// The scenario is named "run/" followed by the test's name, and is limited to the given jig.
+    pub fn for_test(test_name: &UnitName,
+                    jig: Option<&UnitName>,
+                    assumptions: &Vec<UnitName>,
+                    unit_directory: &Path)
+                    -> ScenarioDescription {
+        ScenarioDescription {
+            id: UnitName::temporary(test_name.id(), UnitKind::Scenario),
+            name: format!("Run {}", test_name.id()),
+            description: format!("Run the test {} and its dependencies", test_name),
+
+            jigs: jig.into_iter().cloned().collect(),
+            tests: vec![test_name.clone()],
+            assumptions: assumptions.iter().filter(|x| *x != test_name).cloned().collect(),
+            parameters: BTreeMap::new(),
+
+            timeout: None,
+
+            unit_directory: unit_directory.to_owned(),
+            working_directory: None,
+
+            exec_start: None,
+            exec_start_timeout: None,
+            exec_stop_success: None,
+            exec_stop_success_timeout: None,
+            exec_stop_failure: None,
+            exec_stop_failure_timeout: None,
+            stop_after_failure_count: None,
+        }
+    }
+
     pub fn id(&self) -> &UnitName {
         &self.id
     }
@@ -342,6 +460,16 @@ impl ScenarioDescription {
     pub fn get_test_order(&self,
                           manager: &UnitManager)
                           -> Result<(Vec<UnitName>, Dependy<UnitName>), UnitIncompatibleReason> {
+        self.get_test_order_assuming(manager, &vec![])
+    }
+
+    /// Determine the test order, treating `extra_assumptions` as having passed
+    /// in addition to the tests listed in `Assume=`.
+    pub fn get_test_order_assuming(&self,
+                                   manager: &UnitManager,
+                                   extra_assumptions: &Vec<UnitName>)
+                                   -> Result<(Vec<UnitName>, Dependy<UnitName>), UnitIncompatibleReason> {
+        let is_assumed = |name: &UnitName| self.assumptions.contains(name) || extra_assumptions.contains(name);
 
         // Create a new dependency graph
         let mut graph = Dependy::new();
@@ -351,7 +479,7 @@ impl ScenarioDescription {
             let tests_rc = manager.get_tests();
             let tests = tests_rc.borrow();
             for (test_name, test) in tests.iter() {
-                if self.assumptions.contains(test_name) {
+                if is_assumed(test_name) {
                     let assumption_dep = AssumptionDependency::new(test_name.clone());
                     graph.add_dependency(&assumption_dep);
                 } else {
@@ -369,7 +497,7 @@ impl ScenarioDescription {
         let mut test_order = vec![];
         for test_name in test_sequence {
             // Only add the test to the test order if it's not an assumption.
-            if !self.assumptions.contains(&test_name) {
+            if !is_assumed(&test_name) {
                 test_order.push(test_name);
             }
         }
@@ -452,6 +580,30 @@ pub struct Scenario {
 
     /// The currently-executing program (if any)
     program: Rc<RefCell<Option<Running>>>,
+
+    /// The jig slot this instance is running on, if the jig has more than one.
+    slot: Option<u32>,
+
+    /// Extra environment variables passed to tests and support commands.
+    environment: HashMap<String, String>,
+
+    /// Whether the scenario should hold off on starting the next test.
+    paused: Rc<RefCell<bool>>,
+
+    /// Set by a "step" request to let exactly one more test start while paused.
+    step_once: Rc<RefCell<bool>>,
+
+    /// The test that would have been started, had the scenario not been paused.
+    held_test: Rc<RefCell<Option<UnitName>>>,
+
+    /// Set when the run has been aborted, so that no further tests get started.
+    aborted: Rc<RefCell<bool>>,
+
+    /// Assumptions and skips given when this run was started.
+    run_options: RunOptions,
+
+    /// Values stored by tests during this run, using "EXCLAVE-SET key value".
+    values: BTreeMap<String, String>,
 }
 
 impl Scenario {
@@ -487,9 +639,189 @@ impl Scenario {
             graph: graph,
             start_time: Instant::now(),
             program: Rc::new(RefCell::new(None)),
+            slot: None,
+            environment: HashMap::new(),
+            paused: Rc::new(RefCell::new(false)),
+            step_once: Rc::new(RefCell::new(false)),
+            held_test: Rc::new(RefCell::new(None)),
+            aborted: Rc::new(RefCell::new(false)),
+            run_options: RunOptions::default(),
+            values: BTreeMap::new(),
         }
     }
 
+    /// Create a separate copy of this scenario to run on the given jig slot.
+    /// Each copy gets its own tests, so that results from one slot don't
+    /// affect the others.
+    pub fn instance_for_slot(&self, manager: &UnitManager, slot: u32) -> Result<Scenario, UnitIncompatibleReason> {
+        let (_, graph) = self.description.get_test_order(manager)?;
+
+        let mut tests = HashMap::new();
+        let mut test_sequence = vec![];
+        let mut test_states = HashMap::new();
+        for test in &self.test_sequence {
+            let test = Rc::new(RefCell::new(test.borrow().instance_for_slot(slot)));
+            let test_name = test.borrow().id().clone();
+            test_sequence.push(test.clone());
+            test_states.insert(test_name.clone(), Rc::new(RefCell::new(TestState::Pending)));
+            tests.insert(test_name, test);
+        }
+
+        let mut environment = self.environment.clone();
+        environment.insert("EXCLAVE_SLOT".to_owned(), format!("{}", slot));
+
+        Ok(Scenario {
+            description: self.description.clone(),
+            tests: tests,
+            test_sequence: test_sequence,
+            test_states: test_states,
+            exec_start_state: Rc::new(RefCell::new(TestState::Pending)),
+            state: Rc::new(RefCell::new(ScenarioState::Idle)),
+            support_wd: Rc::new(RefCell::new(self.description.unit_directory.clone())),
+            failures: Rc::new(RefCell::new(0)),
+            graph: graph,
+            start_time: Instant::now(),
+            program: Rc::new(RefCell::new(None)),
+            slot: Some(slot),
+            environment: environment,
+            paused: Rc::new(RefCell::new(false)),
+            step_once: Rc::new(RefCell::new(false)),
+            held_test: Rc::new(RefCell::new(None)),
+            aborted: Rc::new(RefCell::new(false)),
+            run_options: self.run_options.clone(),
+            values: BTreeMap::new(),
+        })
+    }
+
+    /// Apply the assumptions, skips, and parameters for the next run.  If the set
+    /// of assumed tests changed, the test order is worked out again.
+    pub fn set_run_options(&mut self, manager: &UnitManager, options: &RunOptions) -> Result<(), UnitIncompatibleReason> {
+        for name in options.assume.iter().chain(options.skip.iter()) {
+            if manager.get_test_named(name).is_none() {
+                return Err(UnitIncompatibleReason::DependencyError(DepError::DependencyNotFound(name.clone())));
+            }
+        }
+
+        // Check the parameters against the ones the scenario declares, filling in defaults.
+        let mut parameters = BTreeMap::new();
+        for key in options.parameters.keys() {
+            if !self.description.parameters.contains_key(key) {
+                return Err(UnitIncompatibleReason::InvalidParameter(format!("{} is not a parameter of {}", key, self.id())));
+            }
+        }
+        for (key, default) in &self.description.parameters {
+            match options.parameters.get(key).or(default.as_ref()) {
+                Some(value) => {
+                    parameters.insert(key.clone(), value.clone());
+                }
+                None => {
+                    return Err(UnitIncompatibleReason::InvalidParameter(format!("{} requires a value for {}", self.id(), key)));
+                }
+            }
+        }
+
+        if options.assume != self.run_options.assume {
+            self.set_test_order(manager, &options.assume)?;
+        }
+
+        // Parameters are passed to tests and support commands as EXCLAVE_PARAM_<NAME>.
+        let stale_keys: Vec<String> = self.environment.keys().filter(|x| x.starts_with("EXCLAVE_PARAM_")).cloned().collect();
+        for key in stale_keys {
+            self.environment.remove(&key);
+        }
+        for (key, value) in &parameters {
+            self.environment.insert(Self::environment_variable("EXCLAVE_PARAM_", key), value.clone());
+        }
+
+        self.run_options = options.clone();
+        self.run_options.parameters = parameters;
+        Ok(())
+    }
+
+    /// Go back to running the scenario as its unit file describes, once the run
+    /// that the options were given for is over.
+    pub fn clear_run_options(&mut self, manager: &UnitManager) -> Result<(), UnitIncompatibleReason> {
+        if !self.run_options.assume.is_empty() {
+            self.set_test_order(manager, &vec![])?;
+        }
+        let stale_keys: Vec<String> = self.environment.keys().filter(|x| x.starts_with("EXCLAVE_PARAM_")).cloned().collect();
+        for key in stale_keys {
+            self.environment.remove(&key);
+        }
+        self.run_options = RunOptions::default();
+        Ok(())
+    }
+
+    pub fn run_options(&self) -> &RunOptions {
+        &self.run_options
+    }
+
+    /// Work out the test order again, treating `assume` as having passed.
+    fn set_test_order(&mut self, manager: &UnitManager, assume: &Vec<UnitName>) -> Result<(), UnitIncompatibleReason> {
+        let (test_order, graph) = self.description.get_test_order_assuming(manager, assume)?;
+        let mut tests = HashMap::new();
+        let mut test_sequence = vec![];
+        let mut test_states = HashMap::new();
+        for test_name in test_order {
+            let test = manager.get_test_named(&test_name).expect("Unable to check out requested test from library");
+            // Tests on a jig slot get their own copy, as with instance_for_slot().
+            let test = match self.slot {
+                Some(slot) => Rc::new(RefCell::new(test.borrow().instance_for_slot(slot))),
+                None => test,
+            };
+            test_sequence.push(test.clone());
+            test_states.insert(test_name.clone(), Rc::new(RefCell::new(TestState::Pending)));
+            tests.insert(test_name, test);
+        }
+        self.tests = tests;
+        self.test_sequence = test_sequence;
+        self.test_states = test_states;
+        self.graph = graph;
+        Ok(())
+    }
+
+    /// The name of the environment variable holding a parameter or value, e.g. the
+    /// parameter "serial-number" becomes EXCLAVE_PARAM_SERIAL_NUMBER.
+    fn environment_variable(prefix: &str, key: &str) -> String {
+        let name: String = key.chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+            .collect();
+        format!("{}{}", prefix, name)
+    }
+
+    /// Store a value for the rest of this run.  Tests that start afterwards, along
+    /// with support commands, see it as EXCLAVE_VALUE_<KEY>.
+    pub fn set_value(&mut self, key: &str, value: &str) {
+        self.environment.insert(Self::environment_variable("EXCLAVE_VALUE_", key), value.to_owned());
+        self.values.insert(key.to_owned(), value.to_owned());
+    }
+
+    fn is_assumed(&self, test_name: &UnitName) -> bool {
+        self.description.assumptions.contains(test_name) || self.run_options.assume.contains(test_name)
+    }
+
+    pub fn slot(&self) -> Option<u32> {
+        self.slot
+    }
+
+    /// Tests that this scenario treats as having passed without running them.
+    pub fn assumptions(&self) -> &Vec<UnitName> {
+        &self.description.assumptions
+    }
+
+    pub fn environment(&self) -> &HashMap<String, String> {
+        &self.environment
+    }
+
+    /// Tell tests and support commands where to save files for this run,
+    /// in EXCLAVE_ARTIFACT_DIR.
+    pub fn set_artifact_dir(&mut self, path: Option<&Path>) {
+        match path {
+            Some(p) => self.environment.insert("EXCLAVE_ARTIFACT_DIR".to_owned(), p.to_string_lossy().into_owned()),
+            None => self.environment.remove("EXCLAVE_ARTIFACT_DIR"),
+        };
+    }
+
     pub fn test_sequence(&self) -> Vec<UnitName> {
         let mut test_sequence = vec![];
         for test in &self.test_sequence {
@@ -502,6 +834,16 @@ impl Scenario {
         &self.tests
     }
 
+    /// Tests in this run that are still waiting to run, or are running now.
+    pub fn remaining_tests(&self) -> Vec<Rc<RefCell<Test>>> {
+        self.test_sequence.iter().filter(|test| {
+            match self.test_states.get(test.borrow().id()) {
+                Some(state) => *state.borrow() == TestState::Pending || *state.borrow() == TestState::Running,
+                None => false,
+            }
+        }).cloned().collect()
+    }
+
     pub fn id(&self) -> &UnitName {
         &self.description.id
     }
@@ -528,10 +870,32 @@ impl Scenario {
         self.start_time = Instant::now();
         *self.state.borrow_mut() = ScenarioState::Idle;
         *self.exec_start_state.borrow_mut() = TestState::Pending;
+        *self.paused.borrow_mut() = false;
+        *self.step_once.borrow_mut() = false;
+        *self.held_test.borrow_mut() = None;
+        *self.aborted.borrow_mut() = false;
         for (_, item) in &self.test_states {
             *item.borrow_mut() = TestState::Pending;
         }
 
+        // Values from a previous run don't carry over.
+        self.values.clear();
+        let stale_keys: Vec<String> = self.environment.keys().filter(|x| x.starts_with("EXCLAVE_VALUE_")).cloned().collect();
+        for key in stale_keys {
+            self.environment.remove(&key);
+        }
+
+        // Record any overrides for this run, and mark tests the operator asked to skip.
+        if !self.run_options.is_empty() {
+            ctrl.send(ManagerControlMessage::new_with_slot(self.id(), self.slot, ManagerControlMessageContents::Log(format!("run options: {}", self.run_options)))).ok();
+        }
+        for test_name in &self.run_options.skip {
+            if let Some(state) = self.test_states.get(test_name) {
+                *state.borrow_mut() = TestState::Skip;
+                ctrl.send(ManagerControlMessage::new_with_slot(self.id(), self.slot, ManagerControlMessageContents::Skip(test_name.clone(), "skipped when starting scenario".to_owned()))).ok();
+            }
+        }
+
         // Re-assign our working directory.
         if let &Some(ref wd) = &self.description.working_directory {
             config.set_scenario_working_directory(&wd);
@@ -545,7 +909,8 @@ impl Scenario {
         *self.support_wd.borrow_mut() = config.working_directory(&self.description.unit_directory, &self.description.working_directory);
 
         // Cause the scenario to move to the next (i.e. first) phase.
-        ctrl.send(ManagerControlMessage::new(self.id(), ManagerControlMessageContents::AdvanceScenario(0))).ok();
+        ctrl.send(ManagerControlMessage::new_with_slot(self.id(), self.slot, ManagerControlMessageContents::ScenarioStarted)).ok();
+        ctrl.send(ManagerControlMessage::new_with_slot(self.id(), self.slot, ManagerControlMessageContents::AdvanceScenario(0))).ok();
 
         Ok(())
     }
@@ -575,20 +940,20 @@ impl Scenario {
             ScenarioState::Running(step) => {
                 let test_id = self.test_sequence[step].borrow().id().clone();
                 if test_id != *last_unit {
-                    ctrl.send(ManagerControlMessage::new(self.id(), ManagerControlMessageContents::LogError(format!("unit {} is not the expected currently-running unit: {} (step {})", last_unit, test_id, step)))).ok();
+                    ctrl.send(ManagerControlMessage::new_with_slot(self.id(), self.slot, ManagerControlMessageContents::LogError(format!("unit {} is not the expected currently-running unit: {} (step {})", last_unit, test_id, step)))).ok();
                 }
                 let result = match last_result {
                     0 => TestState::Pass,
                     r => {
                         *self.failures.borrow_mut() += 1;
-                        ctrl.send(ManagerControlMessage::new(last_unit, ManagerControlMessageContents::LogError(format!("test failed with nonzero return code: {}", r)))).ok();
+                        ctrl.send(ManagerControlMessage::new_with_slot(last_unit, self.slot, ManagerControlMessageContents::LogError(format!("test failed with nonzero return code: {}", r)))).ok();
                         TestState::Fail(format!("test exited with nonzero return code: {}", r))
                     },
                 };
                 *self.test_states.get(&test_id).unwrap().borrow_mut() = result;
                 /* Run the test's STOP command */
                 if ! self.test_sequence[step].borrow().is_daemon() {
-                    ctrl.send(ManagerControlMessage::new(self.id(), ManagerControlMessageContents::StopTest(test_id))).ok();
+                    ctrl.send(ManagerControlMessage::new_with_slot(self.id(), self.slot, ManagerControlMessageContents::StopTest(test_id))).ok();
                 }
             }
             ScenarioState::PreStart => {
@@ -604,7 +969,11 @@ impl Scenario {
         }
 
         let new_state = self.find_next_state(current_state, ctrl);
+        self.enter_state(new_state, ctrl);
+    }
 
+    /// Do whatever is needed on entering a new state, such as starting a test.
+    fn enter_state(&self, new_state: ScenarioState, ctrl: &Sender<ManagerControlMessage>) {
         match new_state {
             // We generally shouldn't transition to the Idle state.
             ScenarioState::Idle => (),
@@ -622,7 +991,7 @@ impl Scenario {
                 let ref test = self.test_sequence[next_step].borrow();
                 let test_timeout = test.timeout();
                 let test_max_time = self.make_timeout(test_timeout);
-                ctrl.send(ManagerControlMessage::new(self.id(), ManagerControlMessageContents::StartTest(test.id().clone()))).ok();
+                self.start_test_unless_paused(test.id(), ctrl);
             }
             ScenarioState::PostSuccess => {
                 let cmd = &self.description.exec_stop_success.clone().unwrap();
@@ -645,11 +1014,79 @@ impl Scenario {
         }
     }
 
+    /// Start the given test, or hold on to it until the scenario is resumed or stepped.
+    fn start_test_unless_paused(&self, test_id: &UnitName, ctrl: &Sender<ManagerControlMessage>) {
+        if *self.paused.borrow() {
+            if *self.step_once.borrow() {
+                *self.step_once.borrow_mut() = false;
+            } else {
+                *self.held_test.borrow_mut() = Some(test_id.clone());
+                ctrl.send(ManagerControlMessage::new_with_slot(self.id(), self.slot, ManagerControlMessageContents::ScenarioPaused(test_id.clone()))).ok();
+                return;
+            }
+        }
+        ctrl.send(ManagerControlMessage::new_with_slot(self.id(), self.slot, ManagerControlMessageContents::StartTest(test_id.clone()))).ok();
+    }
+
+    /// Stop starting new tests.  The currently-running test, if any, is allowed to finish.
+    pub fn pause(&self) {
+        *self.paused.borrow_mut() = true;
+        *self.step_once.borrow_mut() = false;
+    }
+
+    /// Continue running tests, starting the held test if there is one.
+    pub fn resume(&self, ctrl: &Sender<ManagerControlMessage>) {
+        *self.paused.borrow_mut() = false;
+        *self.step_once.borrow_mut() = false;
+        if let Some(test_id) = self.held_test.borrow_mut().take() {
+            ctrl.send(ManagerControlMessage::new_with_slot(self.id(), self.slot, ManagerControlMessageContents::StartTest(test_id))).ok();
+        }
+    }
+
+    /// Run exactly one more test, then pause again.  Pauses the scenario if it wasn't already.
+    pub fn step(&self, ctrl: &Sender<ManagerControlMessage>) {
+        *self.paused.borrow_mut() = true;
+        if let Some(test_id) = self.held_test.borrow_mut().take() {
+            *self.step_once.borrow_mut() = false;
+            ctrl.send(ManagerControlMessage::new_with_slot(self.id(), self.slot, ManagerControlMessageContents::StartTest(test_id))).ok();
+        } else {
+            *self.step_once.borrow_mut() = true;
+        }
+    }
+
+    /// Whether the scenario is holding on to a test until it is resumed or stepped.
+    pub fn is_paused(&self) -> bool {
+        self.held_test.borrow().is_some()
+    }
+
+    /// Stop the run early.  The current test is stopped and counts as a failure,
+    /// no further tests are started, and ExecStopFailure is run as usual.
+    pub fn abort(&self, ctrl: &Sender<ManagerControlMessage>) {
+        *self.aborted.borrow_mut() = true;
+        *self.paused.borrow_mut() = false;
+        *self.step_once.borrow_mut() = false;
+
+        let current_state = self.state.borrow().clone();
+        if let ScenarioState::Running(step) = current_state {
+            let test_id = self.test_sequence[step].borrow().id().clone();
+            if self.held_test.borrow_mut().take().is_some() {
+                // The test was held by a pause and never started, so skip it and move on.
+                *self.test_states.get(&test_id).unwrap().borrow_mut() = TestState::Skip;
+                ctrl.send(ManagerControlMessage::new_with_slot(self.id(), self.slot, ManagerControlMessageContents::Skip(test_id, "scenario aborted".to_owned()))).ok();
+                let new_state = self.find_next_state(current_state, ctrl);
+                self.enter_state(new_state, ctrl);
+            } else {
+                // Stopping the test causes it to finish, which advances the scenario.
+                ctrl.send(ManagerControlMessage::new_with_slot(self.id(), self.slot, ManagerControlMessageContents::StopTest(test_id))).ok();
+            }
+        }
+    }
+
     /// Run a support command (i.e. ExecStart, ExecStopSuccess, or ExecStopFailure).
     /// Will emit an AdvanceScenario message upon completion.
     fn run_support_cmd(&self, cmd: &String, ctrl: &Sender<ManagerControlMessage>, timeout: &Option<Duration>, testname: &str) {
-        ctrl.send(ManagerControlMessage::new(self.id(), ManagerControlMessageContents::Log(format!("{}: starting [{}]", testname, cmd)))).ok();
-        let mut run_cmd = Runny::new(cmd);
+        ctrl.send(ManagerControlMessage::new_with_slot(self.id(), self.slot, ManagerControlMessageContents::Log(format!("{}: starting [{}]", testname, cmd)))).ok();
+        let mut run_cmd = Runny::new(&command_with_environment(cmd, &self.environment));
         if let Some(timeout) = *timeout {
             run_cmd.timeout(timeout);
         }
@@ -657,54 +1094,63 @@ impl Scenario {
         let mut running = match run_cmd.start() {
             Ok(o) => o,
             Err(e) => {
-                ctrl.send(ManagerControlMessage::new(self.id(), ManagerControlMessageContents::LogError(format!("{}: unable to run command: {:?}", testname, e)))).ok();
-                ctrl.send(ManagerControlMessage::new(self.id(), ManagerControlMessageContents::AdvanceScenario(1))).ok();
+                ctrl.send(ManagerControlMessage::new_with_slot(self.id(), self.slot, ManagerControlMessageContents::LogError(format!("{}: unable to run command: {:?}", testname, e)))).ok();
+                ctrl.send(ManagerControlMessage::new_with_slot(self.id(), self.slot, ManagerControlMessageContents::AdvanceScenario(1))).ok();
                 return;
             }
         };
 
-        self.log_output(ctrl, &mut running);
+        let output_done = self.log_output(ctrl, &mut running);
 
         // Keep a waiter around in a separate thread to send that AdvanceScenario message upon completion.
         let thr_waiter = running.waiter();
         let thr_control = ctrl.clone();
         let id = self.id().clone();
+        let slot = self.slot;
         let thr_cmd = cmd.clone();
         let thr_testname = testname.to_owned();
         thread::spawn(move || {
             thr_waiter.wait();
-            thr_control.send(ManagerControlMessage::new(&id, ManagerControlMessageContents::AdvanceScenario(thr_waiter.result()))).ok();
-            thr_control.send(ManagerControlMessage::new(&id, ManagerControlMessageContents::Log(format!("{}: finished [{}]", thr_testname, thr_cmd)))).ok();
+            // As with tests, let the last lines of output through before moving on.
+            output_done.recv_timeout(Duration::from_secs(1)).ok();
+            thr_control.send(ManagerControlMessage::new_with_slot(&id, slot, ManagerControlMessageContents::AdvanceScenario(thr_waiter.result()))).ok();
+            thr_control.send(ManagerControlMessage::new_with_slot(&id, slot, ManagerControlMessageContents::Log(format!("{}: finished [{}]", thr_testname, thr_cmd)))).ok();
         });
 
         *self.program.borrow_mut() = Some(running);
     }
 
-    fn log_output(&self, control: &Sender<ManagerControlMessage>, process: &mut Running) {
+    /// Log each line of output.  The returned channel is closed once stdout has been read.
+    fn log_output(&self, control: &Sender<ManagerControlMessage>, process: &mut Running) -> Receiver<()> {
         
         let stdout = process.take_output();
         let thr_control = control.clone();
         let id = self.id().clone();
+        let slot = self.slot;
+        let (done_sender, done_receiver) = channel();
         thread::spawn(move || {
             for line in BufReader::new(stdout).lines() {
                 let line = line.expect("Unable to get next line");
-                if let Err(_) = thr_control.send(ManagerControlMessage::new(&id, ManagerControlMessageContents::Log(line))) {
+                if let Err(_) = thr_control.send(ManagerControlMessage::new_with_slot(&id, slot, ManagerControlMessageContents::Output(LogSource::Stdout, line))) {
                     break;
                 }
             }
+            drop(done_sender);
         });
 
         let stderr = process.take_error();
         let thr_control = control.clone();
         let id = self.id().clone();
+        let slot = self.slot;
         thread::spawn(move || {
             for line in BufReader::new(stderr).lines() {
                 let line = line.expect("Unable to get next line");
-                if let Err(_) = thr_control.send(ManagerControlMessage::new(&id, ManagerControlMessageContents::LogError(line))) {
+                if let Err(_) = thr_control.send(ManagerControlMessage::new_with_slot(&id, slot, ManagerControlMessageContents::Output(LogSource::Stderr, line))) {
                     break;
                 }
             }
         });
+        done_receiver
     }
 
     /// Find the next state.
@@ -716,6 +1162,7 @@ impl Scenario {
 
         let test_count = self.tests.len();
         let failure_count = *self.failures.borrow();
+        let failed = failure_count > 0 || *self.aborted.borrow();
 
         let new_state = match current_state {
             ScenarioState::Idle => {
@@ -743,10 +1190,10 @@ impl Scenario {
                     }
                 }
             },
-            ScenarioState::Running(i) if (i + 1) >= test_count && failure_count > 0 => {
+            ScenarioState::Running(i) if (i + 1) >= test_count && failed => {
                 ScenarioState::PostFailure
             }
-            ScenarioState::Running(i) if (i + 1) >= test_count && failure_count == 0 => {
+            ScenarioState::Running(i) if (i + 1) >= test_count && !failed => {
                 ScenarioState::PostSuccess
             }
             ScenarioState::Running(i) => {
@@ -788,7 +1235,7 @@ impl Scenario {
                 let tests = &self.test_sequence;
                 let test = tests[i].borrow();
                 let test_name = test.id();
-                if self.scenario_timed_out() {
+                if self.scenario_timed_out() || *self.aborted.borrow() {
                     false
                 } else if i >= self.tests.len() {
                     false
@@ -802,7 +1249,7 @@ impl Scenario {
                 // Make sure all required dependencies succeeded.
                 else if !self.all_dependencies_succeeded(&test_name) {
                     *self.test_states.get(test_name).unwrap().borrow_mut() = TestState::Skip;
-                    ctrl.send(ManagerControlMessage::new(self.id(), ManagerControlMessageContents::Skip(test_name.clone(), "dependency failed".to_owned()))).ok();
+                    ctrl.send(ManagerControlMessage::new_with_slot(self.id(), self.slot, ManagerControlMessageContents::Skip(test_name.clone(), "dependency failed".to_owned()))).ok();
                     false
                 } else {
                     true
@@ -822,7 +1269,7 @@ impl Scenario {
 
     fn all_dependencies_succeeded(&self, test_name: &UnitName) -> bool {
         for parent_name in self.graph.required_parents_of_named(test_name) {
-            if self.description.assumptions.contains(parent_name) {
+            if self.is_assumed(parent_name) {
                 return true;
             }
 
@@ -878,17 +1325,25 @@ impl Scenario {
         let failures = *self.failures.borrow();
         for test in &self.test_sequence {
             // Stop the test.  This will catch normal tests and daemons.
-            ctrl.send(ManagerControlMessage::new(self.id(),
+            ctrl.send(ManagerControlMessage::new_with_slot(self.id(), self.slot,
                                                 ManagerControlMessageContents::StopTest(test.borrow().id().clone()))).ok();
         }
         // Also stop the scenario.
-        ctrl.send(ManagerControlMessage::new(self.id(),
+        ctrl.send(ManagerControlMessage::new_with_slot(self.id(), self.slot,
                                             ManagerControlMessageContents::StopTest(self.id().clone()))).ok();
-        if failures > 0 {
-            ctrl.send(ManagerControlMessage::new(self.id(),
+        // Report whatever the tests stored, so loggers and interfaces can record it.
+        if !self.values.is_empty() {
+            ctrl.send(ManagerControlMessage::new_with_slot(self.id(), self.slot,
+                                                ManagerControlMessageContents::Values(self.values.clone()))).ok();
+        }
+        if *self.aborted.borrow() {
+            ctrl.send(ManagerControlMessage::new_with_slot(self.id(), self.slot,
+                                                ManagerControlMessageContents::ScenarioFinished(failures + 500, "scenario aborted".to_owned()))).ok();
+        } else if failures > 0 {
+            ctrl.send(ManagerControlMessage::new_with_slot(self.id(), self.slot,
                                                 ManagerControlMessageContents::ScenarioFinished(failures + 500, "at least one test failed".to_owned()))).ok();
         } else {
-            ctrl.send(ManagerControlMessage::new(self.id(),
+            ctrl.send(ManagerControlMessage::new_with_slot(self.id(), self.slot,
                                                 ManagerControlMessageContents::ScenarioFinished(200, "all tests passed".to_owned()))).ok();
         }
     }
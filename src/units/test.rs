@@ -5,13 +5,14 @@ extern crate runny;
 extern crate systemd_parser;
 
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::error::Error;
 use std::fs::File;
-use std::io::{BufRead, BufReader, Read};
+use std::io::{BufRead, BufReader, Read, Write};
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::sync::{Arc, Mutex};
-use std::sync::mpsc::Sender;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
 use std::thread;
 use std::time::Duration;
 
@@ -19,12 +20,13 @@ use self::dependy::Dependency;
 use self::humantime::{parse_duration, DurationError};
 use self::regex::Regex;
 use self::runny::Runny;
-use self::runny::running::{RunningOutput, RunningWaiter};
+use self::runny::running::{RunningInput, RunningOutput, RunningWaiter};
 use self::systemd_parser::items::DirectiveEntry;
 
-use config::Config;
+use config::{command_with_environment, Config};
 use unit::{UnitName, UnitActivateError, UnitDeactivateError, UnitSelectError, UnitDeselectError,
            UnitIncompatibleReason, UnitDescriptionError};
+use unitbroadcaster::LogSource;
 use unitmanager::{ManagerControlMessage, ManagerControlMessageContents,
                   UnitManager};
 
@@ -32,6 +34,7 @@ use unitmanager::{ManagerControlMessage, ManagerControlMessageContents,
 enum TestType {
     Simple,
     Daemon,
+    Manual,
 }
 
 /// A struct defining an in-memory representation of a .test file
@@ -70,11 +73,21 @@ pub struct TestDescription {
     /// The maximum amount of time to allow an ExecStopFailure to run.
     exec_stop_failure_timeout: Option<Duration>,
 
-    /// Type: One of "simple" or "daemon".  For "simple" tests, the return code will indicate pass or fail,
+    /// Type: One of "simple", "daemon", or "manual".  For "simple" tests, the return code will indicate pass or fail,
     /// and each line printed will be considered progress.  For "daemon", the process will be forked
-    /// and left to run in the background.  See "daemons" in the documentation.
+    /// and left to run in the background.  See "daemons" in the documentation.  For "manual", no
+    /// program is run, and an operator is asked to judge the result instead.
     test_type: TestType,
 
+    /// Prompt: The question to ask the operator when running a manual test.
+    prompt: String,
+
+    /// Responses: The answers the operator may give to a manual test.
+    responses: Vec<String>,
+
+    /// PassResponses: The answers that cause a manual test to pass.  Any other allowed answer fails it.
+    pass_responses: Vec<String>,
+
     /// If present, the daemon won't be considered "ready" until this string is matched.
     test_daemon_ready: Option<Regex>,
 
@@ -127,6 +140,9 @@ impl TestDescription {
             exec_stop_failure_timeout: None,
 
             test_type: TestType::Simple,
+            prompt: "".to_owned(),
+            responses: vec!["pass".to_owned(), "fail".to_owned()],
+            pass_responses: vec![],
 
             test_daemon_ready: None,
 
@@ -185,16 +201,26 @@ impl TestDescription {
                                     match s.to_string().to_lowercase().as_ref() {
                                         "simple" => TestType::Simple,
                                         "daemon" => TestType::Daemon,
+                                        "manual" => TestType::Manual,
                                         other => return Err(UnitDescriptionError::InvalidValue(
                                             "Test".to_owned(),
                                         "Type".to_owned(),
                                         other.to_owned(),
-                                        vec!["Simple".to_owned(), "Daemon".to_owned()])),
+                                        vec!["Simple".to_owned(), "Daemon".to_owned(), "Manual".to_owned()])),
                                     }
                                 }
                                 None => TestType::Simple,
                             };
                         }
+                        "Prompt" => {
+                            test_description.prompt = directive.value().unwrap_or("").to_owned()
+                        }
+                        "Responses" => {
+                            test_description.responses = Self::parse_responses(directive.value().unwrap_or(""));
+                        }
+                        "PassResponses" => {
+                            test_description.pass_responses = Self::parse_responses(directive.value().unwrap_or(""));
+                        }
                         "WorkingDirectory" => {
                             // If a WorkingDirectory was specified, add it to the current directory
                             // (replaces `working_directory` if the new WD is absolute)
@@ -243,12 +269,41 @@ impl TestDescription {
                 &_ => (),
             }
         }
-        if test_description.exec_start == "" {
+        if test_description.test_type == TestType::Manual {
+            if test_description.prompt == "" {
+                return Err(UnitDescriptionError::MissingValue("Test".to_owned(), "Prompt".to_owned()));
+            }
+            if test_description.responses.is_empty() {
+                return Err(UnitDescriptionError::MissingValue("Test".to_owned(), "Responses".to_owned()));
+            }
+            // Unless told otherwise, the first response is the passing one.
+            if test_description.pass_responses.is_empty() {
+                test_description.pass_responses = vec![test_description.responses[0].clone()];
+            }
+            for response in &test_description.pass_responses {
+                if !test_description.responses.contains(response) {
+                    return Err(UnitDescriptionError::InvalidValue(
+                        "Test".to_owned(),
+                        "PassResponses".to_owned(),
+                        response.clone(),
+                        test_description.responses.clone(),
+                    ));
+                }
+            }
+        } else if test_description.exec_start == "" {
             return Err(UnitDescriptionError::MissingValue("Test".to_owned(), "ExecStart".to_owned()));
         }
         Ok(test_description)
     }
 
+    /// Responses are single words, separated by commas or whitespace.
+    fn parse_responses(value: &str) -> Vec<String> {
+        value.split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|x| !x.is_empty())
+            .map(|x| x.to_lowercase())
+            .collect()
+    }
+
     fn parse_time(time_str: &str) -> Result<Duration, DurationError> {
         if let Ok(val) = time_str.parse::<u64>() {
             Ok(Duration::from_secs(val))
@@ -278,18 +333,40 @@ pub struct Test {
     program: Rc<RefCell<Option<RunningWaiter>>>,
     result_arc: Arc<Mutex<Option<i32>>>,
     last_line: Arc<Mutex<String>>,
+
+    /// The jig slot this copy of the test runs on, if any.
+    slot: Option<u32>,
+
+    /// While a manual test is waiting for the operator, this passes their answer along.
+    answer: Rc<RefCell<Option<Sender<(i32, String)>>>>,
+
+    /// The stdin of the running program, where answers to its prompts are written.
+    input: Rc<RefCell<Option<RunningInput>>>,
 }
 
 impl Test {
     pub fn new(desc: &TestDescription) -> Test {
+        Self::new_with_slot(desc, None)
+    }
+
+    /// Create a private copy of a test for running on a particular jig slot.
+    pub fn new_with_slot(desc: &TestDescription, slot: Option<u32>) -> Test {
         Test {
             description: desc.clone(),
             program: Rc::new(RefCell::new(None)),
             result_arc: Arc::new(Mutex::new(None)),
             last_line: Arc::new(Mutex::new("".to_owned())),
+            slot: slot,
+            answer: Rc::new(RefCell::new(None)),
+            input: Rc::new(RefCell::new(None)),
          }
     }
 
+    /// Create a fresh copy of this test for the given jig slot.
+    pub fn instance_for_slot(&self, slot: u32) -> Test {
+        Self::new_with_slot(&self.description, Some(slot))
+    }
+
     pub fn select(&self, manager: &UnitManager) -> Result<(), UnitSelectError> {
         // If there is at least one jig in the description list, then make sure
         // that jig is loaded.
@@ -316,6 +393,7 @@ impl Test {
     /// Send the "test finished" message and update the local result value.
     /// This ensures that we only send the "Finished" result once.
     pub fn send_finished_once(id: &UnitName,
+                              slot: Option<u32>,
                               ctrl: &Sender<ManagerControlMessage>,
                               result_val: i32,
                               result_arc: &Arc<Mutex<Option<i32>>>,
@@ -324,7 +402,7 @@ impl Test {
         let mut result = result_arc.lock().unwrap();
 
         if result.is_none() {
-            ctrl.send(ManagerControlMessage::new(&id, ManagerControlMessageContents::TestFinished(result_val, last_line.lock().unwrap().clone()))).ok();
+            ctrl.send(ManagerControlMessage::new_with_slot(&id, slot, ManagerControlMessageContents::TestFinished(result_val, last_line.lock().unwrap().clone()))).ok();
             *result = Some(result_val);
         }
     }
@@ -333,21 +411,28 @@ impl Test {
         &mut self,
         manager: &UnitManager,
         config: &Config,
+        environment: &HashMap<String, String>,
     ) -> Result<(), UnitActivateError> {
 
         // We'll communicate to the manager through this pipe.
         let ctrl = manager.get_control_channel();
         let id = self.id().clone();
+        let slot = self.slot;
 
         *self.result_arc.lock().unwrap() = None;
 
         // Announce to the world that we've started considering this test.
-        ctrl.send(ManagerControlMessage::new(&id, ManagerControlMessageContents::TestStarted)).ok();
+        ctrl.send(ManagerControlMessage::new_with_slot(&id, slot, ManagerControlMessageContents::TestStarted)).ok();
+
+        if self.description.test_type == TestType::Manual {
+            self.ask_operator(&ctrl);
+            return Ok(());
+        }
 
         let cmd = &self.description.exec_start;
         let timeout = &self.description.timeout;
 
-        let mut cmd = Runny::new(cmd);
+        let mut cmd = Runny::new(&command_with_environment(cmd, environment));
         if let Some(timeout) = *timeout {
             cmd.timeout(timeout);
         }
@@ -355,9 +440,9 @@ impl Test {
         let mut running = match cmd.start() {
             Ok(r) => r,
             Err(e) => {
-                ctrl.send(ManagerControlMessage::new(&id, ManagerControlMessageContents::LogError(format!("unable to start test: {:?}", e)))).unwrap();
-                ctrl.send(ManagerControlMessage::new(&id, ManagerControlMessageContents::TestFinished(-3, format!("unable to start test: {:?}", e)))).ok();
-                ctrl.send(ManagerControlMessage::new(&id, ManagerControlMessageContents::AdvanceScenario(-3))).ok();
+                ctrl.send(ManagerControlMessage::new_with_slot(&id, slot, ManagerControlMessageContents::LogError(format!("unable to start test: {:?}", e)))).unwrap();
+                ctrl.send(ManagerControlMessage::new_with_slot(&id, slot, ManagerControlMessageContents::TestFinished(-3, format!("unable to start test: {:?}", e)))).ok();
+                ctrl.send(ManagerControlMessage::new_with_slot(&id, slot, ManagerControlMessageContents::AdvanceScenario(-3))).ok();
                 return Err(UnitActivateError::ExecFailed(e));
             }
         };
@@ -365,6 +450,8 @@ impl Test {
         // Keep track of the last line, which we can use to report test status.
         let last_line = self.last_line.clone();
 
+        *self.input.borrow_mut() = Some(running.take_input());
+
         let waiter = running.waiter();
         let thr_control = ctrl.clone();
         let thr_last_line = self.last_line.clone();
@@ -374,7 +461,7 @@ impl Test {
                 let daemon_ready_string = self.description.test_daemon_ready.clone();
 
                 thread::spawn(move || {
-                    Self::log_error(&id, &ctrl, running.take_error(), &last_line);
+                    Self::log_error(&id, slot, &ctrl, running.take_error(), &last_line);
                     let buf_reader = BufReader::new(running.take_output());
                     let buf_lines = buf_reader.lines();
                     let mut buf_iter = buf_lines.into_iter();
@@ -383,15 +470,15 @@ impl Test {
                         while let Some(line_result) = buf_iter.next() {
                             match line_result {
                                 Err(e) => {
-                                    thr_control.send(ManagerControlMessage::new(&id, ManagerControlMessageContents::LogError(format!("test daemon raised an error: {}", e.description())))).unwrap();
-                                    thr_control.send(ManagerControlMessage::new(&id, ManagerControlMessageContents::AdvanceScenario(-2))).ok();
+                                    thr_control.send(ManagerControlMessage::new_with_slot(&id, slot, ManagerControlMessageContents::LogError(format!("test daemon raised an error: {}", e.description())))).unwrap();
+                                    thr_control.send(ManagerControlMessage::new_with_slot(&id, slot, ManagerControlMessageContents::AdvanceScenario(-2))).ok();
                                     running.terminate(Some(Duration::from_secs(1))).ok();
                                     // thr_control.send(ManagerControlMessage::new(&id, ManagerControlMessageContents::TestFinished(-2, thr_last_line.lock().unwrap().clone()))).ok();
-                                    Self::send_finished_once(&id, &thr_control, -2, &thr_result_arc, &thr_last_line);
+                                    Self::send_finished_once(&id, slot, &thr_control, -2, &thr_result_arc, &thr_last_line);
                                     return;
                                 }
                                 Ok(line) => {
-                                    thr_control.send(ManagerControlMessage::new(&id, ManagerControlMessageContents::Log(line.clone()))).unwrap();
+                                    thr_control.send(ManagerControlMessage::new_with_slot(&id, slot, Self::output_contents(line.clone()))).unwrap();
                                     if r.is_match(&line) {
                                         found = true;
                                         break;
@@ -400,10 +487,10 @@ impl Test {
                             }
                         }
                         if !found {
-                            thr_control.send(ManagerControlMessage::new(&id, ManagerControlMessageContents::LogError(format!("test daemon exited before ready string was found")))).unwrap();
-                            thr_control.send(ManagerControlMessage::new(&id, ManagerControlMessageContents::AdvanceScenario(-1))).ok();
+                            thr_control.send(ManagerControlMessage::new_with_slot(&id, slot, ManagerControlMessageContents::LogError(format!("test daemon exited before ready string was found")))).unwrap();
+                            thr_control.send(ManagerControlMessage::new_with_slot(&id, slot, ManagerControlMessageContents::AdvanceScenario(-1))).ok();
                             running.terminate(Some(Duration::from_secs(1))).ok();
-                            Self::send_finished_once(&id, &thr_control, -1, &thr_result_arc, &thr_last_line);
+                            Self::send_finished_once(&id, slot, &thr_control, -1, &thr_result_arc, &thr_last_line);
 //                            thr_control.send(ManagerControlMessage::new(&id, ManagerControlMessageContents::TestFinished(-1, thr_last_line.lock().unwrap().clone()))).ok();
                             return;
                         }
@@ -416,41 +503,101 @@ impl Test {
                         for line in buf_iter {
                             let line = line.expect("Unable to get next line");
                             *thr_thr_last_line.lock().unwrap() = line.clone();
-                            if let Err(_) = thr_thr_control.send(ManagerControlMessage::new(&thr_id, ManagerControlMessageContents::Log(line))) {
+                            if let Err(_) = thr_thr_control.send(ManagerControlMessage::new_with_slot(&thr_id, slot, Self::output_contents(line))) {
                                 break;
                             }
                         }
                     });
 
                     // Advance to the next test while this one hangs out.
-                    thr_control.send(ManagerControlMessage::new(&id, ManagerControlMessageContents::AdvanceScenario(0))).ok();
+                    thr_control.send(ManagerControlMessage::new_with_slot(&id, slot, ManagerControlMessageContents::AdvanceScenario(0))).ok();
                     running.wait().ok();
-                    Self::send_finished_once(&id, &thr_control, running.result(), &thr_result_arc, &thr_last_line);
+                    Self::send_finished_once(&id, slot, &thr_control, running.result(), &thr_result_arc, &thr_last_line);
                 });
             },
             TestType::Simple => {
 
                 // Keep a waiter around in a separate thread to send that AdvanceScenario message upon completion.
-                Self::log_output(&id, &ctrl, running.take_output(), &last_line);
-                Self::log_error(&id, &ctrl, running.take_error(), &last_line);
+                let output_done = Self::log_output(&id, slot, &ctrl, running.take_output(), &last_line);
+                Self::log_error(&id, slot, &ctrl, running.take_error(), &last_line);
                 thread::spawn(move || {
                     running.wait().ok();
-                    Self::send_finished_once(&id, &thr_control, running.result(), &thr_result_arc, &thr_last_line);
-                    thr_control.send(ManagerControlMessage::new(&id, ManagerControlMessageContents::AdvanceScenario(running.result()))).ok();
+                    // Let the last lines of output through first, so any values the test set
+                    // are stored before the next test starts.  Don't wait forever, since a
+                    // child process may still be holding stdout open.
+                    output_done.recv_timeout(Duration::from_secs(1)).ok();
+                    Self::send_finished_once(&id, slot, &thr_control, running.result(), &thr_result_arc, &thr_last_line);
+                    thr_control.send(ManagerControlMessage::new_with_slot(&id, slot, ManagerControlMessageContents::AdvanceScenario(running.result()))).ok();
                 });
             }
+            TestType::Manual => unreachable!("manual tests don't run a program"),
         }
         *self.program.borrow_mut() = Some(waiter);
 
         Ok(())
     }
 
+    /// Prompt the operator, and wait for an answer in a separate thread.  If the
+    /// test has a timeout and nobody answers in time, the test fails.
+    fn ask_operator(&self, ctrl: &Sender<ManagerControlMessage>) {
+        let id = self.id().clone();
+        let slot = self.slot;
+        let timeout = self.description.timeout;
+        let thr_control = ctrl.clone();
+        let thr_last_line = self.last_line.clone();
+        let thr_result_arc = self.result_arc.clone();
+        let (answer_sender, answer_receiver) = channel();
+        *self.answer.borrow_mut() = Some(answer_sender);
+
+        ctrl.send(ManagerControlMessage::new_with_slot(&id, slot, ManagerControlMessageContents::Prompt(self.description.prompt.clone(), self.description.responses.clone()))).ok();
+        thread::spawn(move || {
+            let answer = match timeout {
+                Some(t) => answer_receiver.recv_timeout(t).map_err(|e| e == RecvTimeoutError::Timeout),
+                None => answer_receiver.recv().map_err(|_| false),
+            };
+            let (result, message) = match answer {
+                Ok(a) => a,
+                Err(true) => (-1, "no answer before timeout".to_owned()),
+                // The test was stopped before anyone answered.
+                Err(false) => return,
+            };
+            *thr_last_line.lock().unwrap() = message;
+            Self::send_finished_once(&id, slot, &thr_control, result, &thr_result_arc, &thr_last_line);
+            thr_control.send(ManagerControlMessage::new_with_slot(&id, slot, ManagerControlMessageContents::AdvanceScenario(result))).ok();
+        });
+    }
+
+    /// Answer a prompt.  Manual tests pass or fail based on the answer, while
+    /// running programs that asked with EXCLAVE-PROMPT get it on stdin.
+    pub fn answer(&self, response: &str) -> Result<(), String> {
+        if self.description.test_type != TestType::Manual {
+            return match *self.input.borrow_mut() {
+                Some(ref mut input) => writeln!(input, "{}", response).map_err(|e| format!("unable to write answer: {}", e)),
+                None => Err("not running".to_owned()),
+            };
+        }
+
+        let response = response.to_lowercase();
+        if !self.description.responses.contains(&response) {
+            return Err(format!("expected one of: {}", self.description.responses.join(", ")));
+        }
+        let sender = match self.answer.borrow_mut().take() {
+            Some(s) => s,
+            None => return Err("not waiting for an answer".to_owned()),
+        };
+        let result = if self.description.pass_responses.contains(&response) { 0 } else { 1 };
+        sender.send((result, format!("operator answered {}", response))).map_err(|_| "no longer waiting for an answer".to_owned())
+    }
+
     pub fn deactivate(&self, manager: &UnitManager) -> Result<(), UnitDeactivateError> {
+        // Stop waiting on the operator, if this is a manual test.
+        self.answer.borrow_mut().take();
+        self.input.borrow_mut().take();
         if let Some(ref running) = *self.program.borrow_mut() {
             // For Daemons, if they haven't failed so far, then they might fail when we tell them
             // to quit.  Since they've fulfilled their purpose, issue a "pass" message.
             if self.description.test_type == TestType::Daemon {
-                Self::send_finished_once(&self.description.id, &manager.get_control_channel(), 0, &self.result_arc, &self.last_line);
+                Self::send_finished_once(&self.description.id, self.slot, &manager.get_control_channel(), 0, &self.result_arc, &self.last_line);
             }
             running.terminate(&None);
         }
@@ -475,26 +622,74 @@ impl Test {
         &self.description.description
     }
 
+    pub fn unit_directory(&self) -> &PathBuf {
+        &self.description.unit_directory
+    }
+
     pub fn timeout(&self) -> &Option<Duration> {
         &self.description.timeout
     }
 
-    fn log_output(id: &UnitName, control: &Sender<ManagerControlMessage>, stdout: RunningOutput, last_line: &Arc<Mutex<String>>) {
+    /// Log each line of stdout.  The returned channel is closed once stdout has been read.
+    fn log_output(id: &UnitName, slot: Option<u32>, control: &Sender<ManagerControlMessage>, stdout: RunningOutput, last_line: &Arc<Mutex<String>>) -> Receiver<()> {
         let thr_control = control.clone();
         let thr_last_line = last_line.clone();
         let thr_id = id.clone();
+        let (done_sender, done_receiver) = channel();
         thread::spawn(move || {
             for line in BufReader::new(stdout).lines() {
                 let line = line.expect("Unable to get next line");
                 *thr_last_line.lock().unwrap() = line.clone();
-                if let Err(_) = thr_control.send(ManagerControlMessage::new(&thr_id, ManagerControlMessageContents::Log(line))) {
+                if let Err(_) = thr_control.send(ManagerControlMessage::new_with_slot(&thr_id, slot, Self::output_contents(line))) {
                     break;
                 }
             }
+            drop(done_sender);
         });
+        done_receiver
+    }
+
+    /// Turn a line of test output into a message.  Lines of the form
+    /// "EXCLAVE-SET key value" store a value for later tests, lines of the
+    /// form "EXCLAVE-PROMPT [choice,choice] question" ask the operator
+    /// something, "EXCLAVE-PROGRESS percent status" reports progress, and
+    /// everything else gets logged.
+    fn output_contents(line: String) -> ManagerControlMessageContents {
+        if line.starts_with("EXCLAVE-PROGRESS ") {
+            let mut words = line["EXCLAVE-PROGRESS ".len()..].trim().splitn(2, char::is_whitespace);
+            if let Some(Ok(percent)) = words.next().map(|x| x.trim_end_matches('%').parse::<u32>()) {
+                let status = words.next().unwrap_or("").trim().to_owned();
+                return ManagerControlMessageContents::Progress(percent.min(100), status);
+            }
+        }
+        if line.starts_with("EXCLAVE-PROMPT ") {
+            let prompt = line["EXCLAVE-PROMPT ".len()..].trim();
+            // An optional list of allowed responses may come first, in square brackets.
+            if prompt.starts_with('[') {
+                if let Some(end) = prompt.find(']') {
+                    let responses = prompt[1..end]
+                        .split(|c: char| c == ',' || c.is_whitespace())
+                        .filter(|x| !x.is_empty())
+                        .map(|x| x.to_lowercase())
+                        .collect();
+                    return ManagerControlMessageContents::Prompt(prompt[end + 1..].trim().to_owned(), responses);
+                }
+            }
+            return ManagerControlMessageContents::Prompt(prompt.to_owned(), vec![]);
+        }
+        if line.starts_with("EXCLAVE-SET ") {
+            let mut words = line["EXCLAVE-SET ".len()..].trim().splitn(2, char::is_whitespace);
+            if let Some(key) = words.next() {
+                if !key.is_empty() {
+                    let value = words.next().unwrap_or("").trim_start().to_owned();
+                    return ManagerControlMessageContents::SetValue(key.to_owned(), value);
+                }
+            }
+        }
+        ManagerControlMessageContents::Output(LogSource::Stdout, line)
     }
 
-    fn log_error(id: &UnitName, control: &Sender<ManagerControlMessage>, stderr: RunningOutput, last_line: &Arc<Mutex<String>>) {
+    fn log_error(id: &UnitName, slot: Option<u32>, control: &Sender<ManagerControlMessage>, stderr: RunningOutput, last_line: &Arc<Mutex<String>>) {
         let thr_control = control.clone();
         let thr_last_line = last_line.clone();
         let thr_id = id.clone();
@@ -502,7 +697,7 @@ impl Test {
             for line in BufReader::new(stderr).lines() {
                 let line = line.expect("Unable to get next line");
                 *thr_last_line.lock().unwrap() = line.clone();
-                if let Err(_) = thr_control.send(ManagerControlMessage::new(&thr_id, ManagerControlMessageContents::LogError(line))) {
+                if let Err(_) = thr_control.send(ManagerControlMessage::new_with_slot(&thr_id, slot, ManagerControlMessageContents::Output(LogSource::Stderr, line))) {
                     break;
                 }
             }
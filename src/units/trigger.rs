@@ -10,7 +10,10 @@ use std::thread;
 
 use config::Config;
 use unit::{UnitActivateError, UnitDeactivateError, UnitDescriptionError, UnitIncompatibleReason, UnitSelectError, UnitDeselectError,
-           UnitName};
+           RestartPolicy, UnitName};
+use unitbroadcaster::LogSource;
+use units::interface::Interface;
+use units::scenario::RunOptions;
 use unitmanager::{ManagerControlMessage, ManagerControlMessageContents, UnitManager};
 
 use self::systemd_parser::items::DirectiveEntry;
@@ -49,6 +52,9 @@ pub struct TriggerDescription {
 
     /// The path to the unit file
     unit_directory: PathBuf,
+
+    /// What to do when the process exits on its own
+    restart: RestartPolicy,
 }
 
 impl TriggerDescription {
@@ -73,6 +79,7 @@ impl TriggerDescription {
             exec_start: "".to_owned(),
             working_directory: None,
             unit_directory: path.parent().unwrap().to_owned(),
+            restart: RestartPolicy::new(),
         };
 
         for entry in unit_file.lookup_by_category("Trigger") {
@@ -124,7 +131,9 @@ impl TriggerDescription {
                             },
                         }
                     }
-                    &_ => (),
+                    key => {
+                        interface_description.restart.parse_directive("Trigger", key, directive.value())?;
+                    }
                 },
                 &_ => (),
             }
@@ -186,6 +195,10 @@ impl Trigger {
         &self.description.id
     }
 
+    pub fn restart_policy(&self) -> &RestartPolicy {
+        &self.description.restart
+    }
+
     pub fn select(&self) -> Result<(), UnitSelectError> {
         Ok(())
     }
@@ -258,7 +271,7 @@ impl Trigger {
         for line in BufReader::new(output).lines() {
             let line = line.expect("Unable to get next line");
             // If the send fails, that means the other end has closed the pipe.
-            if let Err(_) = control.send(ManagerControlMessage::new(&id, ManagerControlMessageContents::LogError(line))) {
+            if let Err(_) = control.send(ManagerControlMessage::new(&id, ManagerControlMessageContents::Output(LogSource::Stderr, line))) {
                 break;
             }
         }
@@ -271,9 +284,11 @@ impl Trigger {
                 .map(|x| Self::cfti_unescape(x.to_owned()))
                 .collect();
 
+            let mut slot = None;
+
             // Don't crash if we get a blank line.
             let msg = if words.len() == 0 {
-                ManagerControlMessageContents::StartScenario(None)
+                ManagerControlMessageContents::StartScenario(None, RunOptions::default())
             }
             else {
 
@@ -283,13 +298,53 @@ impl Trigger {
                 match verb.as_str() {
                     "stop" => ManagerControlMessageContents::Unimplemented("stop".to_owned(), "Unable to stop tests".to_owned()),
                     "start" => {
+                        // Any "assume=" or "skip=" words following the scenario name are run options.
+                        let option_start = if !words.is_empty() && !words[0].contains('=') { 1 } else { 0 };
+                        // Scenarios may be started on a particular jig slot using "name@slot".
+                        let name_word = if option_start > 0 { words[0].as_str() } else { "" };
+                        match (Interface::split_slot(name_word), RunOptions::from_words(&words[option_start..])) {
+                            (Err(e), _) | (_, Err(e)) => ManagerControlMessageContents::Error(e),
+                            (Ok((name, name_slot)), Ok(options)) => {
+                                slot = name_slot;
+                                if name.is_empty() {
+                                    ManagerControlMessageContents::StartScenario(None, options)
+                                } else {
+                                    match UnitName::from_str(name, "scenario") {
+                                        Ok(name) => ManagerControlMessageContents::StartScenario(Some(name), options),
+                                        Err(_) => ManagerControlMessageContents::Unimplemented(words[0].clone(), "name could not be decoded".to_owned()),
+                                    }
+                                }
+                            }
+                        }
+                    },
+                    "run" => {
                         if words.len() > 0 {
-                            match UnitName::from_str(&words[0], "test") {
-                                Ok(name) => ManagerControlMessageContents::StartScenario(Some(name)),
-                                Err(_) => ManagerControlMessageContents::Unimplemented(words[0].clone(), "name could not be decoded".to_owned()),
+                            match Interface::split_slot(&words[0]) {
+                                Err(e) => ManagerControlMessageContents::Error(e),
+                                Ok((name, name_slot)) => {
+                                    slot = name_slot;
+                                    match UnitName::from_str(name, "test") {
+                                        Ok(name) => ManagerControlMessageContents::RunTest(name),
+                                        Err(_) => ManagerControlMessageContents::Unimplemented(words[0].clone(), "name could not be decoded".to_owned()),
+                                    }
+                                }
                             }
                         } else {
-                            ManagerControlMessageContents::StartScenario(None)
+                            ManagerControlMessageContents::Unimplemented("run".to_owned(), "no test specified".to_owned())
+                        }
+                    },
+                    "pause" | "resume" | "step" => {
+                        // An optional "@slot" argument limits this to one jig slot.
+                        match Interface::split_slot(words.get(0).map(|x| x.as_str()).unwrap_or("")) {
+                            Err(e) => ManagerControlMessageContents::Error(e),
+                            Ok((_, word_slot)) => {
+                                slot = word_slot;
+                                match verb.as_str() {
+                                    "pause" => ManagerControlMessageContents::Pause,
+                                    "resume" => ManagerControlMessageContents::Resume,
+                                    _ => ManagerControlMessageContents::Step,
+                                }
+                            }
                         }
                     },
                     v => ManagerControlMessageContents::Unimplemented(v.to_owned(), words.join(" ")),
@@ -297,7 +352,7 @@ impl Trigger {
             };
 
             // If the send fails, that means the other end has closed the pipe.
-            if let Err(_) = control.send(ManagerControlMessage::new(&id, msg)) {
+            if let Err(_) = control.send(ManagerControlMessage::new_with_slot(&id, slot, msg)) {
                 break;
             }
         }
@@ -77,6 +77,11 @@ impl UnitWatcher {
         Ok(())
     }
 
+    /// The configuration directories being watched.
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
     fn watch(&mut self, path: &Path) -> notify::Result<()> {
 
         // Add a path to be watched. All files and directories at that path and
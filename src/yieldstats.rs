@@ -0,0 +1,284 @@
+extern crate humantime;
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use artifacts::ArtifactDir;
+
+/// How many of the most common failure messages are kept.
+const MAX_MESSAGES: usize = 10;
+
+/// How many failing tests are listed in a report.
+const REPORT_TESTS: usize = 5;
+
+/// Which recorded runs statistics are gathered from.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Default)]
+pub struct StatsWindow {
+    /// Only include runs that started at most this long ago.
+    pub within: Option<Duration>,
+
+    /// Only include this many of the most recent runs.
+    pub last: Option<usize>,
+}
+
+impl StatsWindow {
+    /// Parse a window such as "8h", "last 50", or "12h 100".  A plain number
+    /// is a count of runs, and anything else is a duration.  An empty string
+    /// includes every run.
+    pub fn from_str(s: &str) -> Result<StatsWindow, String> {
+        let mut window = StatsWindow::default();
+        for word in s.split_whitespace() {
+            if word.eq_ignore_ascii_case("last") {
+                continue;
+            }
+            if let Ok(count) = word.parse::<usize>() {
+                window.last = Some(count);
+            } else {
+                match humantime::parse_duration(word) {
+                    Ok(d) => window.within = Some(d),
+                    Err(e) => return Err(format!("invalid statistics window \"{}\": {}", word, e)),
+                }
+            }
+        }
+        Ok(window)
+    }
+}
+
+impl fmt::Display for StatsWindow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.within, self.last) {
+            (None, None) => write!(f, "all runs"),
+            (Some(d), None) => write!(f, "the last {}", humantime::format_duration(d)),
+            (None, Some(n)) => write!(f, "the last {} runs", n),
+            (Some(d), Some(n)) => write!(f, "the last {} runs within {}", n, humantime::format_duration(d)),
+        }
+    }
+}
+
+/// The results saved in one run's artifact directory.
+pub struct RunRecord {
+    pub scenario: String,
+    pub started: SystemTime,
+    pub passed: bool,
+
+    /// Each test's name, result ("pass", "fail [code]", "skip", or "assume"), message,
+    /// and how long it took if it ran.
+    pub tests: Vec<(String, String, String, Option<Duration>)>,
+}
+
+impl RunRecord {
+    /// Read "results.tsv" from a run directory.  Runs that haven't finished
+    /// yet, or that have no results, give None.
+    pub fn load(path: &Path) -> io::Result<Option<RunRecord>> {
+        let started = match ArtifactDir::start_time(path) {
+            Some(t) => t,
+            None => return Ok(None),
+        };
+        let contents = match fs::read_to_string(path.join("results.tsv")) {
+            Ok(c) => c,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        let mut tests = vec![];
+        let mut finished = None;
+        for line in contents.lines() {
+            let mut fields = line.splitn(4, '\t');
+            let (unit, result, message) = match (fields.next(), fields.next(), fields.next()) {
+                (Some(u), Some(r), m) => (u, r, m.unwrap_or("")),
+                _ => continue,
+            };
+            // Runs saved by older versions don't have durations.
+            let duration = fields
+                .next()
+                .and_then(|d| d.parse::<f64>().ok())
+                .filter(|d| *d >= 0.0)
+                .map(|d| Duration::from_millis((d * 1000.0) as u64));
+            if unit.ends_with(".scenario") {
+                finished = Some((unit.trim_end_matches(".scenario").to_owned(), result == "pass"));
+            } else if unit.ends_with(".test") {
+                tests.push((
+                    unit.trim_end_matches(".test").to_owned(),
+                    result.to_owned(),
+                    message.to_owned(),
+                    duration,
+                ));
+            }
+        }
+
+        Ok(finished.map(|(scenario, passed)| RunRecord {
+            scenario: scenario,
+            started: started,
+            passed: passed,
+            tests: tests,
+        }))
+    }
+}
+
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+pub struct ScenarioYield {
+    pub scenario: String,
+    pub runs: usize,
+    pub passed: usize,
+}
+
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+pub struct TestFailures {
+    pub test: String,
+
+    /// How many times the test passed or failed.  Skipped and assumed tests aren't counted.
+    pub runs: usize,
+    pub failures: usize,
+}
+
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+pub struct FailureMessage {
+    pub test: String,
+    pub message: String,
+    pub count: usize,
+}
+
+/// Yield and failure statistics over a window of recorded runs.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Default)]
+pub struct YieldStats {
+    pub window: StatsWindow,
+    pub runs: usize,
+
+    /// Each scenario's yield, by name.
+    pub scenarios: Vec<ScenarioYield>,
+
+    /// Every test that ran, the most failures first.
+    pub tests: Vec<TestFailures>,
+
+    /// The most common failure messages, the most common first.
+    pub messages: Vec<FailureMessage>,
+}
+
+impl YieldStats {
+    /// Gather statistics from the run directories under an artifact root.
+    pub fn load(root: &Path, window: &StatsWindow) -> io::Result<YieldStats> {
+        let mut runs = vec![];
+        // A run directory that can't be read shouldn't hide all the others.
+        for path in ArtifactDir::list(root)? {
+            if let Ok(Some(run)) = RunRecord::load(&path) {
+                runs.push(run);
+            }
+        }
+        Ok(Self::from_runs(&runs, window, SystemTime::now()))
+    }
+
+    /// Gather statistics from runs, which must be oldest first.
+    pub fn from_runs(runs: &[RunRecord], window: &StatsWindow, now: SystemTime) -> YieldStats {
+        let mut runs: Vec<&RunRecord> = runs
+            .iter()
+            .filter(|run| match window.within {
+                Some(within) => now.duration_since(run.started).map(|age| age <= within).unwrap_or(true),
+                None => true,
+            })
+            .collect();
+        if let Some(last) = window.last {
+            let excess = runs.len().saturating_sub(last);
+            runs.drain(..excess);
+        }
+
+        let mut scenarios: BTreeMap<&str, ScenarioYield> = BTreeMap::new();
+        let mut tests: HashMap<&str, TestFailures> = HashMap::new();
+        let mut messages: HashMap<(&str, &str), usize> = HashMap::new();
+        for run in &runs {
+            let scenario = scenarios.entry(&run.scenario).or_insert_with(|| ScenarioYield {
+                scenario: run.scenario.clone(),
+                runs: 0,
+                passed: 0,
+            });
+            scenario.runs += 1;
+            if run.passed {
+                scenario.passed += 1;
+            }
+
+            for &(ref name, ref result, ref message, _) in &run.tests {
+                if result == "skip" || result == "assume" {
+                    continue;
+                }
+                let test = tests.entry(name).or_insert_with(|| TestFailures {
+                    test: name.clone(),
+                    runs: 0,
+                    failures: 0,
+                });
+                test.runs += 1;
+                if result != "pass" {
+                    test.failures += 1;
+                    *messages.entry((name, message)).or_insert(0) += 1;
+                }
+            }
+        }
+
+        let mut tests: Vec<TestFailures> = tests.into_iter().map(|(_, v)| v).collect();
+        tests.sort_by(|a, b| b.failures.cmp(&a.failures).then_with(|| a.test.cmp(&b.test)));
+        let mut messages: Vec<FailureMessage> = messages
+            .into_iter()
+            .map(|((test, message), count)| FailureMessage {
+                test: test.to_owned(),
+                message: message.to_owned(),
+                count: count,
+            })
+            .collect();
+        messages.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then_with(|| a.test.cmp(&b.test))
+                .then_with(|| a.message.cmp(&b.message))
+        });
+        messages.truncate(MAX_MESSAGES);
+
+        YieldStats {
+            window: window.clone(),
+            runs: runs.len(),
+            scenarios: scenarios.into_iter().map(|(_, v)| v).collect(),
+            tests: tests,
+            messages: messages,
+        }
+    }
+
+    /// A human-readable summary: the yield of each scenario, the tests that
+    /// fail most (with their share of all failures), and the most common
+    /// failure messages.
+    pub fn report(&self) -> Vec<String> {
+        let mut lines = vec![format!("Statistics for {}: {} runs", self.window, self.runs)];
+
+        lines.push("Yield:".to_owned());
+        for s in &self.scenarios {
+            lines.push(format!("  {}: {} of {} passed ({:.1}%)", s.scenario, s.passed, s.runs, percent(s.passed, s.runs)));
+        }
+
+        let total_failures: usize = self.tests.iter().map(|x| x.failures).sum();
+        lines.push("Top failing tests:".to_owned());
+        for t in self.tests.iter().filter(|x| x.failures > 0).take(REPORT_TESTS) {
+            lines.push(format!(
+                "  {}: {} of {} failed ({:.1}%), {:.1}% of all failures",
+                t.test,
+                t.failures,
+                t.runs,
+                percent(t.failures, t.runs),
+                percent(t.failures, total_failures)
+            ));
+        }
+
+        lines.push("Most common failure messages:".to_owned());
+        for m in &self.messages {
+            lines.push(format!("  {} x{}: {}", m.test, m.count, m.message));
+        }
+        lines
+    }
+}
+
+pub fn percent(count: usize, total: usize) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 * 100.0 / total as f64
+    }
+}